@@ -1,8 +1,16 @@
-use std::thread;
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
 use criterion::{Criterion, criterion_group, criterion_main};
+use crossbeam::queue::ArrayQueue;
 use rtrb::{PopError, PushError};
-use voxine::mpsc::{self};
+use voxine::{
+    bench::{Runable, Threadpool, WorkerSignal},
+    mpsc::{self},
+};
 
 fn run_mpsc_threaded(messages_per_producer: usize, producers: usize, capacity: usize) {
     let (tx, rx) = mpsc::new::<usize>(capacity);
@@ -91,5 +99,100 @@ fn benchmark_channels(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, benchmark_channels);
+/// Answers every task right away, so the pool benchmarks measure waking up only.
+struct Echo {
+    tasks: Arc<ArrayQueue<()>>,
+    done: mpsc::Sender<()>,
+}
+
+impl Runable for Echo {
+    fn execute_tasks(&mut self) -> bool {
+        let mut worked = false;
+        while self.tasks.pop().is_some() {
+            self.done.push(()).unwrap();
+            worked = true;
+        }
+        worked
+    }
+}
+
+/// The task queue of a worker and how to wake it.
+type EchoWorker = (Arc<ArrayQueue<()>>, Arc<WorkerSignal>);
+
+fn echo_pool(workers: usize) -> (Threadpool<Echo>, Vec<EchoWorker>, mpsc::Receiver<()>) {
+    let (done, done_rx) = mpsc::new(1024);
+    let mut handles = Vec::new();
+    let pool = Threadpool::new(workers, |_, signal| {
+        let tasks = Arc::new(ArrayQueue::new(16));
+        handles.push((tasks.clone(), signal.clone()));
+        Echo {
+            tasks,
+            done: done.clone(),
+        }
+    })
+    .unwrap();
+    (pool, handles, done_rx)
+}
+
+/// User plus system time of the whole process.
+fn cpu_time() -> Duration {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: `usage` points to memory for one `rusage`, which `getrusage` fills completely when
+    // it returns 0, so it's initialized after the assertion.
+    let usage = unsafe {
+        assert_eq!(libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()), 0);
+        usage.assume_init()
+    };
+    let time = |t: libc::timeval| {
+        Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+    };
+    time(usage.ru_utime) + time(usage.ru_stime)
+}
+
+fn benchmark_worker_pool(c: &mut Criterion) {
+    let mut group = c.benchmark_group("worker_pool");
+    group.sample_size(20);
+
+    // from submitting a task to a parked worker until its result arrives
+    group.bench_function("wake_latency_4_workers", |b| {
+        let (pool, workers, done) = echo_pool(4);
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for i in 0..iters as usize {
+                let (tasks, signal) = &workers[i % workers.len()];
+                // give the worker time to park
+                thread::sleep(Duration::from_millis(1));
+
+                let start = Instant::now();
+                tasks.push(()).unwrap();
+                signal.unpark();
+                while done.pop().is_err() {
+                    std::hint::spin_loop();
+                }
+                total += start.elapsed();
+            }
+            total
+        });
+        assert!(pool.shutdown(Duration::from_secs(1)));
+    });
+
+    // CPU time the whole process burns while 4 workers wait 20ms for tasks that don't come
+    group.bench_function("idle_cpu_time_4_workers_per_20ms", |b| {
+        let (pool, _workers, _done) = echo_pool(4);
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let start = cpu_time();
+                thread::sleep(Duration::from_millis(20));
+                total += cpu_time() - start;
+            }
+            total
+        });
+        assert!(pool.shutdown(Duration::from_secs(1)));
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_channels, benchmark_worker_pool);
 criterion_main!(benches);
@@ -1,6 +1,9 @@
 use criterion::{Criterion, black_box, criterion_group, criterion_main};
-use glam::Vec3;
-use voxine::{ComposableGenerator, Generator, SphereGeneratorAllocations};
+use glam::{IVec3, Vec3};
+use voxine::{
+    ChunkID, ComposableGenerator, Gen2D, Generator, HeightmapCache, Noise,
+    SphereGeneratorAllocations, VoxelTypes,
+};
 
 fn benchmark_chunk_generation(c: &mut Criterion) {
     let seed = 1039030930193019;
@@ -22,5 +25,49 @@ fn benchmark_chunk_generation(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, benchmark_chunk_generation);
+fn terrain(heightmap_cache: HeightmapCache) -> ComposableGenerator {
+    ComposableGenerator::gen_2d(
+        Gen2D {
+            invert: true,
+            noise: Noise::new(7),
+            octaves: 3,
+            base_height: 0.,
+            x_scale: 20.0,
+            y_scale: 1200.0,
+            z_scale: 20.0,
+            heightmap_cache,
+        },
+        VoxelTypes::Air,
+    )
+}
+
+/// Generates a stack of 8 chunks per iteration, always in a column that hasn't been generated yet.
+fn benchmark_vertical_stack(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Gen2D_vertical_stack_of_8");
+
+    for (name, cache) in [
+        ("uncached", HeightmapCache::new(0)),
+        ("cached", HeightmapCache::default()),
+    ] {
+        let generator = terrain(cache);
+        let mut column = 0;
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                column += 1;
+                for y in -4..4 {
+                    let chunk = ChunkID::new(0, IVec3::new(column, y, 0));
+                    black_box(generator.generate(black_box(chunk)));
+                }
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_chunk_generation,
+    benchmark_vertical_stack
+);
 criterion_main!(benches);
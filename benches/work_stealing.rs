@@ -0,0 +1,117 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use glam::IVec3;
+use parking_lot::RwLock;
+use voxine::{
+    ChunkID,
+    bench::{ChunkTask, Runable, SharedQueues, TaskQueue, Threadpool, WorkerSignal, steal},
+    mpsc::{self},
+};
+
+const WORKERS: usize = 4;
+const JOBS: i32 = 256;
+
+/// Stands in for generating a chunk that takes `cost` rounds of busy work.
+struct Job {
+    chunk: ChunkID,
+    cost: u32,
+}
+
+impl ChunkTask for Job {
+    fn chunk(&self) -> ChunkID {
+        self.chunk
+    }
+}
+
+struct Worker {
+    id: usize,
+    queues: SharedQueues<Job>,
+    stealing: bool,
+    done: mpsc::Sender<()>,
+}
+
+impl Runable for Worker {
+    fn execute_tasks(&mut self) -> bool {
+        let mut worked = false;
+        loop {
+            let own = self.queues.read()[self.id].pop();
+            let job = match own {
+                Some(job) => job,
+                None if self.stealing => match steal(&self.queues.read(), self.id) {
+                    Some(job) => job,
+                    None => return worked,
+                },
+                None => return worked,
+            };
+            let mut x = job.chunk.pos.x as u64;
+            for _ in 0..job.cost * 1_000 {
+                x = black_box(x.wrapping_mul(6364136223846793005).wrapping_add(1));
+            }
+            self.done.push(()).unwrap();
+            worked = true;
+        }
+    }
+}
+
+/// Every worker gets the same number of jobs, but the region of worker 0 is 16 times as
+/// expensive, like mountains next to flat plains.
+fn run_skewed(stealing: bool, iters: u64) -> Duration {
+    let (done, done_rx) = mpsc::new(JOBS as usize);
+    let queues: SharedQueues<Job> = Arc::new(RwLock::new(
+        (0..WORKERS)
+            .map(|_| Arc::new(TaskQueue::new(JOBS as usize)))
+            .collect(),
+    ));
+    let mut signals: Vec<Arc<WorkerSignal>> = vec![];
+    let pool = Threadpool::new(WORKERS, |id, signal| {
+        signals.push(signal.clone());
+        Worker {
+            id,
+            queues: queues.clone(),
+            stealing,
+            done: done.clone(),
+        }
+    })
+    .unwrap();
+
+    let mut total = Duration::ZERO;
+    for _ in 0..iters {
+        let start = Instant::now();
+        for x in 0..JOBS {
+            let worker = x as usize % WORKERS;
+            let cost = if worker == 0 { 16 } else { 1 };
+            let job = Job {
+                chunk: ChunkID::new(0, IVec3::new(x, 0, 0)),
+                cost,
+            };
+            queues.read()[worker].push(job, x as f32).ok().unwrap();
+        }
+        for signal in &signals {
+            signal.unpark();
+        }
+        for _ in 0..JOBS {
+            while done_rx.pop().is_err() {
+                std::hint::spin_loop();
+            }
+        }
+        total += start.elapsed();
+    }
+    assert!(pool.shutdown(Duration::from_secs(1)));
+    total
+}
+
+fn benchmark_work_stealing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("skewed_cost_4_workers_256_jobs");
+    group.sample_size(10);
+    for (name, stealing) in [("fixed_buckets", false), ("work_stealing", true)] {
+        group.bench_function(name, |b| b.iter_custom(|iters| run_skewed(stealing, iters)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_work_stealing);
+criterion_main!(benches);
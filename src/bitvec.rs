@@ -17,6 +17,24 @@ impl PackedVec32 {
         }
     }
 
+    /// Takes the packed words as they are, e.g. after reading them from a file. Returns `None` if
+    /// there are too few words for `len` elements.
+    pub fn from_words(len: usize, bits_per_elem: u8, words: Vec<u32>) -> Option<Self> {
+        let bits = len.checked_mul(bits_per_elem as usize)?;
+        if words.len() < bits.div_ceil(32) {
+            return None;
+        }
+        Some(Self {
+            bits_per_elem,
+            len,
+            words,
+        })
+    }
+
+    pub fn words(&self) -> &[u32] {
+        &self.words
+    }
+
     pub fn len(&self) -> usize {
         self.len
     }
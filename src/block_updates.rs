@@ -0,0 +1,238 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+};
+
+use glam::{IVec3, UVec3};
+use rand::Rng;
+
+use crate::{DeterministicRng, falling::FallingBlocks, voxel, world::World};
+
+/// A behaviour of a voxel type, called with the position of the voxel.
+pub type UpdateFn = fn(&mut UpdateContext, IVec3);
+
+/// What happens to the voxels of a type over time. Registered in [`voxel::behaviour`].
+#[derive(Clone, Copy, Default)]
+pub struct Behaviour {
+    /// Called for randomly picked voxels of every loaded chunk, e.g. for grass spreading.
+    pub random_tick: Option<UpdateFn>,
+    /// Called `delay` ticks after one of the voxels next to this one changed.
+    pub scheduled: Option<(UpdateFn, u64)>,
+}
+
+/// The world as seen by a behaviour.
+pub struct UpdateContext<'a> {
+    pub world: &'a mut World,
+    pub rng: &'a mut DeterministicRng,
+    pub falling: &'a mut FallingBlocks,
+    tick: u64,
+    scheduled: &'a mut Schedule,
+}
+
+impl UpdateContext<'_> {
+    /// Schedules an update of the voxel at `pos` in `delay` ticks.
+    #[allow(unused)]
+    pub fn schedule(&mut self, pos: IVec3, delay: u64) {
+        self.scheduled.push(pos, self.tick + delay);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Schedule {
+    queue: BinaryHeap<Reverse<(u64, [i32; 3])>>,
+    /// Every position in the queue, so a voxel is scheduled at most once.
+    pending: HashSet<IVec3>,
+}
+
+impl Schedule {
+    fn push(&mut self, pos: IVec3, tick: u64) {
+        if self.pending.insert(pos) {
+            self.queue.push(Reverse((tick, pos.to_array())));
+        }
+    }
+
+    fn pop_due(&mut self, tick: u64) -> Option<IVec3> {
+        let Reverse((due, pos)) = *self.queue.peek()?;
+        if due > tick {
+            return None;
+        }
+        self.queue.pop();
+        let pos = IVec3::from_array(pos);
+        self.pending.remove(&pos);
+        Some(pos)
+    }
+}
+
+/// Random ticks and scheduled updates of the voxels in the world, driven by the engine tick.
+#[derive(Debug)]
+pub struct BlockUpdates {
+    tick: u64,
+    rng: DeterministicRng,
+    scheduled: Schedule,
+}
+
+impl BlockUpdates {
+    pub fn new(rng: DeterministicRng) -> Self {
+        Self {
+            tick: 0,
+            rng,
+            scheduled: Schedule::default(),
+        }
+    }
+
+    /// Schedules an update of the voxel at `pos` in `delay` ticks.
+    pub fn schedule(&mut self, pos: IVec3, delay: u64) {
+        self.scheduled.push(pos, self.tick + delay);
+    }
+
+    /// The number of updates waiting to be run.
+    pub fn scheduled(&self) -> usize {
+        self.scheduled.queue.len()
+    }
+
+    /// Schedules the voxels next to the changed ones that react to their neighbors.
+    pub fn voxels_changed(&mut self, world: &World, edits: &[IVec3]) {
+        for pos in edits {
+            for dir in [
+                IVec3::ZERO,
+                IVec3::NEG_X,
+                IVec3::X,
+                IVec3::NEG_Y,
+                IVec3::Y,
+                IVec3::NEG_Z,
+                IVec3::Z,
+            ] {
+                let neighbor = *pos + dir;
+                let Some(voxel) = world.get(neighbor) else {
+                    continue;
+                };
+                if let Some((_, delay)) = voxel::behaviour(voxel).scheduled {
+                    self.schedule(neighbor, delay);
+                }
+            }
+        }
+    }
+
+    /// Runs the updates that are due and `random_ticks_per_chunk` random ticks in every loaded
+    /// chunk.
+    pub fn tick(
+        &mut self,
+        world: &mut World,
+        falling: &mut FallingBlocks,
+        random_ticks_per_chunk: usize,
+    ) {
+        self.tick += 1;
+
+        let mut ctx = UpdateContext {
+            world,
+            rng: &mut self.rng,
+            falling,
+            tick: self.tick,
+            scheduled: &mut self.scheduled,
+        };
+
+        // updates scheduled by the updates run next tick at the earliest
+        let mut due = Vec::new();
+        while let Some(pos) = ctx.scheduled.pop_due(ctx.tick) {
+            due.push(pos);
+        }
+        for pos in due {
+            let Some(voxel) = ctx.world.get(pos) else {
+                continue;
+            };
+            if let Some((update, _)) = voxel::behaviour(voxel).scheduled {
+                update(&mut ctx, pos);
+            }
+        }
+
+        if random_ticks_per_chunk == 0 {
+            return;
+        }
+        for chunk in ctx.world.chunk_ids() {
+            for _ in 0..random_ticks_per_chunk {
+                let local = UVec3::new(
+                    ctx.rng.gen_range(0..32),
+                    ctx.rng.gen_range(0..32),
+                    ctx.rng.gen_range(0..32),
+                );
+                let pos = chunk.pos * 32 + local.as_ivec3();
+                let Some(voxel) = ctx.world.get(pos) else {
+                    continue;
+                };
+                if let Some(update) = voxel::behaviour(voxel).random_tick {
+                    update(&mut ctx, pos);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+    use rand::SeedableRng;
+
+    use crate::{
+        Chunk, ChunkID, DeterministicRng, VoxelTypes, falling::FallingBlocks, voxel::fill,
+        world::World,
+    };
+
+    use super::BlockUpdates;
+
+    const AIR: u16 = VoxelTypes::Air as u16;
+    const DIRT: u16 = VoxelTypes::Dirt0 as u16;
+    const GRASS: u16 = VoxelTypes::Grass as u16;
+    const STONE: u16 = VoxelTypes::Stone as u16;
+
+    /// A dirt floor below `y = 0` with air above.
+    fn field() -> World {
+        let mut world = World::default();
+        world.insert_chunk(
+            ChunkID::new(0, IVec3::NEG_Y),
+            Chunk::from_buffer(&fill(DIRT)),
+        );
+        world.insert_chunk(ChunkID::new(0, IVec3::ZERO), Chunk::from_buffer(&fill(AIR)));
+        world
+    }
+
+    fn run(updates: &mut BlockUpdates, world: &mut World, ticks: usize, random_ticks: usize) {
+        for _ in 0..ticks {
+            let edits = world.take_edits();
+            updates.voxels_changed(world, &edits);
+            updates.tick(world, &mut FallingBlocks::default(), random_ticks);
+        }
+    }
+
+    #[test]
+    fn grass_spreads_on_random_ticks() {
+        let mut world = field();
+        let mut updates = BlockUpdates::new(DeterministicRng::seed_from_u64(0));
+        world.set(IVec3::new(16, -1, 16), GRASS);
+
+        run(&mut updates, &mut world, 1000, 512);
+
+        let grass = (10..22)
+            .flat_map(|x| (10..22).map(move |z| IVec3::new(x, -1, z)))
+            .filter(|pos| world.get(*pos) == Some(GRASS))
+            .count();
+        assert!(grass > 1, "grass didn't spread");
+    }
+
+    #[test]
+    fn covered_grass_turns_into_dirt_when_scheduled() {
+        let mut world = field();
+        let mut updates = BlockUpdates::new(DeterministicRng::seed_from_u64(0));
+        let grass = IVec3::new(16, -1, 16);
+        world.set(grass, GRASS);
+        run(&mut updates, &mut world, 1, 0);
+
+        world.set(grass + IVec3::Y, STONE);
+        run(&mut updates, &mut world, 1, 0);
+        assert_eq!(world.get(grass), Some(GRASS));
+        assert_eq!(updates.scheduled(), 1);
+
+        run(&mut updates, &mut world, 20, 0);
+        assert_eq!(world.get(grass), Some(DIRT));
+        assert_eq!(updates.scheduled(), 0);
+    }
+}
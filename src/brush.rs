@@ -0,0 +1,377 @@
+use std::collections::{HashSet, VecDeque};
+
+use glam::{IVec3, Vec3};
+
+use crate::{
+    ChunkID, VoxelType,
+    world::{VoxelEdit, World},
+};
+
+/// A region of the world. All bounds are inclusive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Box {
+        min: IVec3,
+        max: IVec3,
+    },
+    /// The shell of a box with walls `thickness` voxels thick.
+    HollowBox {
+        min: IVec3,
+        max: IVec3,
+        thickness: i32,
+    },
+    Sphere {
+        center: IVec3,
+        radius: f32,
+    },
+    /// A cylinder standing upright on `base`.
+    Cylinder {
+        base: IVec3,
+        radius: f32,
+        height: i32,
+    },
+}
+
+impl Shape {
+    pub fn bounds(&self) -> (IVec3, IVec3) {
+        match *self {
+            Self::Box { min, max } | Self::HollowBox { min, max, .. } => (min, max),
+            Self::Sphere { center, radius } => {
+                let radius = IVec3::splat(radius.floor() as i32);
+                (center - radius, center + radius)
+            }
+            Self::Cylinder {
+                base,
+                radius,
+                height,
+            } => {
+                let radius = radius.floor() as i32;
+                (
+                    base - IVec3::new(radius, 0, radius),
+                    base + IVec3::new(radius, height - 1, radius),
+                )
+            }
+        }
+    }
+
+    pub fn contains(&self, pos: IVec3) -> bool {
+        let (min, max) = self.bounds();
+        if pos.cmplt(min).any() || pos.cmpgt(max).any() {
+            return false;
+        }
+        match *self {
+            Self::Box { .. } => true,
+            Self::HollowBox { thickness, .. } => {
+                (pos - min).min_element() < thickness || (max - pos).min_element() < thickness
+            }
+            Self::Sphere { center, radius } => {
+                (pos - center).as_vec3().length_squared() <= radius * radius
+            }
+            Self::Cylinder { base, radius, .. } => {
+                let offset = (pos - base).as_vec3();
+                Vec3::new(offset.x, 0., offset.z).length_squared() <= radius * radius
+            }
+        }
+    }
+
+    /// Whether every voxel of the chunk is inside the shape.
+    fn covers(&self, chunk: ChunkID) -> bool {
+        let min = chunk.pos * 32;
+        let max = min + 31;
+        match self {
+            Self::Box { .. } | Self::Sphere { .. } | Self::Cylinder { .. } => (0..8)
+                .map(|corner| {
+                    IVec3::new(
+                        if corner & 1 == 0 { min.x } else { max.x },
+                        if corner & 2 == 0 { min.y } else { max.y },
+                        if corner & 4 == 0 { min.z } else { max.z },
+                    )
+                })
+                .all(|corner| self.contains(corner)),
+            Self::HollowBox { .. } => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Brush {
+    Fill {
+        shape: Shape,
+        voxel: VoxelType,
+    },
+    /// Replaces one voxel type with another inside the shape.
+    Replace {
+        shape: Shape,
+        from: VoxelType,
+        to: VoxelType,
+    },
+    /// Replaces the connected voxels of the same type as the one at `start`, up to `max_voxels`.
+    FloodReplace {
+        start: IVec3,
+        to: VoxelType,
+        max_voxels: usize,
+    },
+}
+
+impl Brush {
+    /// Applies the brush to the loaded part of the world. Every touched chunk gets rebuilt once
+    /// and chunks the brush fills completely become uniform without looking at their voxels.
+    /// Returns the voxels that changed, e.g. to record them in the journal.
+    pub fn apply(&self, world: &mut World) -> Vec<VoxelEdit> {
+        match *self {
+            Self::Fill { shape, voxel } => {
+                let mut edits = Vec::new();
+                let mut voxels = Vec::new();
+                for chunk in chunks_in(world, shape.bounds()) {
+                    if shape.covers(chunk) {
+                        edits.extend(world.fill_chunk(chunk, voxel));
+                        continue;
+                    }
+                    voxels.extend(
+                        voxels_in(chunk, shape.bounds())
+                            .filter(|pos| shape.contains(*pos))
+                            .map(|pos| (pos, voxel)),
+                    );
+                }
+                edits.extend(world.set_many(voxels));
+                edits
+            }
+            Self::Replace { shape, from, to } => {
+                let mut edits = Vec::new();
+                let mut voxels = Vec::new();
+                for chunk in chunks_in(world, shape.bounds()) {
+                    let Some(data) = world.chunk(chunk) else {
+                        continue;
+                    };
+                    match data.uniform_type() {
+                        Some(uniform) if uniform != from => continue,
+                        Some(_) if shape.covers(chunk) => {
+                            edits.extend(world.fill_chunk(chunk, to));
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    voxels.extend(
+                        voxels_in(chunk, shape.bounds())
+                            .filter(|pos| shape.contains(*pos) && world.get(*pos) == Some(from))
+                            .map(|pos| (pos, to)),
+                    );
+                }
+                edits.extend(world.set_many(voxels));
+                edits
+            }
+            Self::FloodReplace {
+                start,
+                to,
+                max_voxels,
+            } => {
+                let Some(from) = world.get(start).filter(|from| *from != to) else {
+                    return Vec::new();
+                };
+
+                let mut visited = HashSet::from([start]);
+                let mut queue = VecDeque::from([start]);
+                while let Some(pos) = queue.pop_front()
+                    && visited.len() < max_voxels
+                {
+                    for dir in [
+                        IVec3::NEG_X,
+                        IVec3::X,
+                        IVec3::NEG_Y,
+                        IVec3::Y,
+                        IVec3::NEG_Z,
+                        IVec3::Z,
+                    ] {
+                        let neighbor = pos + dir;
+                        if visited.len() < max_voxels
+                            && world.get(neighbor) == Some(from)
+                            && visited.insert(neighbor)
+                        {
+                            queue.push_back(neighbor);
+                        }
+                    }
+                }
+                world.set_many(visited.into_iter().map(|pos| (pos, to)))
+            }
+        }
+    }
+}
+
+/// The loaded chunks touching the bounds. Walks the loaded chunks instead of the bounds if they
+/// are fewer, so huge shapes don't visit chunks that don't exist.
+fn chunks_in(world: &World, (min, max): (IVec3, IVec3)) -> Vec<ChunkID> {
+    let (min, max): (IVec3, IVec3) = (min >> 5, max >> 5);
+    let in_bounds = (0..3)
+        .map(|axis| (max[axis] as i64 - min[axis] as i64 + 1).max(0) as u64)
+        .try_fold(1_u64, |count, side| count.checked_mul(side))
+        .unwrap_or(u64::MAX);
+    if in_bounds > world.loaded_chunks() as u64 {
+        let mut chunks = world.chunk_ids();
+        chunks.retain(|chunk| chunk.pos.cmpge(min).all() && chunk.pos.cmple(max).all());
+        return chunks;
+    }
+    (min.x..=max.x)
+        .flat_map(move |x| {
+            (min.y..=max.y).flat_map(move |y| {
+                (min.z..=max.z).map(move |z| ChunkID::new(0, IVec3::new(x, y, z)))
+            })
+        })
+        .filter(|chunk| world.chunk(*chunk).is_some())
+        .collect()
+}
+
+/// The voxels of the chunk inside the bounds.
+fn voxels_in(chunk: ChunkID, (min, max): (IVec3, IVec3)) -> impl Iterator<Item = IVec3> {
+    let min = min.max(chunk.pos * 32);
+    let max = max.min(chunk.pos * 32 + 31);
+    (min.x..=max.x).flat_map(move |x| {
+        (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| IVec3::new(x, y, z)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::{Chunk, ChunkID, VoxelTypes, voxel::fill, world::World};
+
+    use super::{Brush, Shape};
+
+    const AIR: u16 = VoxelTypes::Air as u16;
+    const STONE: u16 = VoxelTypes::Stone as u16;
+    const DIRT: u16 = VoxelTypes::Dirt0 as u16;
+
+    fn world() -> World {
+        let mut world = World::default();
+        for x in -1..=1 {
+            for z in -1..=1 {
+                world.insert_chunk(
+                    ChunkID::new(0, IVec3::new(x, 0, z)),
+                    Chunk::from_buffer(&fill(AIR)),
+                );
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn fills_make_covered_chunks_uniform() {
+        let mut world = world();
+        let edits = Brush::Fill {
+            shape: Shape::Box {
+                min: IVec3::new(-10, 0, 0),
+                max: IVec3::new(40, 31, 31),
+            },
+            voxel: STONE,
+        }
+        .apply(&mut world);
+
+        assert_eq!(edits.len(), 51 * 32 * 32);
+        let center = world.chunk(ChunkID::new(0, IVec3::ZERO)).unwrap();
+        assert_eq!(center.uniform_type(), Some(STONE));
+        assert_eq!(world.get(IVec3::new(-10, 5, 5)), Some(STONE));
+        assert_eq!(world.get(IVec3::new(-11, 5, 5)), Some(AIR));
+        assert_eq!(world.get(IVec3::new(40, 5, 5)), Some(STONE));
+    }
+
+    #[test]
+    fn huge_shapes_only_visit_loaded_chunks() {
+        let mut world = world();
+        world.set(IVec3::new(-20, 1, 40), STONE);
+        world.set(IVec3::new(-20, 2, 40), STONE);
+        let edits = Brush::Replace {
+            shape: Shape::Box {
+                min: IVec3::splat(-1 << 30),
+                max: IVec3::new(1 << 30, 1, 1 << 30),
+            },
+            from: STONE,
+            to: DIRT,
+        }
+        .apply(&mut world);
+
+        assert_eq!(edits.len(), 1);
+        assert_eq!(world.get(IVec3::new(-20, 1, 40)), Some(DIRT));
+        assert_eq!(world.get(IVec3::new(-20, 2, 40)), Some(STONE));
+    }
+
+    #[test]
+    fn shapes_contain_the_right_voxels() {
+        let sphere = Shape::Sphere {
+            center: IVec3::ZERO,
+            radius: 3.,
+        };
+        assert!(sphere.contains(IVec3::new(3, 0, 0)));
+        assert!(!sphere.contains(IVec3::new(3, 1, 0)));
+
+        let hollow = Shape::HollowBox {
+            min: IVec3::ZERO,
+            max: IVec3::splat(9),
+            thickness: 2,
+        };
+        assert!(hollow.contains(IVec3::new(1, 5, 5)));
+        assert!(!hollow.contains(IVec3::new(2, 5, 5)));
+        assert!(hollow.contains(IVec3::new(5, 5, 8)));
+
+        let cylinder = Shape::Cylinder {
+            base: IVec3::ZERO,
+            radius: 2.,
+            height: 5,
+        };
+        assert!(cylinder.contains(IVec3::new(2, 4, 0)));
+        assert!(!cylinder.contains(IVec3::new(2, 5, 0)));
+        assert!(!cylinder.contains(IVec3::new(2, 0, 1)));
+    }
+
+    #[test]
+    fn replace_only_touches_the_given_type() {
+        let mut world = world();
+        world.set(IVec3::new(1, 1, 1), STONE);
+        world.set(IVec3::new(2, 2, 2), DIRT);
+
+        let edits = Brush::Replace {
+            shape: Shape::Box {
+                min: IVec3::ZERO,
+                max: IVec3::splat(5),
+            },
+            from: STONE,
+            to: DIRT,
+        }
+        .apply(&mut world);
+
+        assert_eq!(edits.len(), 1);
+        assert_eq!(world.get(IVec3::new(1, 1, 1)), Some(DIRT));
+        assert_eq!(world.get(IVec3::new(3, 3, 3)), Some(AIR));
+    }
+
+    #[test]
+    fn flood_replace_stays_inside_the_connected_region() {
+        let mut world = world();
+        Brush::Fill {
+            shape: Shape::HollowBox {
+                min: IVec3::ZERO,
+                max: IVec3::splat(10),
+                thickness: 1,
+            },
+            voxel: STONE,
+        }
+        .apply(&mut world);
+
+        let edits = Brush::FloodReplace {
+            start: IVec3::splat(5),
+            to: DIRT,
+            max_voxels: usize::MAX,
+        }
+        .apply(&mut world);
+
+        assert_eq!(edits.len(), 9 * 9 * 9);
+        assert_eq!(world.get(IVec3::new(12, 5, 5)), Some(AIR));
+
+        let limited = Brush::FloodReplace {
+            start: IVec3::new(20, 20, 20),
+            to: DIRT,
+            max_voxels: 100,
+        }
+        .apply(&mut world);
+        assert_eq!(limited.len(), 100);
+    }
+}
@@ -121,7 +121,7 @@ impl CamController {
             self.config.standart_speed * self.config.max_speed,
         );
 
-        println!("new speed: {}", self.speed);
+        tracing::debug!("new speed: {}", self.speed);
     }
 
     pub fn update_config(&mut self, config: CameraConfig) {
@@ -39,6 +39,31 @@ impl Chunk {
         Self::from_iterator(every_input_voxel)
     }
 
+    /// A chunk filled with a single voxel type, built without touching every voxel.
+    pub fn uniform(voxel_type: VoxelType) -> Self {
+        Self {
+            count_of_change: 0,
+            palette_data: Some(PaletteData {
+                palette_index_size: 0,
+                type_to_id: HashMap::from([(voxel_type, 0)]),
+                free_list: VecDeque::new(),
+                max_palette_size: 1,
+                palette: vec![voxel_type],
+                palette_rc: vec![CHUNK_VOLUME as u16],
+            }),
+            dense_data: None,
+            voxel: PackedVec32::new(CHUNK_VOLUME, 0),
+        }
+    }
+
+    /// Returns the voxel type if the whole chunk consists of it.
+    pub fn uniform_type(&self) -> Option<VoxelType> {
+        let p_data = self.palette_data.as_ref()?;
+        let mut used = p_data.palette_rc.iter().zip(&p_data.palette);
+        let (_, voxel_type) = used.find(|(rc, _)| **rc as usize == CHUNK_VOLUME)?;
+        Some(*voxel_type)
+    }
+
     fn from_iterator(every_input_voxel: impl Iterator<Item = u16> + Clone) -> Self {
         let mut voxel_type_uses = [0_u16; 65_536];
         every_input_voxel
@@ -320,6 +345,21 @@ mod tests {
         (x01 * max).round() as u16
     }
 
+    #[test]
+    fn uniform_chunks_behave_like_filled_buffers() {
+        let mut uniform = Chunk::uniform(3);
+        let mut filled = Chunk::from_buffer(&[3; CHUNK_VOLUME]);
+        assert_eq!(uniform.uniform_type(), Some(3));
+        assert_eq!(uniform.to_buffer(), filled.to_buffer());
+
+        for i in [0, 77, CHUNK_VOLUME - 1] {
+            uniform.set(idx_to_coord(i), 5);
+            filled.set(idx_to_coord(i), 5);
+        }
+        assert_eq!(uniform.uniform_type(), None);
+        assert_eq!(uniform.to_buffer(), filled.to_buffer());
+    }
+
     #[test]
     fn set_does_not_skip_when_palette_zero_matches_target() {
         let mut buffer = [0_u16; CHUNK_VOLUME];
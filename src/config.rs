@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::Lod;
+use crate::{Lod, Seed};
 
 /// This is the configuration for the engine thread
 #[derive(Deserialize, Serialize)]
@@ -16,6 +16,36 @@ pub struct EngineConfig {
 
     pub worker_count: usize,
 
+    /// The vertical field of view of the camera in radians. Chunks inside of it get generated
+    /// first.
+    #[serde(default = "default_fov")]
+    pub fov: f32,
+    #[serde(default = "default_aspect_ratio")]
+    pub aspect_ratio: f32,
+
+    /// How many fluid cells get simulated per tick at most.
+    #[serde(default = "default_fluid_updates_per_tick")]
+    pub fluid_updates_per_tick: usize,
+    /// How many random voxels of every full detail chunk get a random tick per tick.
+    #[serde(default = "default_random_ticks_per_chunk")]
+    pub random_ticks_per_chunk: usize,
+    /// The seed of the world. Random ticks derive theirs from it.
+    #[serde(default)]
+    pub seed: Seed,
+
+    /// How many bytes the undo history takes at most.
+    #[serde(default = "default_edit_history_cap")]
+    pub edit_history_cap: usize,
+
+    /// Only generates full detail chunks and never meshes, for servers and tools without a
+    /// renderer. The default without the `renderer` feature.
+    #[serde(default = "default_headless")]
+    pub headless: bool,
+
+    /// Serves the metrics in the Prometheus text format on this address, e.g. `127.0.0.1:9184`.
+    #[serde(default)]
+    pub metrics_address: Option<String>,
+
     pub engine_worker_config_queue_cap: usize,
     pub task_queue_cap: usize,
     pub discarded_tasks_queue_cap: usize,
@@ -23,6 +53,8 @@ pub struct EngineConfig {
     pub chunk_queue_cap: usize,
     pub collider_queue_cap: usize,
     pub solid_map_queue_cap: usize,
+    #[serde(default = "default_request_queue_cap")]
+    pub request_queue_cap: usize,
 }
 
 /// This are the parts of the configuration of the engine thread that can be changed live
@@ -36,6 +68,49 @@ pub struct ConfigUpdate {
 
     pub print_tps_per: Option<f64>,
     pub target_tps: f64,
+
+    /// Workers get added or stopped to match, e.g. to free up the CPU while paused. `None` keeps
+    /// the current count.
+    #[serde(default)]
+    pub worker_count: Option<usize>,
+
+    #[serde(default = "default_fov")]
+    pub fov: f32,
+    #[serde(default = "default_aspect_ratio")]
+    pub aspect_ratio: f32,
+
+    #[serde(default = "default_fluid_updates_per_tick")]
+    pub fluid_updates_per_tick: usize,
+    #[serde(default = "default_random_ticks_per_chunk")]
+    pub random_ticks_per_chunk: usize,
+}
+
+fn default_fov() -> f32 {
+    std::f32::consts::FRAC_PI_3
+}
+
+fn default_aspect_ratio() -> f32 {
+    16. / 9.
+}
+
+fn default_fluid_updates_per_tick() -> usize {
+    4096
+}
+
+fn default_random_ticks_per_chunk() -> usize {
+    3
+}
+
+fn default_headless() -> bool {
+    !cfg!(feature = "renderer")
+}
+
+fn default_request_queue_cap() -> usize {
+    64
+}
+
+fn default_edit_history_cap() -> usize {
+    64 << 20
 }
 
 impl ConfigUpdate {
@@ -56,6 +131,11 @@ impl EngineConfig {
             max_chunks,
             print_tps_per,
             target_tps,
+            worker_count,
+            fov,
+            aspect_ratio,
+            fluid_updates_per_tick,
+            random_ticks_per_chunk,
         } = update;
 
         self.full_detail_distance = full_detail_distance;
@@ -65,6 +145,13 @@ impl EngineConfig {
         self.max_chunks = max_chunks;
         self.print_tps_per = print_tps_per;
         self.target_tps = target_tps;
+        if let Some(worker_count) = worker_count {
+            self.worker_count = worker_count;
+        }
+        self.fov = fov;
+        self.aspect_ratio = aspect_ratio;
+        self.fluid_updates_per_tick = fluid_updates_per_tick;
+        self.random_ticks_per_chunk = random_ticks_per_chunk;
     }
 
     pub fn worker_config(&self) -> WorkerConfig {
@@ -80,3 +167,36 @@ pub struct WorkerConfig {
     pub task_cancelation_lod_threshold: u16,
     pub full_detail_distance: f32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigUpdate, EngineConfig};
+
+    const LIVE: &str = r#"
+full_detail_distance = 1.0
+task_cancelation_lod_threshold = 2
+total_generation_distance = 3.0
+max_chunks = 1000
+target_tps = 20.0
+"#;
+
+    #[test]
+    fn updates_without_a_worker_count_keep_the_workers() {
+        let mut config: EngineConfig = toml::from_str(&format!(
+            "{LIVE}worker_count = 3
+engine_worker_config_queue_cap = 4
+task_queue_cap = 256
+discarded_tasks_queue_cap = 256
+mesh_queue_cap = 256
+chunk_queue_cap = 256
+collider_queue_cap = 256
+solid_map_queue_cap = 256"
+        ))
+        .unwrap();
+
+        config.update(toml::from_str::<ConfigUpdate>(LIVE).unwrap());
+        assert_eq!(config.worker_count, 3);
+        config.update(toml::from_str(&format!("{LIVE}worker_count = 1")).unwrap());
+        assert_eq!(config.worker_count, 1);
+    }
+}
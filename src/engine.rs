@@ -1,32 +1,88 @@
 use std::{
     collections::{HashMap, HashSet},
+    net::TcpListener,
     sync::Arc,
     thread,
     time::{Duration, Instant},
 };
 
+use glam::{IVec3, Vec3};
 use parking_lot::RwLock;
+use rand::SeedableRng;
 use rtrb::RingBuffer;
 use tokio::io;
+use tracing::{error, info, trace_span, warn};
 
 use crate::{
-    Chunk, ComposableGenerator, MeshReceiver,
+    Chunk, ComposableGenerator, DeterministicRng, Frustum, MeshReceiver, VoxelType,
+    block_updates::BlockUpdates,
+    brush::Brush,
     cam_controller::CamController,
     chunk::ChunkID,
-    config::{ConfigUpdate, EngineConfig},
+    config::{ConfigUpdate, EngineConfig, WorkerConfig},
+    falling::FallingBlocks,
     flood_fill::{SphereGeneratorAllocations, chunk_neighbors},
+    fluids::Fluids,
+    journal::Journal,
     mesh::MeshUpload,
     meshing::{BitMap2D, BitMap3D},
+    metrics::{Counters, MemoryUsage, Metrics, serve_prometheus},
     mpsc,
+    random::derive_seed,
+    requests::{ChunkRequests, PendingRequests, Requested},
+    scheduler::View,
+    schematic::{RegionTooLarge, Schematic},
     worker::{self, Task},
-    worker_pool::Threadpool,
-    worker_spsc::WorkerSPMC,
+    worker_pool::{Threadpool, WorkerID, WorkerSignal},
+    worker_spsc::{WorkerSPMC, count_lod},
+    world::World,
 };
 
-const MAX_LOD: usize = 16;
+/// How far the camera has to turn, in radians, before pending tasks get reprioritized.
+const REPRIORITIZE_ANGLE: f32 = 0.1;
+
+/// How often the metrics get refreshed, in seconds.
+const METRICS_INTERVAL: f64 = 0.5;
+
+/// How long the workers get to finish their current task when shutting down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the engine waits for room in a full reply queue before dropping the reply.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(100);
 
 pub enum Update {
-    ConfigUpdate { update: ConfigUpdate },
+    ConfigUpdate {
+        update: ConfigUpdate,
+    },
+    /// Replaces a voxel and records it in the edit history. Voxels which aren't loaded at full
+    /// detail are left alone.
+    SetVoxel {
+        pos: IVec3,
+        voxel: VoxelType,
+    },
+    /// Applies a bulk edit and records it in the edit history as one transaction.
+    Brush {
+        brush: Brush,
+    },
+    /// Copies the voxels between `min` and `max` inclusive and sends the schematic back, or the
+    /// error if the region is too large. Logs a warning instead if `reply` stays full.
+    CopySchematic {
+        min: IVec3,
+        max: IVec3,
+        reply: mpsc::Sender<Result<Schematic, RegionTooLarge>>,
+    },
+    /// Pastes a schematic with its minimum corner at `origin` and records it in the edit history
+    /// as one transaction.
+    PasteSchematic {
+        schematic: Arc<Schematic>,
+        origin: IVec3,
+        keep_existing: bool,
+    },
+    /// Groups the following edits until `CommitTransaction` so they get undone together.
+    BeginTransaction,
+    CommitTransaction,
+    Undo,
+    Redo,
     ShutDown,
 }
 
@@ -35,6 +91,14 @@ pub struct RenderThreadChannels {
     pub player: Arc<RwLock<CamController>>,
     pub voxel_collider: Arc<RwLock<HashMap<ChunkID, BitMap3D>>>,
     pub mesh_updates: MeshReceiver,
+    /// The center and the type of every falling block.
+    pub falling_blocks: Arc<RwLock<Vec<(Vec3, VoxelType)>>>,
+    /// Refreshed a few times a second.
+    pub metrics: Arc<RwLock<Metrics>>,
+    pub requests: ChunkRequests,
+    /// Finishes after [`Update::ShutDown`] or once `updates` got dropped. Fails with
+    /// [`io::ErrorKind::TimedOut`] if workers were still busy after the shutdown timeout.
+    pub engine: thread::JoinHandle<Result<(), io::Error>>,
 }
 
 pub fn engine_thread(
@@ -51,15 +115,28 @@ pub fn engine_thread(
     let collider = Arc::new(RwLock::new(HashMap::<ChunkID, BitMap3D>::new()));
     let collider_render = collider.clone();
 
+    let falling_blocks = Arc::new(RwLock::new(Vec::new()));
+    let falling_blocks_render = falling_blocks.clone();
+
+    let (requests, mut requests_recv) = ChunkRequests::new();
+
+    let metrics = Arc::new(RwLock::new(Metrics::default()));
+    let metrics_render = metrics.clone();
+    let exporter = match &config.metrics_address {
+        Some(address) => Some(serve_prometheus(
+            TcpListener::bind(address.as_str())?,
+            metrics.clone(),
+        )?),
+        None => None,
+    };
+
     let (mesh_updates_tx, mesh_updates_rx) =
         mpsc::new::<(ChunkID, MeshUpload)>(config.mesh_queue_cap);
 
-    thread::Builder::new()
+    let engine = thread::Builder::new()
         .name("engine thread".to_owned())
         .spawn(move || -> Result<(), io::Error> {
-            let worker_count = (num_cpus::get() - 2).min(config.worker_count).max(1); // minus main + engine thread
-
-            let mut working_class = WorkerSPMC::new();
+            let mut working_class = WorkerSPMC::new(view(&config, &player.read()));
 
             let (chunk_tx, chunk_submission_queue) =
                 mpsc::new::<(ChunkID, Chunk)>(config.chunk_queue_cap);
@@ -73,11 +150,23 @@ pub fn engine_thread(
             let (solid_maps_tx, solid_map_queue) =
                 mpsc::new::<(ChunkID, Box<[BitMap2D; 6]>)>(config.solid_map_queue_cap);
 
-            let threadpool = Threadpool::new(worker_count, |_| worker::Context {
-                config: config.worker_config(),
-                config_queue: working_class.add_config_queue(config.engine_worker_config_queue_cap),
-
-                task_queues: working_class.add_task_queues(config.task_queue_cap, MAX_LOD),
+            let (request_results_tx, request_results) =
+                mpsc::new::<(ChunkID, Requested)>(config.request_queue_cap);
+            let mut pending_requests = PendingRequests::default();
+
+            let counters = Arc::new(Counters::default());
+            let (task_queue_cap, config_queue_cap) =
+                (config.task_queue_cap, config.engine_worker_config_queue_cap);
+            let new_worker = |id: WorkerID,
+                              signal: &Arc<WorkerSignal>,
+                              working_class: &mut WorkerSPMC,
+                              config: WorkerConfig| worker::Context {
+                config,
+                config_queue: working_class.add_config_queue(config_queue_cap),
+
+                id,
+                task_queue: working_class.add_task_queue(task_queue_cap, signal.clone()),
+                peers: working_class.queues(),
                 player_pos: player.clone(),
 
                 world_generator: world_generator.clone(),
@@ -89,13 +178,26 @@ pub fn engine_thread(
                 solid_map_tx: solid_maps_tx.clone(),
 
                 meshes: mesh_updates_tx.clone(),
+                requests: request_results_tx.clone(),
+                counters: counters.clone(),
+                signal: signal.clone(),
+            };
+            let mut threadpool = Threadpool::new(worker_count(&config), |id, signal| {
+                new_worker(id, signal, &mut working_class, config.worker_config())
             })?;
 
             let mut sphere_generator_allocations =
                 SphereGeneratorAllocations::default(config.max_chunks);
             let mut players_last_pos = None;
+            let mut saturated = false;
 
-            let mut chunks: HashMap<ChunkID, Chunk> = HashMap::with_capacity(10_000);
+            let mut world = World::with_capacity(10_000);
+            let mut fluids = Fluids::default();
+            let mut block_updates = BlockUpdates::new(DeterministicRng::seed_from_u64(
+                derive_seed(config.seed, "block_updates"),
+            ));
+            let mut falling = FallingBlocks::default();
+            let mut journal = Journal::new(config.edit_history_cap);
 
             let mut solid_maps: [HashMap<ChunkID, BitMap2D>; 6] = [
                 HashMap::with_capacity(10_000),
@@ -105,28 +207,109 @@ pub fn engine_thread(
                 HashMap::with_capacity(10_000),
                 HashMap::with_capacity(10_000),
             ];
-            print_info!("setup complete");
+            info!("setup complete");
 
             let mut time_window = Instant::now();
+            let mut last_print = Instant::now();
             let mut tick_count = 0_usize;
             'tick_loop: loop {
                 let tick_start = Instant::now();
+                let tick_span = trace_span!("tick").entered();
 
                 // update configs
                 while let Ok(update) = updates_recv.pop() {
                     use Update::*;
                     match update {
                         ConfigUpdate { update } => {
-                            working_class.submit_config_update(update.worker_config());
+                            if !working_class.submit_config_update(update.worker_config()) {
+                                Counters::count(&counters.config_stalls);
+                            }
                             config.update(update);
+
+                            // grow or shrink the pool and hand the tasks out again, as the chunks
+                            // are spread over the workers by their count
+                            let workers = worker_count(&config);
+                            if workers == threadpool.len() {
+                                continue;
+                            }
+                            let mut orphans = vec![];
+                            while threadpool.len() > workers {
+                                threadpool.retire_worker();
+                                orphans.extend(working_class.remove_worker());
+                            }
+                            while threadpool.len() < workers {
+                                let spawned = threadpool.spawn_worker(|id, signal| {
+                                    new_worker(id, signal, &mut working_class, config.worker_config())
+                                });
+                                if let Err(err) = spawned {
+                                    error!("couldn't spawn worker: {err}");
+                                    break;
+                                }
+                            }
+                            for task in working_class.redistribute(orphans) {
+                                match task {
+                                    Task::GenerateChunkAndMesh { chunk, .. } => {
+                                        submitted_chunks.remove(&chunk);
+                                        saturated = true;
+                                    }
+                                    Task::MeshChunk { chunk, .. } | Task::Collider { chunk, .. } => {
+                                        world.mark_dirty(chunk)
+                                    }
+                                    Task::Request { chunk, kind, .. } => {
+                                        pending_requests.retry(chunk, kind)
+                                    }
+                                }
+                            }
+                        }
+                        SetVoxel { pos, voxel } => _ = journal.set(&mut world, pos, voxel),
+                        Brush { brush } => journal.record_all(brush.apply(&mut world)),
+                        CopySchematic { min, max, reply } => {
+                            let deadline = Instant::now() + REPLY_TIMEOUT;
+                            let copy = Schematic::copy(&world, min, max);
+                            if reply
+                                .push_with_backoff(copy, || Instant::now() >= deadline)
+                                .is_err()
+                            {
+                                warn!("dropped a copied schematic, its reply queue is full");
+                            }
                         }
+                        PasteSchematic {
+                            schematic,
+                            origin,
+                            keep_existing,
+                        } => journal.record_all(schematic.paste(&mut world, origin, keep_existing)),
+                        BeginTransaction => journal.begin(),
+                        CommitTransaction => journal.commit(),
+                        Undo => _ = journal.undo(&mut world),
+                        Redo => _ = journal.redo(&mut world),
                         ShutDown => break 'tick_loop,
                     }
                 }
+                if updates_recv.is_abandoned() {
+                    break 'tick_loop;
+                }
+
+                working_class.flush_config_updates();
+
+                // generate and mesh what's in front of the camera first
+                let new_view = view(&config, &player.read());
+                let old = &working_class.view().frustum;
+                if new_view.frustum.cam_pos.floor() != old.cam_pos.floor()
+                    || new_view.frustum.direction.angle_between(old.direction) > REPRIORITIZE_ANGLE
+                    || (new_view.frustum.fov, new_view.frustum.aspect_ratio)
+                        != (old.fov, old.aspect_ratio)
+                {
+                    working_class.set_view(new_view);
+                }
 
                 // submit chunk generation tasks
                 let player_pos = { player.read().pos() / 32. }.round();
-                if Some(player_pos) != players_last_pos {
+                // once the workers caught up, retry what didn't fit into their queues last time
+                if saturated && working_class.len() <= working_class.capacity() / 2 {
+                    saturated = false;
+                    players_last_pos = None;
+                }
+                if Some(player_pos) != players_last_pos && !saturated {
                     players_last_pos = Some(player_pos);
 
                     sphere_generator_allocations.flood_fill(
@@ -135,32 +318,31 @@ pub fn engine_thread(
                         config.total_generation_distance,
                         config.max_chunks,
                         |chunk| {
-                            if submitted_chunks.insert(chunk) {
-                                let mut axis = 0;
-                                working_class.submit_task(
-                                    chunk,
-                                    Task::GenerateChunkAndMesh {
-                                        chunk,
-                                        neighbors: Box::new(chunk_neighbors(chunk).map(
-                                            |neighbor| {
-                                                let solid_map = solid_maps[axis]
-                                                    .get(&neighbor)
-                                                    .unwrap_or(&[0_u32; 32])
-                                                    .clone();
-                                                axis += 1;
-                                                solid_map
-                                            },
-                                        )),
-                                    },
-                                );
+                            // only full detail chunks are of use without meshes
+                            if saturated
+                                || submitted_chunks.contains(&chunk)
+                                || (config.headless && chunk.lod != 0)
+                            {
+                                return;
+                            }
+                            let task = Task::GenerateChunkAndMesh {
+                                chunk,
+                                neighbors: neighbor_solid_maps(&solid_maps, chunk),
+                            };
+                            match working_class.try_submit_task(chunk, task) {
+                                Ok(()) => _ = submitted_chunks.insert(chunk),
+                                Err(_) => saturated = true,
                             }
                         },
                     );
+                    if saturated {
+                        Counters::count(&counters.submission_stalls);
+                    }
                 }
 
                 // process thread pool output
-                while let Ok(submission) = chunk_submission_queue.pop() {
-                    chunks.insert(submission.0, submission.1);
+                while let Ok((chunk, data)) = chunk_submission_queue.pop() {
+                    world.insert_chunk(chunk, data);
                 }
 
                 while let Ok((chunk, solid_map)) = solid_map_queue.pop() {
@@ -179,10 +361,82 @@ pub fn engine_thread(
                     }
                 }
 
+                // serve awaited chunks
+                while let Ok(request) = requests_recv.try_recv() {
+                    pending_requests.add(request, &world);
+                }
+                pending_requests.prune();
+                pending_requests.submit(|chunk, kind, canceled| {
+                    // loaded chunks are served as they are now, with their edits and light
+                    let loaded = world
+                        .chunk(chunk)
+                        .map(|data| (Box::new(data.to_buffer()), world.padded_light(chunk)));
+                    let task = Task::Request {
+                        chunk,
+                        kind,
+                        loaded,
+                        neighbors: neighbor_solid_maps(&solid_maps, chunk),
+                        canceled,
+                    };
+                    working_class.try_submit_task(chunk, task).is_ok()
+                });
+                while let Ok((chunk, requested)) = request_results.pop() {
+                    pending_requests.complete(chunk, requested);
+                }
+
                 while let Ok(chunk) = discarded_tasks_queue.pop() {
                     submitted_chunks.remove(&chunk);
                 }
 
+                // simulate
+                let edits = world.take_edits();
+                fluids.voxels_changed(&world, &edits);
+                fluids.step(&mut world, config.fluid_updates_per_tick);
+                block_updates.voxels_changed(&world, &edits);
+                block_updates.tick(&mut world, &mut falling, config.random_ticks_per_chunk);
+                falling.step(&mut world, config.target_tps as f32);
+                *falling_blocks.write() = falling
+                    .blocks()
+                    .iter()
+                    .map(|block| (block.pos(), block.voxel))
+                    .collect();
+
+                // mesh edited and relit chunks, or only rebuild their colliders if nobody
+                // renders them
+                let dirty = world.take_dirty();
+                let mut mesh_stalled = false;
+                for chunk in dirty {
+                    let Some(data) = world.chunk(chunk) else {
+                        continue;
+                    };
+                    if mesh_stalled {
+                        world.mark_dirty(chunk);
+                        continue;
+                    }
+                    let task = if config.headless {
+                        Task::Collider {
+                            chunk,
+                            data: Box::new(data.to_buffer()),
+                        }
+                    } else {
+                        Task::MeshChunk {
+                            chunk,
+                            data: Box::new(data.to_buffer()),
+                            light: world.padded_light(chunk),
+                            neighbors: neighbor_solid_maps(&solid_maps, chunk),
+                        }
+                    };
+                    if working_class.try_submit_task(chunk, task).is_err() {
+                        // try again next tick
+                        world.mark_dirty(chunk);
+                        mesh_stalled = true;
+                    }
+                }
+                if mesh_stalled {
+                    Counters::count(&counters.submission_stalls);
+                }
+
+                drop(tick_span);
                 let tick_time = tick_start.elapsed().as_secs_f64();
                 if tick_time < config.target_tps {
                     thread::sleep(Duration::from_secs_f64(config.target_tps - tick_time));
@@ -191,21 +445,70 @@ pub fn engine_thread(
                 // tick measurement
                 tick_count += 1;
                 let time_elapsed = time_window.elapsed().as_secs_f64();
-                if let Some(time_per_print) = config.print_tps_per {
-                    if time_elapsed >= time_per_print {
-                        print_info!(
-                            "tps  {}\tqueued-tasks  {}",
-                            (tick_count as f64 / time_elapsed).round() as usize,
-                            working_class.len()
-                        );
-                        tick_count = 0;
-                        time_window = Instant::now();
+                if time_elapsed >= METRICS_INTERVAL {
+                    // lower detail chunks are only kept as meshes, which come with a collider
+                    let mut chunks_per_lod = vec![world.loaded_chunks()];
+                    for chunk in collider.read().keys().filter(|chunk| chunk.lod != 0) {
+                        count_lod(&mut chunks_per_lod, *chunk);
                     }
+                    *metrics.write() = Metrics {
+                        tps: tick_count as f64 / time_elapsed,
+                        workers: threadpool.len(),
+                        generate: counters.generate.snapshot(),
+                        mesh: counters.mesh.snapshot(),
+                        collider: counters.collider.snapshot(),
+                        queued_tasks_per_lod: working_class.len_per_lod(),
+                        chunks_per_lod,
+                        memory: MemoryUsage {
+                            chunks: world.memory_usage(),
+                            solid_maps: solid_maps.iter().map(HashMap::len).sum::<usize>()
+                                * size_of::<(ChunkID, BitMap2D)>(),
+                            colliders: collider.read().len() * size_of::<(ChunkID, BitMap3D)>(),
+                            edit_history: journal.memory_usage(),
+                        },
+                        pending_requests: pending_requests.len(),
+                        cancellations: Counters::get(&counters.cancellations),
+                        output_stalls: Counters::get(&counters.output_stalls),
+                        submission_stalls: Counters::get(&counters.submission_stalls),
+                        config_stalls: Counters::get(&counters.config_stalls),
+                    };
+                    tick_count = 0;
+                    time_window = Instant::now();
+                }
+                if let Some(time_per_print) = config.print_tps_per
+                    && last_print.elapsed().as_secs_f64() >= time_per_print
+                {
+                    let metrics = metrics.read();
+                    info!(
+                        "tps  {}\tqueued-tasks  {}\tfluid-chunks  {}\tscheduled-updates  {}\tmemory  {}KB\tedit-history  {}KB\tcanceled  {}\tstalls  {}/{}/{}",
+                        metrics.tps.round() as usize,
+                        working_class.len(),
+                        fluids.active_chunks(),
+                        block_updates.scheduled(),
+                        metrics.memory.total() >> 10,
+                        metrics.memory.edit_history >> 10,
+                        metrics.cancellations,
+                        metrics.output_stalls,
+                        metrics.submission_stalls,
+                        metrics.config_stalls
+                    );
+                    last_print = Instant::now();
                 }
             }
-            print_info!("SHUTDOWN");
+            info!("shut down");
 
-            drop(threadpool);
+            // in-flight tasks finish, the pending ones are of no use anymore
+            working_class.clear();
+            let stopped = threadpool.shutdown(SHUTDOWN_TIMEOUT);
+            if let Some(exporter) = exporter {
+                exporter.shutdown();
+            }
+            if !stopped {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "workers didn't stop in time",
+                ));
+            }
             Ok(())
         })?;
 
@@ -214,5 +517,46 @@ pub fn engine_thread(
         player: player_render,
         voxel_collider: collider_render,
         mesh_updates: mesh_updates_rx,
+        falling_blocks: falling_blocks_render,
+        metrics: metrics_render,
+        requests,
+        engine,
     })
 }
+
+fn worker_count(config: &EngineConfig) -> usize {
+    // minus main + engine thread
+    num_cpus::get()
+        .saturating_sub(2)
+        .min(config.worker_count)
+        .max(1)
+}
+
+fn view(config: &EngineConfig, player: &CamController) -> View {
+    View {
+        frustum: Frustum {
+            cam_pos: player.pos() / 32.,
+            direction: player.dir(),
+            fov: config.fov,
+            aspect_ratio: config.aspect_ratio,
+            max_chunks: config.max_chunks,
+            max_distance: config.total_generation_distance,
+            full_detail_range: config.full_detail_distance,
+        },
+    }
+}
+
+fn neighbor_solid_maps(
+    solid_maps: &[HashMap<ChunkID, BitMap2D>; 6],
+    chunk: ChunkID,
+) -> Box<[BitMap2D; 6]> {
+    let mut axis = 0;
+    Box::new(chunk_neighbors(chunk).map(|neighbor| {
+        let solid_map = solid_maps[axis]
+            .get(&neighbor)
+            .unwrap_or(&[0_u32; 32])
+            .clone();
+        axis += 1;
+        solid_map
+    }))
+}
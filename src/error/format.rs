@@ -0,0 +1,62 @@
+use std::{
+    fmt::Display,
+    io::{self, ErrorKind},
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors while reading or writing one of the supported file formats.
+#[derive(Debug)]
+pub enum Error {
+    FileNotFound,
+    PermissionDenied,
+    Io {
+        err: io::Error,
+    },
+
+    /// The data doesn't follow the format.
+    Corrupt {
+        msg: String,
+    },
+    /// The data is valid but uses a feature that isn't supported.
+    Unsupported {
+        msg: String,
+    },
+}
+
+impl Error {
+    pub(crate) fn corrupt(msg: impl Into<String>) -> Self {
+        Self::Corrupt { msg: msg.into() }
+    }
+
+    pub(crate) fn unsupported(msg: impl Into<String>) -> Self {
+        Self::Unsupported { msg: msg.into() }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Error::*;
+        match self {
+            FileNotFound => write!(f, "the file was not found"),
+            PermissionDenied => {
+                write!(f, "the process didnt had the permission to access the file")
+            }
+            Io { err } => write!(f, "{err}"),
+
+            Corrupt { msg } => write!(f, "the file is corrupt: {msg}"),
+            Unsupported { msg } => write!(f, "the file uses an unsupported feature: {msg}"),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        match value.kind() {
+            ErrorKind::NotFound => Error::FileNotFound,
+            ErrorKind::PermissionDenied => Error::PermissionDenied,
+            ErrorKind::UnexpectedEof => Error::corrupt("unexpected end of file"),
+            _ => Error::Io { err: value },
+        }
+    }
+}
@@ -1,4 +1,8 @@
 mod config;
+mod format;
 
 pub type ConfigResult<T> = config::Result<T>;
 pub type ConfigError = config::Error;
+
+pub type FormatResult<T> = format::Result<T>;
+pub type FormatError = format::Error;
@@ -0,0 +1,192 @@
+use glam::{IVec3, Vec3};
+
+use crate::{
+    VoxelType, VoxelTypes, block,
+    physics::{Aabb, Body, Voxel},
+    voxel,
+    world::World,
+};
+
+/// In voxels per second squared.
+const GRAVITY: f32 = 20.;
+
+/// Slightly smaller than a voxel, so a block sitting exactly on the grid only touches its own
+/// voxel.
+const HALF_EXTENTS: Vec3 = Vec3::splat(0.49);
+
+/// A voxel that lost its support and falls as an entity until it lands.
+#[derive(Debug, Clone)]
+pub struct FallingBlock {
+    pub voxel: VoxelType,
+    body: Body,
+    aabb: Aabb,
+}
+
+impl FallingBlock {
+    pub fn pos(&self) -> Vec3 {
+        self.aabb.center()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FallingBlocks {
+    blocks: Vec<FallingBlock>,
+}
+
+impl Voxel for World {
+    /// Voxels that aren't loaded count as solid, so nothing falls out of the loaded world.
+    fn solid_at(&self, pos: IVec3) -> bool {
+        self.get(pos)
+            .is_none_or(|voxel| voxel::is_physically_solid_u32(voxel) != 0)
+    }
+}
+
+impl FallingBlocks {
+    /// Takes the voxel at `pos` out of the world and lets it fall.
+    pub fn spawn(&mut self, world: &mut World, pos: IVec3) {
+        let Some(voxel) = world.set(pos, VoxelTypes::Air as u16) else {
+            return;
+        };
+        let center = pos.as_vec3() + 0.5;
+        self.blocks.push(FallingBlock {
+            voxel,
+            body: Body::new(center),
+            aabb: Aabb::new(center, HALF_EXTENTS),
+        });
+    }
+
+    pub fn blocks(&self) -> &[FallingBlock] {
+        &self.blocks
+    }
+
+    /// Moves every block by one tick of `dt` seconds and puts the ones that landed back into the
+    /// world. Blocks that landed on a chunk that isn't loaded wait for it.
+    pub fn step(&mut self, world: &mut World, dt: f32) {
+        self.blocks.retain_mut(|falling| {
+            falling.body.add_impuls(Vec3::NEG_Y * GRAVITY * dt * dt);
+            falling.body.step_time(0.);
+
+            let before = falling.aabb.center();
+            let delta = falling.body.pos() - before;
+            falling.aabb.sweep_through_voxel(world, delta, 0.);
+            let after = falling.aabb.center();
+            falling.body.constrain(|_, _| after);
+
+            let blocked = after.y - before.y > delta.y * 0.5;
+            if !blocked {
+                return true;
+            }
+
+            !settle(world, block(after), falling.voxel)
+        });
+    }
+}
+
+/// Puts the voxel into the free voxel resting on the ground closest to `pos`, as the sweep might
+/// stop a bit above the ground or inside of it. Returns false if that voxel isn't loaded.
+fn settle(world: &mut World, mut pos: IVec3, voxel: VoxelType) -> bool {
+    while world.solid_at(pos) {
+        if world.get(pos).is_none() {
+            return false;
+        }
+        pos.y += 1;
+    }
+    while !world.solid_at(pos - IVec3::Y) {
+        pos.y -= 1;
+    }
+    world.set(pos, voxel).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+    use rand::SeedableRng;
+
+    use crate::{
+        Chunk, ChunkID, DeterministicRng, VoxelTypes, block_updates::BlockUpdates, voxel::fill,
+        world::World,
+    };
+
+    use super::FallingBlocks;
+
+    const AIR: u16 = VoxelTypes::Air as u16;
+    const STONE: u16 = VoxelTypes::Stone as u16;
+    const SAND: u16 = VoxelTypes::Sand as u16;
+
+    #[test]
+    fn blocks_fall_and_land_on_the_ground() {
+        let mut world = World::default();
+        world.insert_chunk(
+            ChunkID::new(0, IVec3::NEG_Y),
+            Chunk::from_buffer(&fill(STONE)),
+        );
+        world.insert_chunk(ChunkID::new(0, IVec3::ZERO), Chunk::from_buffer(&fill(AIR)));
+        world.set(IVec3::new(3, 20, 7), SAND);
+
+        let mut falling = FallingBlocks::default();
+        falling.spawn(&mut world, IVec3::new(3, 20, 7));
+        assert_eq!(world.get(IVec3::new(3, 20, 7)), Some(AIR));
+        assert_eq!(falling.blocks().len(), 1);
+
+        for _ in 0..200 {
+            falling.step(&mut world, 1. / 30.);
+        }
+        assert!(falling.blocks().is_empty());
+        assert_eq!(world.get(IVec3::new(3, 0, 7)), Some(SAND));
+    }
+
+    #[test]
+    fn removing_the_support_makes_sand_fall() {
+        let mut world = World::default();
+        world.insert_chunk(
+            ChunkID::new(0, IVec3::NEG_Y),
+            Chunk::from_buffer(&fill(STONE)),
+        );
+        world.insert_chunk(ChunkID::new(0, IVec3::ZERO), Chunk::from_buffer(&fill(AIR)));
+        world.set(IVec3::new(3, 5, 7), STONE);
+        world.set(IVec3::new(3, 6, 7), SAND);
+        world.set(IVec3::new(3, 7, 7), SAND);
+
+        let mut updates = BlockUpdates::new(DeterministicRng::seed_from_u64(0));
+        let mut falling = FallingBlocks::default();
+        let mut run = |world: &mut World, ticks| {
+            for _ in 0..ticks {
+                let edits = world.take_edits();
+                updates.voxels_changed(world, &edits);
+                updates.tick(world, &mut falling, 0);
+                falling.step(world, 1. / 30.);
+            }
+        };
+
+        run(&mut world, 10);
+        assert_eq!(world.get(IVec3::new(3, 7, 7)), Some(SAND));
+
+        world.set(IVec3::new(3, 5, 7), AIR);
+        run(&mut world, 100);
+        assert_eq!(world.get(IVec3::new(3, 0, 7)), Some(SAND));
+        assert_eq!(world.get(IVec3::new(3, 1, 7)), Some(SAND));
+        assert_eq!(world.get(IVec3::new(3, 6, 7)), Some(AIR));
+    }
+
+    #[test]
+    fn blocks_wait_for_the_chunk_they_land_in() {
+        let mut world = World::default();
+        world.insert_chunk(ChunkID::new(0, IVec3::ZERO), Chunk::from_buffer(&fill(AIR)));
+        world.set(IVec3::new(3, 20, 7), SAND);
+        let mut falling = FallingBlocks::default();
+        falling.spawn(&mut world, IVec3::new(3, 20, 7));
+        falling.step(&mut world, 1. / 30.);
+
+        // buried, with the only free voxel above in a chunk that isn't loaded yet
+        world.fill_chunk(ChunkID::new(0, IVec3::ZERO), STONE);
+        for _ in 0..10 {
+            falling.step(&mut world, 1. / 30.);
+        }
+        assert_eq!(falling.blocks().len(), 1);
+
+        world.insert_chunk(ChunkID::new(0, IVec3::Y), Chunk::from_buffer(&fill(AIR)));
+        falling.step(&mut world, 1. / 30.);
+        assert!(falling.blocks().is_empty());
+        assert_eq!(world.get(IVec3::new(3, 32, 7)), Some(SAND));
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::{HashMap, HashSet};
+
+use glam::IVec3;
+
+use crate::{
+    ChunkID, VoxelTypes,
+    light::split,
+    voxel::{self, MAX_FLUID_LEVEL, fluid, fluid_level},
+    world::World,
+};
+
+/// Lava only flows every few ticks.
+const LAVA_SLOWDOWN: u64 = 4;
+
+const HORIZONTAL: [IVec3; 4] = [IVec3::X, IVec3::Z, IVec3::NEG_X, IVec3::NEG_Z];
+
+const AIR: u16 = VoxelTypes::Air as u16;
+
+/// Cellular fluid flow. Fluid cells fall into the voxel below and spread sideways on the ground
+/// one level at a time, keeping the amount of fluid constant.
+///
+/// Only cells that might still move are simulated. They are grouped by chunk, so chunks with
+/// settled fluid cost nothing until a voxel next to them changes.
+#[derive(Debug, Default)]
+pub struct Fluids {
+    active: HashMap<ChunkID, HashSet<IVec3>>,
+    tick: u64,
+}
+
+impl Fluids {
+    /// Wakes up the fluid cells at and next to the changed voxels.
+    pub fn voxels_changed(&mut self, world: &World, edits: &[IVec3]) {
+        for pos in edits {
+            for neighbor in [IVec3::ZERO, IVec3::NEG_Y, IVec3::Y]
+                .into_iter()
+                .chain(HORIZONTAL)
+                .map(|dir| *pos + dir)
+            {
+                if world.get(neighbor).is_some_and(voxel::is_fluid) {
+                    self.wake(neighbor);
+                }
+            }
+        }
+    }
+
+    fn wake(&mut self, pos: IVec3) {
+        self.active.entry(split(pos).0).or_default().insert(pos);
+    }
+
+    /// The number of chunks with fluid that is still moving.
+    pub fn active_chunks(&self) -> usize {
+        self.active.len()
+    }
+
+    /// Simulates up to `max_updates` active cells. The changes show up in the edits of the
+    /// world, which wake up the cells around them for the next step.
+    pub fn step(&mut self, world: &mut World, max_updates: usize) {
+        self.tick += 1;
+
+        let mut cells = Vec::new();
+        self.active.retain(|_, chunk_cells| {
+            let take = chunk_cells.len().min(max_updates - cells.len());
+            let taken: Vec<IVec3> = chunk_cells.iter().copied().take(take).collect();
+            for cell in &taken {
+                chunk_cells.remove(cell);
+            }
+            cells.extend(taken);
+            !chunk_cells.is_empty()
+        });
+
+        // lower cells first, so a falling column moves as one
+        cells.sort_unstable_by_key(|cell| cell.y);
+        for cell in cells {
+            self.flow(world, cell);
+        }
+    }
+
+    fn flow(&mut self, world: &mut World, pos: IVec3) {
+        let Some(voxel) = world.get(pos).filter(|voxel| voxel::is_fluid(*voxel)) else {
+            return;
+        };
+        let kind = voxel::kind(voxel);
+        if kind == VoxelTypes::Lava as u16 && !self.tick.is_multiple_of(LAVA_SLOWDOWN) {
+            self.wake(pos);
+            return;
+        }
+
+        let mut level = fluid_level(voxel);
+        let below = pos - IVec3::Y;
+        match world.get(below) {
+            Some(AIR) => {
+                world.set(below, voxel);
+                world.set(pos, AIR);
+                return;
+            }
+            Some(below_voxel) if voxel::kind(below_voxel) == kind => {
+                let moved = (MAX_FLUID_LEVEL - fluid_level(below_voxel)).min(level);
+                if moved > 0 {
+                    world.set(below, fluid(kind, fluid_level(below_voxel) + moved));
+                    level -= moved;
+                }
+            }
+            _ => {}
+        }
+
+        // rotate the order every tick, so the fluid doesn't drift in one direction
+        for i in 0..HORIZONTAL.len() {
+            if level <= 1 {
+                break;
+            }
+            let neighbor = pos + HORIZONTAL[(i + self.tick as usize) % HORIZONTAL.len()];
+            match world.get(neighbor) {
+                Some(AIR) => {
+                    world.set(neighbor, fluid(kind, 1));
+                    level -= 1;
+                }
+                Some(other) if voxel::kind(other) == kind && fluid_level(other) + 1 < level => {
+                    world.set(neighbor, fluid(kind, fluid_level(other) + 1));
+                    level -= 1;
+                }
+                _ => {}
+            }
+        }
+
+        if level != fluid_level(voxel) {
+            world.set(pos, if level == 0 { AIR } else { fluid(kind, level) });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::{
+        Chunk, ChunkID, VoxelTypes,
+        voxel::{MAX_FLUID_LEVEL, fill, fluid_level, is_fluid},
+        world::World,
+    };
+
+    use super::Fluids;
+
+    const AIR: u16 = VoxelTypes::Air as u16;
+    const STONE: u16 = VoxelTypes::Stone as u16;
+    const WATER: u16 = VoxelTypes::Water as u16;
+    const LAVA: u16 = VoxelTypes::Lava as u16;
+
+    /// Air with a stone floor below `y = 0`.
+    fn basin() -> World {
+        let mut world = World::default();
+        world.insert_chunk(
+            ChunkID::new(0, IVec3::NEG_Y),
+            Chunk::from_buffer(&fill(STONE)),
+        );
+        world.insert_chunk(ChunkID::new(0, IVec3::ZERO), Chunk::from_buffer(&fill(AIR)));
+        world
+    }
+
+    fn run(fluids: &mut Fluids, world: &mut World, steps: usize) {
+        for _ in 0..steps {
+            let edits = world.take_edits();
+            fluids.voxels_changed(world, &edits);
+            fluids.step(world, usize::MAX);
+        }
+    }
+
+    fn total_fluid(world: &World) -> u32 {
+        let mut total = 0;
+        for x in 0..32 {
+            for y in 0..32 {
+                for z in 0..32 {
+                    let voxel = world.get(IVec3::new(x, y, z)).unwrap();
+                    if is_fluid(voxel) {
+                        total += fluid_level(voxel) as u32;
+                    }
+                }
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn water_falls_spreads_and_settles() {
+        let mut world = basin();
+        let mut fluids = Fluids::default();
+        world.set(IVec3::new(16, 10, 16), WATER);
+
+        run(&mut fluids, &mut world, 100);
+
+        assert!(world.get(IVec3::new(16, 10, 16)) == Some(AIR));
+        assert!(world.get(IVec3::new(16, 0, 16)).is_some_and(is_fluid));
+        assert!(world.get(IVec3::new(17, 0, 16)).is_some_and(is_fluid));
+        assert_eq!(total_fluid(&world), MAX_FLUID_LEVEL as u32);
+        assert_eq!(fluids.active_chunks(), 0);
+    }
+
+    #[test]
+    fn lava_flows_slower_than_water() {
+        let height_after_falling = |fluid| {
+            let mut world = basin();
+            world.set(IVec3::new(16, 20, 16), fluid);
+            run(&mut Fluids::default(), &mut world, 8);
+            (0..32)
+                .find(|y| world.get(IVec3::new(16, *y, 16)) != Some(AIR))
+                .unwrap()
+        };
+        assert_eq!(height_after_falling(WATER), 12);
+        assert_eq!(height_after_falling(LAVA), 18);
+    }
+}
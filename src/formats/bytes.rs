@@ -0,0 +1,46 @@
+//! Bounds checked reading of little endian binary data.
+
+use crate::error::{FormatError, FormatResult};
+
+pub(crate) struct ByteReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn take(&mut self, len: usize) -> FormatResult<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(FormatError::corrupt("unexpected end of file"));
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> FormatResult<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> FormatResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> FormatResult<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> FormatResult<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn i32(&mut self) -> FormatResult<i32> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+}
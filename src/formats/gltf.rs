@@ -0,0 +1,188 @@
+//! Binary glTF (`.glb`) export of meshes. Every texture becomes its own primitive and material,
+//! the light of each face is stored as vertex color.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+use glam::Vec3;
+
+use crate::{
+    ChunkID, TextureID,
+    mesh::{MeshUpload, Quad},
+};
+
+const UVS: [[f32; 2]; 4] = [[0., 0.], [1., 0.], [1., 1.], [0., 1.]];
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// Writes the meshes at their chunk's place in the world as a single mesh.
+pub fn write_glb(meshes: &[(ChunkID, &MeshUpload)], mut out: impl Write) -> io::Result<()> {
+    let mut by_texture: BTreeMap<TextureID, Vec<Quad>> = BTreeMap::new();
+    for (chunk, mesh) in meshes {
+        for quad in mesh.quads(*chunk) {
+            by_texture.entry(quad.texture).or_default().push(quad);
+        }
+    }
+
+    let mut bin = Vec::new();
+    let mut buffer_views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut primitives = Vec::new();
+    let mut materials = Vec::new();
+
+    for (material, (texture, quads)) in by_texture.iter().enumerate() {
+        let (mut min, mut max) = (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY));
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut colors = Vec::new();
+        let mut indices = Vec::new();
+        for (i, quad) in quads.iter().enumerate() {
+            let brightness = quad.brightness();
+            for (corner, uv) in quad.corners.iter().zip(UVS) {
+                min = min.min(*corner);
+                max = max.max(*corner);
+                positions.extend(corner.to_array());
+                normals.extend(quad.normal.to_array());
+                uvs.extend(uv);
+                colors.extend([brightness, brightness, brightness, 1.]);
+            }
+            let v = 4 * i as u32;
+            indices.extend([v, v + 1, v + 2, v, v + 2, v + 3]);
+        }
+
+        let vertices = quads.len() * 4;
+        let mut attribute = |data: &[f32], kind: &str, extra: String| {
+            let view = push_view(&mut bin, &mut buffer_views, data, ARRAY_BUFFER);
+            accessors.push(format!(
+                r#"{{"bufferView":{view},"componentType":{FLOAT},"count":{vertices},"type":"{kind}"{extra}}}"#
+            ));
+            accessors.len() - 1
+        };
+        let position = attribute(
+            &positions,
+            "VEC3",
+            format!(
+                r#","min":[{},{},{}],"max":[{},{},{}]"#,
+                min.x, min.y, min.z, max.x, max.y, max.z
+            ),
+        );
+        let normal = attribute(&normals, "VEC3", String::new());
+        let uv = attribute(&uvs, "VEC2", String::new());
+        let color = attribute(&colors, "VEC4", String::new());
+
+        let view = push_view(&mut bin, &mut buffer_views, &indices, ELEMENT_ARRAY_BUFFER);
+        accessors.push(format!(
+            r#"{{"bufferView":{view},"componentType":{UNSIGNED_INT},"count":{},"type":"SCALAR"}}"#,
+            indices.len()
+        ));
+        primitives.push(format!(
+            r#"{{"attributes":{{"POSITION":{position},"NORMAL":{normal},"TEXCOORD_0":{uv},"COLOR_0":{color}}},"indices":{},"material":{material}}}"#,
+            accessors.len() - 1
+        ));
+        materials.push(format!(
+            r#"{{"name":"texture_{texture}","pbrMetallicRoughness":{{"metallicFactor":0}}}}"#
+        ));
+    }
+
+    let mut json = String::from(r#"{"asset":{"version":"2.0","generator":"voxine"}"#);
+    if !primitives.is_empty() {
+        write!(
+            json,
+            r#","scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"meshes":[{{"primitives":[{}]}}],"materials":[{}],"accessors":[{}],"bufferViews":[{}],"buffers":[{{"byteLength":{}}}]"#,
+            primitives.join(","),
+            materials.join(","),
+            accessors.join(","),
+            buffer_views.join(","),
+            bin.len()
+        )
+        .unwrap();
+    }
+    json.push('}');
+
+    let mut json = json.into_bytes();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let bin_chunk = if bin.is_empty() { 0 } else { 8 + bin.len() };
+
+    out.write_all(b"glTF")?;
+    out.write_all(&2_u32.to_le_bytes())?;
+    out.write_all(&((12 + 8 + json.len() + bin_chunk) as u32).to_le_bytes())?;
+    out.write_all(&(json.len() as u32).to_le_bytes())?;
+    out.write_all(b"JSON")?;
+    out.write_all(&json)?;
+    if !bin.is_empty() {
+        out.write_all(&(bin.len() as u32).to_le_bytes())?;
+        out.write_all(b"BIN\0")?;
+        out.write_all(&bin)?;
+    }
+    Ok(())
+}
+
+/// Appends the data to the binary chunk and describes it with a buffer view. All data is made of
+/// 4 byte values, so every view stays aligned.
+fn push_view<T: bytemuck::Pod>(
+    bin: &mut Vec<u8>,
+    views: &mut Vec<String>,
+    data: &[T],
+    target: u32,
+) -> usize {
+    let bytes: &[u8] = bytemuck::cast_slice(data);
+    views.push(format!(
+        r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{target}}}"#,
+        bin.len(),
+        bytes.len()
+    ));
+    bin.extend_from_slice(bytes);
+    views.len() - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3};
+
+    use crate::{ChunkID, mesh::Mesh};
+
+    use super::write_glb;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_a_valid_container() {
+        let mut mesh = Mesh::with_capacity(2);
+        mesh.add_px(UVec3::new(0, 0, 0), 3, 0xF0);
+        mesh.add_nz(UVec3::new(2, 0, 0), 3, 0xF0);
+        mesh.add_ny(UVec3::new(5, 0, 0), 1, 0x00);
+        let mesh = mesh.bytes();
+
+        let mut out = Vec::new();
+        write_glb(&[(ChunkID::new(0, IVec3::ZERO), &mesh)], &mut out).unwrap();
+
+        assert_eq!(&out[..4], b"glTF");
+        assert_eq!(u32_at(&out, 8) as usize, out.len());
+        let json_len = u32_at(&out, 12) as usize;
+        assert_eq!(&out[16..20], b"JSON");
+        let json = std::str::from_utf8(&out[20..20 + json_len]).unwrap();
+        let bin_len = u32_at(&out, 20 + json_len) as usize;
+        assert_eq!(&out[24 + json_len..28 + json_len], b"BIN\0");
+        assert_eq!(28 + json_len + bin_len, out.len());
+
+        // one primitive per texture: 4 vertices and 6 indices per quad
+        assert_eq!(json.matches("texture_").count(), 2);
+        assert!(json.contains(r#""count":8,"type":"VEC3","min":[1,0,0],"max":[3,1,1]"#));
+        assert!(json.contains(r#""count":12,"type":"SCALAR""#));
+        // positions, normals and uvs, colors and indices of both primitives
+        assert_eq!(bin_len, 3 * (4 * 12 + 4 * 12 + 4 * 8 + 4 * 16 + 6 * 4));
+
+        let mut empty = Vec::new();
+        write_glb(&[], &mut empty).unwrap();
+        assert_eq!(u32_at(&empty, 8) as usize, empty.len());
+    }
+}
@@ -0,0 +1,376 @@
+//! A small DEFLATE decoder (RFC 1951) with the zlib (RFC 1950) and gzip (RFC 1952) wrappers.
+//! It follows the structure of zlib's `puff`, trading speed for being short.
+
+use crate::error::{FormatError, FormatResult};
+
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order in which the code length code lengths are stored in dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Decompresses a zlib stream, the format used by PNG and Anvil region files.
+pub fn zlib_decompress(data: &[u8]) -> FormatResult<Vec<u8>> {
+    let [cmf, flg, ..] = *data else {
+        return Err(FormatError::corrupt("zlib stream is too short"));
+    };
+    if cmf & 0x0F != 8 || (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+        return Err(FormatError::corrupt("invalid zlib header"));
+    }
+    if flg & 0x20 != 0 {
+        return Err(FormatError::unsupported("zlib preset dictionary"));
+    }
+    inflate(&data[2..])
+}
+
+/// Decompresses a gzip stream, the format used by NBT files.
+pub fn gzip_decompress(data: &[u8]) -> FormatResult<Vec<u8>> {
+    if data.len() < 18 || data[0..3] != [0x1F, 0x8B, 8] {
+        return Err(FormatError::corrupt("invalid gzip header"));
+    }
+    let flags = data[3];
+    let mut pos = 10;
+    let truncated = || FormatError::corrupt("gzip header runs past the end of the file");
+    if flags & 0x04 != 0 {
+        let extra = u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+        pos += 2 + extra;
+    }
+    for flag in [0x08, 0x10] {
+        if flags & flag != 0 {
+            let end = data
+                .get(pos..)
+                .ok_or_else(truncated)?
+                .iter()
+                .position(|byte| *byte == 0)
+                .ok_or_else(|| FormatError::corrupt("unterminated gzip header field"))?;
+            pos += end + 1;
+        }
+    }
+    if flags & 0x02 != 0 {
+        pos += 2;
+    }
+    inflate(data.get(pos..).ok_or_else(truncated)?)
+}
+
+/// Decompresses raw DEFLATE data.
+pub fn inflate(data: &[u8]) -> FormatResult<Vec<u8>> {
+    let mut state = State {
+        input: data,
+        pos: 0,
+        bit_buf: 0,
+        bit_count: 0,
+        out: Vec::with_capacity(data.len() * 4),
+    };
+
+    loop {
+        let last = state.bits(1)? == 1;
+        match state.bits(2)? {
+            0 => state.stored()?,
+            1 => {
+                let (lengths, distances) = fixed_tables();
+                state.codes(&lengths, &distances)?
+            }
+            2 => {
+                let (lengths, distances) = state.dynamic_tables()?;
+                state.codes(&lengths, &distances)?
+            }
+            _ => return Err(FormatError::corrupt("invalid deflate block type")),
+        }
+        if last {
+            return Ok(state.out);
+        }
+    }
+}
+
+struct State<'a> {
+    input: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+    out: Vec<u8>,
+}
+
+/// A canonical Huffman code, stored as the number of codes per length and the symbols ordered by code.
+struct Huffman {
+    count: [u16; MAX_BITS + 1],
+    symbol: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> FormatResult<Self> {
+        let mut count = [0_u16; MAX_BITS + 1];
+        lengths.iter().for_each(|len| count[*len as usize] += 1);
+
+        let mut left = 1_i32;
+        for count in &count[1..] {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err(FormatError::corrupt("over-subscribed huffman code"));
+            }
+        }
+
+        let mut offsets = [0_u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + count[len];
+        }
+        let mut symbol = vec![0; lengths.len()];
+        for (sym, len) in lengths.iter().enumerate() {
+            if *len != 0 {
+                symbol[offsets[*len as usize] as usize] = sym as u16;
+                offsets[*len as usize] += 1;
+            }
+        }
+        Ok(Self { count, symbol })
+    }
+}
+
+impl State<'_> {
+    fn bits(&mut self, need: u32) -> FormatResult<u32> {
+        while self.bit_count < need {
+            let byte = *self
+                .input
+                .get(self.pos)
+                .ok_or_else(|| FormatError::corrupt("deflate stream ended early"))?;
+            self.pos += 1;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1 << need) - 1);
+        self.bit_buf >>= need;
+        self.bit_count -= need;
+        Ok(value)
+    }
+
+    fn stored(&mut self) -> FormatResult<()> {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+
+        let header = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| FormatError::corrupt("stored block header ended early"))?;
+        let len = u16::from_le_bytes([header[0], header[1]]);
+        let nlen = u16::from_le_bytes([header[2], header[3]]);
+        if len != !nlen {
+            return Err(FormatError::corrupt("stored block length mismatch"));
+        }
+        self.pos += 4;
+
+        let data = self
+            .input
+            .get(self.pos..self.pos + len as usize)
+            .ok_or_else(|| FormatError::corrupt("stored block ended early"))?;
+        self.out.extend_from_slice(data);
+        self.pos += len as usize;
+        Ok(())
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> FormatResult<u16> {
+        let mut code = 0_i32;
+        let mut first = 0_i32;
+        let mut index = 0_i32;
+        for len in 1..=MAX_BITS {
+            code |= self.bits(1)? as i32;
+            let count = huffman.count[len] as i32;
+            if code - count < first {
+                return Ok(huffman.symbol[(index + code - first) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(FormatError::corrupt("invalid huffman code"))
+    }
+
+    fn codes(&mut self, lengths: &Huffman, distances: &Huffman) -> FormatResult<()> {
+        loop {
+            let symbol = self.decode(lengths)? as usize;
+            match symbol {
+                0..256 => self.out.push(symbol as u8),
+                256 => return Ok(()),
+                _ => {
+                    let symbol = symbol - 257;
+                    if symbol >= LENGTH_BASE.len() {
+                        return Err(FormatError::corrupt("invalid length symbol"));
+                    }
+                    let len = LENGTH_BASE[symbol] as usize
+                        + self.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+                    let symbol = self.decode(distances)? as usize;
+                    if symbol >= DISTANCE_BASE.len() {
+                        return Err(FormatError::corrupt("invalid distance symbol"));
+                    }
+                    let distance = DISTANCE_BASE[symbol] as usize
+                        + self.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+                    if distance > self.out.len() {
+                        return Err(FormatError::corrupt("distance reaches before the output"));
+                    }
+
+                    let start = self.out.len() - distance;
+                    for i in 0..len {
+                        self.out.push(self.out[start + i]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn dynamic_tables(&mut self) -> FormatResult<(Huffman, Huffman)> {
+        let literal_count = self.bits(5)? as usize + 257;
+        let distance_count = self.bits(5)? as usize + 1;
+        let code_length_count = self.bits(4)? as usize + 4;
+
+        let mut code_lengths = [0_u8; 19];
+        for i in 0..code_length_count {
+            code_lengths[CODE_LENGTH_ORDER[i]] = self.bits(3)? as u8;
+        }
+        let code_length_huffman = Huffman::new(&code_lengths)?;
+
+        let mut lengths = vec![0_u8; literal_count + distance_count];
+        let mut i = 0;
+        while i < lengths.len() {
+            let symbol = self.decode(&code_length_huffman)?;
+            let (value, repeat) = match symbol {
+                0..16 => (symbol as u8, 1),
+                16 => {
+                    let previous = *lengths[..i]
+                        .last()
+                        .ok_or_else(|| FormatError::corrupt("repeat without a length"))?;
+                    (previous, 3 + self.bits(2)? as usize)
+                }
+                17 => (0, 3 + self.bits(3)? as usize),
+                _ => (0, 11 + self.bits(7)? as usize),
+            };
+            if i + repeat > lengths.len() {
+                return Err(FormatError::corrupt("too many code lengths"));
+            }
+            lengths[i..i + repeat].fill(value);
+            i += repeat;
+        }
+
+        Ok((
+            Huffman::new(&lengths[..literal_count])?,
+            Huffman::new(&lengths[literal_count..])?,
+        ))
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0_u8; 288];
+    lengths[0..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..288].fill(8);
+    (
+        Huffman::new(&lengths).expect("the fixed code is valid"),
+        Huffman::new(&[5; 30]).expect("the fixed code is valid"),
+    )
+}
+
+/// Wraps `data` into a zlib stream of stored blocks. Used to write PNGs without a compressor.
+#[cfg(test)]
+pub fn zlib_store(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut chunks = data.chunks(u16::MAX as usize).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        out.push(chunks.peek().is_none() as u8);
+        out.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(chunk.len() as u16)).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Wraps `data` into a gzip stream of stored blocks. Used to write NBT files.
+#[cfg(test)]
+pub fn gzip_store(data: &[u8]) -> Vec<u8> {
+    let zlib = zlib_store(data);
+    let mut out = vec![0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF];
+    out.extend_from_slice(&zlib[2..zlib.len() - 4]);
+    out.extend_from_slice(&crate::formats::png::crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gzip_decompress, gzip_store, inflate, zlib_decompress, zlib_store};
+
+    #[test]
+    fn stored_blocks_round_trip() {
+        let data: Vec<u8> = (0..200_000_u32).map(|i| (i * 7 % 251) as u8).collect();
+        assert_eq!(zlib_decompress(&zlib_store(&data)).unwrap(), data);
+        assert_eq!(zlib_decompress(&zlib_store(&[])).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn fixed_huffman_block() {
+        // `printf 'hello hello hello' | zlib deflate -raw`
+        let compressed = [0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0xC8, 0x40, 0x90, 0x00];
+        assert_eq!(inflate(&compressed).unwrap(), b"hello hello hello");
+    }
+
+    #[test]
+    fn dynamic_huffman_block() {
+        // `zlib.compress(bytes((i * i // 7) % 13 + 97 for i in range(3000)), 9)`
+        let compressed = [
+            0x78, 0xDA, 0xED, 0xCC, 0xBB, 0x11, 0xC0, 0x30, 0x08, 0x04, 0xD1, 0x5A, 0x81, 0xE3,
+            0x23, 0x84, 0xFA, 0x4F, 0x2D, 0xCF, 0xB8, 0x04, 0x87, 0xB7, 0xF1, 0xCE, 0x13, 0x11,
+            0x35, 0x44, 0xF5, 0xA8, 0xD7, 0x58, 0x6E, 0xAB, 0x13, 0xE3, 0xDB, 0x27, 0x64, 0x61,
+            0x0A, 0xA7, 0x13, 0x32, 0x5D, 0x19, 0x8E, 0x9B, 0x47, 0xDE, 0x57, 0x90, 0x7D, 0x50,
+            0x83, 0x25, 0xDF, 0x7C, 0xCA, 0x76, 0xDA, 0x94, 0xEB, 0x7D, 0x03, 0xA6, 0xF2, 0x46,
+            0x9A, 0x34, 0x69, 0xD2, 0xA4, 0x49, 0x93, 0x26, 0x4D, 0x9A, 0x34, 0xE9, 0x3F, 0xE8,
+            0x07, 0xB5, 0xF8, 0xB3, 0xFC,
+        ];
+        let expected: Vec<u8> = (0..3000_u32)
+            .map(|i| ((i * i / 7) % 13 + 97) as u8)
+            .collect();
+        assert_eq!(zlib_decompress(&compressed).unwrap(), expected);
+    }
+
+    #[test]
+    fn gzip_header_fields_past_the_end_are_corrupt() {
+        let mut data = gzip_store(b"voxels");
+        assert_eq!(gzip_decompress(&data).unwrap(), b"voxels");
+
+        // an extra field longer than the whole file, with a file name flag behind it
+        data[3] = 0x04 | 0x08;
+        data[10..12].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(gzip_decompress(&data).is_err());
+    }
+}
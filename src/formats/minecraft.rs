@@ -0,0 +1,504 @@
+//! Imports Minecraft builds: Sponge `.schem` schematics (versions 2 and 3) and the sections of
+//! Anvil `.mca` region files (Minecraft 1.16 and newer, when block states stopped spanning
+//! longs).
+
+use std::collections::HashMap;
+
+use glam::{IVec3, UVec3};
+use serde::Deserialize;
+
+use crate::{
+    Chunk, ChunkID, VoxelType, VoxelTypes,
+    chunk::{DenseChunk, coords_to_1d_index},
+    error::{FormatError, FormatResult},
+    formats::{
+        inflate::{gzip_decompress, zlib_decompress},
+        nbt::{self, Tag},
+    },
+    schematic::Schematic,
+    voxel::fill,
+};
+
+const SECTION_SIZE: usize = 16;
+const SECTION_VOLUME: usize = SECTION_SIZE * SECTION_SIZE * SECTION_SIZE;
+const SECTOR_SIZE: usize = 4096;
+
+/// Maps block states like `minecraft:grass_block[snowy=false]` to voxel types. A state without an
+/// entry falls back to its block name without properties and then to `default`.
+///
+/// ```toml
+/// default = "Stone"
+///
+/// [blocks]
+/// "minecraft:oak_leaves" = "Grass"
+/// "minecraft:redstone_lamp[lit=true]" = "Lamp"
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockTranslation {
+    #[serde(default = "default_block")]
+    pub default: VoxelTypes,
+    #[serde(default)]
+    pub blocks: HashMap<String, VoxelTypes>,
+}
+
+fn default_block() -> VoxelTypes {
+    VoxelTypes::Stone
+}
+
+impl Default for BlockTranslation {
+    fn default() -> Self {
+        use VoxelTypes::*;
+        let blocks = [
+            ("minecraft:air", Air),
+            ("minecraft:cave_air", Air),
+            ("minecraft:void_air", Air),
+            ("minecraft:stone", Stone),
+            ("minecraft:cobblestone", CrackedStone),
+            ("minecraft:cracked_stone_bricks", CrackedStone),
+            ("minecraft:dirt", Dirt0),
+            ("minecraft:coarse_dirt", Dirt1),
+            ("minecraft:grass_block", Grass),
+            ("minecraft:sand", Sand),
+            ("minecraft:gravel", Gravel),
+            ("minecraft:water", Water),
+            ("minecraft:lava", Lava),
+            ("minecraft:glowstone", Lamp),
+            ("minecraft:sea_lantern", Lamp),
+        ];
+        Self {
+            default: default_block(),
+            blocks: blocks
+                .into_iter()
+                .map(|(name, voxel)| (name.to_string(), voxel))
+                .collect(),
+        }
+    }
+}
+
+impl BlockTranslation {
+    pub fn from_toml(text: &str) -> FormatResult<Self> {
+        toml::from_str(text).map_err(|err| FormatError::corrupt(err.to_string()))
+    }
+
+    pub fn translate(&self, state: &str) -> VoxelType {
+        let name = state.split('[').next().unwrap_or(state);
+        let voxel = self
+            .blocks
+            .get(state)
+            .or_else(|| self.blocks.get(name))
+            .unwrap_or(&self.default);
+        *voxel as u16
+    }
+}
+
+/// Reads a gzipped Sponge schematic.
+pub fn read_sponge_schematic(
+    data: &[u8],
+    translation: &BlockTranslation,
+) -> FormatResult<Schematic> {
+    let (_, root) = nbt::read(&gzip_decompress(data)?)?;
+    // version 3 wraps everything into a `Schematic` compound and moves the blocks into `Blocks`
+    let schematic = root.get("Schematic").unwrap_or(&root);
+    let blocks = schematic.get("Blocks").unwrap_or(schematic);
+
+    let dimension = |name| {
+        schematic
+            .get(name)
+            .and_then(Tag::as_int)
+            .map(|value| value as u16 as u32)
+            .ok_or_else(|| FormatError::corrupt(format!("schematic without {name}")))
+    };
+    let size = UVec3::new(
+        dimension("Width")?,
+        dimension("Height")?,
+        dimension("Length")?,
+    );
+
+    let mut palette = HashMap::new();
+    for (state, id) in blocks
+        .get("Palette")
+        .and_then(Tag::as_compound)
+        .ok_or_else(|| FormatError::corrupt("schematic without palette"))?
+    {
+        let id = id
+            .as_int()
+            .ok_or_else(|| FormatError::corrupt("invalid palette entry"))?;
+        palette.insert(id, translation.translate(state));
+    }
+
+    let data = blocks
+        .get("BlockData")
+        .or_else(|| blocks.get("Data"))
+        .and_then(Tag::as_byte_array)
+        .ok_or_else(|| FormatError::corrupt("schematic without block data"))?;
+    let mut bytes = data.iter().map(|byte| *byte as u8);
+    let volume = size.x as usize * size.y as usize * size.z as usize;
+    // every block takes at least one varint byte
+    if volume > data.len() {
+        return Err(FormatError::corrupt(format!(
+            "{} bytes of block data for {volume} blocks",
+            data.len()
+        )));
+    }
+    let mut voxels = Vec::with_capacity(volume);
+    for _ in 0..volume {
+        let id = read_varint(&mut bytes)?;
+        let voxel = palette
+            .get(&id)
+            .ok_or_else(|| FormatError::corrupt("block id missing from the palette"))?;
+        voxels.push(*voxel);
+    }
+
+    // Sponge orders blocks by y, then z, then x
+    Ok(Schematic::from_fn(size, |pos| {
+        voxels[((pos.y * size.z + pos.z) * size.x + pos.x) as usize]
+    }))
+}
+
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> FormatResult<i64> {
+    let mut value = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = bytes
+            .next()
+            .ok_or_else(|| FormatError::corrupt("block data ended early"))?;
+        value |= ((byte & 0x7F) as i64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(FormatError::corrupt("varint is too long"))
+}
+
+/// Reads every section of a region file into the full detail chunks they belong to. Two
+/// sections stacked on top of each other and their neighbors along x and z share a chunk. Parts
+/// of a chunk no section covers are air, and so are chunks in a compression this can't read.
+pub fn read_region(
+    data: &[u8],
+    translation: &BlockTranslation,
+) -> FormatResult<HashMap<ChunkID, Chunk>> {
+    let header = data
+        .get(..SECTOR_SIZE)
+        .ok_or_else(|| FormatError::corrupt("region without header"))?;
+
+    let mut buffers: HashMap<ChunkID, Box<DenseChunk>> = HashMap::new();
+    for location in header.chunks_exact(4) {
+        let sector = u32::from_be_bytes([0, location[0], location[1], location[2]]) as usize;
+        if sector == 0 {
+            continue;
+        }
+        let start = sector * SECTOR_SIZE;
+        let chunk_header = data
+            .get(start..start + 5)
+            .ok_or_else(|| FormatError::corrupt("chunk outside of the region"))?;
+        let len = u32::from_be_bytes(chunk_header[..4].try_into().unwrap()) as usize;
+        let compression = chunk_header[4];
+        let compressed = data
+            .get(start + 5..start + 4 + len.max(1))
+            .ok_or_else(|| FormatError::corrupt("chunk ended early"))?;
+        let nbt = match compression {
+            1 => gzip_decompress(compressed)?,
+            2 => zlib_decompress(compressed)?,
+            3 => compressed.to_vec(),
+            // newer versions added LZ4 and custom compressions, the rest of the region is fine
+            _ => {
+                tracing::warn!("skipping a region chunk with compression {compression}");
+                continue;
+            }
+        };
+
+        let (_, root) = nbt::read(&nbt)?;
+        read_chunk(&root, translation, &mut buffers)?;
+    }
+
+    Ok(buffers
+        .into_iter()
+        .map(|(id, buffer)| (id, Chunk::from_buffer(&buffer)))
+        .collect())
+}
+
+fn read_chunk(
+    root: &Tag,
+    translation: &BlockTranslation,
+    buffers: &mut HashMap<ChunkID, Box<DenseChunk>>,
+) -> FormatResult<()> {
+    // before 1.18 everything was inside of `Level`
+    let level = root.get("Level").unwrap_or(root);
+    let coord = |name| {
+        level
+            .get(name)
+            .and_then(Tag::as_int)
+            .map(|value| value as i32)
+            .ok_or_else(|| FormatError::corrupt(format!("chunk without {name}")))
+    };
+    let (chunk_x, chunk_z) = (coord("xPos")?, coord("zPos")?);
+    let Some(sections) = level
+        .get("sections")
+        .or_else(|| level.get("Sections"))
+        .and_then(Tag::as_list)
+    else {
+        return Ok(());
+    };
+
+    for section in sections {
+        let Some(y) = section.get("Y").and_then(Tag::as_int) else {
+            continue;
+        };
+        let states = section.get("block_states").unwrap_or(section);
+        let Some(palette) = states
+            .get("palette")
+            .or_else(|| states.get("Palette"))
+            .and_then(Tag::as_list)
+        else {
+            continue;
+        };
+        let palette = palette
+            .iter()
+            .map(|entry| Ok(translation.translate(&block_state(entry)?)))
+            .collect::<FormatResult<Vec<_>>>()?;
+        let data = states
+            .get("data")
+            .or_else(|| states.get("BlockStates"))
+            .and_then(Tag::as_long_array)
+            .unwrap_or_default();
+        let indices = unpack_indices(data, palette.len())?;
+
+        let origin = IVec3::new(chunk_x, y as i32, chunk_z) * SECTION_SIZE as i32;
+        let id = ChunkID::new(0, origin >> 5);
+        let offset = (origin & 31).as_uvec3();
+        let buffer = buffers
+            .entry(id)
+            .or_insert_with(|| Box::new(fill(VoxelTypes::Air as u16)));
+        for (i, index) in indices.into_iter().enumerate() {
+            // sections order blocks by y, then z, then x
+            let pos = UVec3::new(
+                (i % SECTION_SIZE) as u32,
+                (i / (SECTION_SIZE * SECTION_SIZE)) as u32,
+                (i / SECTION_SIZE % SECTION_SIZE) as u32,
+            );
+            buffer[coords_to_1d_index(offset + pos)] = *palette
+                .get(index)
+                .ok_or_else(|| FormatError::corrupt("block state missing from the palette"))?;
+        }
+    }
+    Ok(())
+}
+
+/// The state in the `name[key=value,...]` notation with sorted properties.
+fn block_state(entry: &Tag) -> FormatResult<String> {
+    let name = entry
+        .get("Name")
+        .and_then(Tag::as_str)
+        .ok_or_else(|| FormatError::corrupt("palette entry without name"))?;
+    let Some(properties) = entry.get("Properties").and_then(Tag::as_compound) else {
+        return Ok(name.to_string());
+    };
+    let mut properties: Vec<String> = properties
+        .iter()
+        .filter_map(|(key, value)| Some(format!("{key}={}", value.as_str()?)))
+        .collect();
+    properties.sort();
+    Ok(format!("{name}[{}]", properties.join(",")))
+}
+
+/// Palette indices packed into longs, at least 4 bits each and never spanning two longs. A single
+/// entry palette stores no data.
+fn unpack_indices(data: &[i64], palette_len: usize) -> FormatResult<Vec<usize>> {
+    if palette_len <= 1 {
+        return Ok(vec![0; SECTION_VOLUME]);
+    }
+    let bits = (usize::BITS - (palette_len - 1).leading_zeros()).max(4) as usize;
+    let per_long = 64 / bits;
+    if data.len() < SECTION_VOLUME.div_ceil(per_long) {
+        return Err(FormatError::corrupt("section data ended early"));
+    }
+    let mask = (1_u64 << bits) - 1;
+    Ok((0..SECTION_VOLUME)
+        .map(|i| ((data[i / per_long] as u64 >> (i % per_long * bits)) & mask) as usize)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3};
+
+    use crate::{
+        ChunkID, VoxelTypes,
+        formats::{
+            inflate::{gzip_store, zlib_store},
+            nbt::{Tag, write},
+        },
+        world::World,
+    };
+
+    use super::{BlockTranslation, SECTOR_SIZE, read_region, read_sponge_schematic};
+
+    const AIR: u16 = VoxelTypes::Air as u16;
+    const STONE: u16 = VoxelTypes::Stone as u16;
+    const SAND: u16 = VoxelTypes::Sand as u16;
+    const LAMP: u16 = VoxelTypes::Lamp as u16;
+
+    fn compound<const N: usize>(children: [(&str, Tag); N]) -> Tag {
+        Tag::Compound(
+            children
+                .into_iter()
+                .map(|(name, tag)| (name.to_string(), tag))
+                .collect(),
+        )
+    }
+
+    fn block(name: &str) -> Tag {
+        compound([("Name", Tag::String(name.into()))])
+    }
+
+    #[test]
+    fn translation_tables_fall_back_to_the_block_name() {
+        let translation = BlockTranslation::from_toml(
+            r#"
+            default = "Dirt0"
+            [blocks]
+            "minecraft:redstone_lamp[lit=true]" = "Lamp"
+            "minecraft:sand" = "Sand"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            translation.translate("minecraft:redstone_lamp[lit=true]"),
+            LAMP
+        );
+        assert_eq!(
+            translation.translate("minecraft:redstone_lamp[lit=false]"),
+            VoxelTypes::Dirt0 as u16
+        );
+        assert_eq!(translation.translate("minecraft:sand[foo=bar]"), SAND);
+        assert!(BlockTranslation::from_toml("default = \"Bedrock\"").is_err());
+    }
+
+    #[test]
+    fn reads_sponge_schematics() {
+        // 3 wide, 2 high and 2 long, with a palette id that needs two varint bytes
+        let mut data = vec![0_i8; 12];
+        data[1] = 1; // (1, 0, 0)
+        data[3 * 2 + 2] = -128; // (2, 1, 0)
+        data.insert(3 * 2 + 3, 1);
+        let schematic = compound([
+            ("Version", Tag::Int(2)),
+            ("Width", Tag::Short(3)),
+            ("Height", Tag::Short(2)),
+            ("Length", Tag::Short(2)),
+            (
+                "Palette",
+                compound([
+                    ("minecraft:air", Tag::Int(0)),
+                    ("minecraft:stone", Tag::Int(1)),
+                    ("minecraft:sand", Tag::Int(128)),
+                ]),
+            ),
+            ("BlockData", Tag::ByteArray(data)),
+        ]);
+        let file = gzip_store(&write("Schematic", &schematic));
+
+        let schematic = read_sponge_schematic(&file, &BlockTranslation::default()).unwrap();
+        assert_eq!(schematic.size(), UVec3::new(3, 2, 2));
+        assert_eq!(schematic.get(UVec3::new(1, 0, 0)), STONE);
+        assert_eq!(schematic.get(UVec3::new(2, 1, 0)), SAND);
+        assert_eq!(schematic.get(UVec3::new(0, 1, 1)), AIR);
+    }
+
+    #[test]
+    fn sponge_dimensions_have_to_fit_the_block_data() {
+        let schematic = compound([
+            ("Version", Tag::Int(2)),
+            ("Width", Tag::Short(-1)),
+            ("Height", Tag::Short(-1)),
+            ("Length", Tag::Short(-1)),
+            ("Palette", compound([("minecraft:air", Tag::Int(0))])),
+            ("BlockData", Tag::ByteArray(vec![0; 16])),
+        ]);
+        let file = gzip_store(&write("Schematic", &schematic));
+        assert!(read_sponge_schematic(&file, &BlockTranslation::default()).is_err());
+    }
+
+    #[test]
+    fn region_sections_end_up_in_chunks() {
+        // 2 bits would do, but indices take at least 4
+        let mut longs = vec![0_i64; 256];
+        longs[0] = 1 << 4; // (1, 0, 0) is stone
+        longs[255] = 2 << 60; // (15, 15, 15) is sand
+        let section = |y| {
+            compound([
+                ("Y", Tag::Byte(y)),
+                (
+                    "block_states",
+                    compound([
+                        (
+                            "palette",
+                            Tag::List(vec![
+                                block("minecraft:air"),
+                                block("minecraft:stone"),
+                                block("minecraft:sand"),
+                            ]),
+                        ),
+                        ("data", Tag::LongArray(longs.clone())),
+                    ]),
+                ),
+            ])
+        };
+        let chunk = compound([
+            ("xPos", Tag::Int(-1)),
+            ("zPos", Tag::Int(2)),
+            (
+                "sections",
+                Tag::List(vec![
+                    section(-1),
+                    compound([
+                        ("Y", Tag::Byte(0)),
+                        (
+                            "block_states",
+                            compound([("palette", Tag::List(vec![block("minecraft:glowstone")]))]),
+                        ),
+                    ]),
+                ]),
+            ),
+        ]);
+
+        let compressed = zlib_store(&write("", &chunk));
+        let mut region = vec![0; 2 * SECTOR_SIZE];
+        region[2] = 2; // the first chunk starts in the third sector
+        region[3] = 1;
+        region.extend_from_slice(&(compressed.len() as u32 + 1).to_be_bytes());
+        region.push(2);
+        region.extend_from_slice(&compressed);
+
+        let chunks = read_region(&region, &BlockTranslation::default()).unwrap();
+        assert_eq!(chunks.len(), 2);
+        let mut world = World::default();
+        for (id, chunk) in chunks {
+            world.insert_chunk(id, chunk);
+        }
+        assert!(
+            world
+                .chunk(ChunkID::new(0, IVec3::new(-1, -1, 1)))
+                .is_some()
+        );
+        assert_eq!(world.get(IVec3::new(-15, -16, 32)), Some(STONE));
+        assert_eq!(world.get(IVec3::new(-1, -1, 47)), Some(SAND));
+        assert_eq!(world.get(IVec3::new(-5, 7, 40)), Some(LAMP));
+        assert_eq!(world.get(IVec3::new(-20, 7, 40)), Some(AIR));
+    }
+
+    #[test]
+    fn chunks_in_unknown_compressions_are_skipped() {
+        let mut region = vec![0; 2 * SECTOR_SIZE];
+        region[2] = 2;
+        region[3] = 1;
+        region.extend_from_slice(&5_u32.to_be_bytes());
+        region.push(4); // LZ4
+        region.extend_from_slice(&[0; 4]);
+
+        assert!(
+            read_region(&region, &BlockTranslation::default())
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,10 @@
+//! Readers and writers for the file formats the engine exchanges data with.
+
+pub(crate) mod bytes;
+pub(crate) mod gltf;
+pub(crate) mod inflate;
+pub(crate) mod minecraft;
+pub(crate) mod nbt;
+pub(crate) mod obj;
+pub(crate) mod png;
+pub(crate) mod vox;
@@ -0,0 +1,280 @@
+//! Minecraft's Named Binary Tag format, uncompressed and big endian.
+
+use std::collections::HashMap;
+
+use crate::{
+    error::{FormatError, FormatResult},
+    formats::bytes::ByteReader,
+};
+
+/// How deep lists and compounds may nest before a file counts as corrupt.
+const MAX_DEPTH: usize = 512;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// The child of a compound.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Self::Compound(children) => children.get(name),
+            _ => None,
+        }
+    }
+
+    /// Any integer tag, widened.
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            Self::Byte(value) => Some(value as i64),
+            Self::Short(value) => Some(value as i64),
+            Self::Int(value) => Some(value as i64),
+            Self::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Self::List(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&HashMap<String, Tag>> {
+        match self {
+            Self::Compound(children) => Some(children),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte_array(&self) -> Option<&[i8]> {
+        match self {
+            Self::ByteArray(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Self::LongArray(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    #[cfg(test)]
+    fn id(&self) -> u8 {
+        match self {
+            Self::Byte(_) => 1,
+            Self::Short(_) => 2,
+            Self::Int(_) => 3,
+            Self::Long(_) => 4,
+            Self::Float(_) => 5,
+            Self::Double(_) => 6,
+            Self::ByteArray(_) => 7,
+            Self::String(_) => 8,
+            Self::List(_) => 9,
+            Self::Compound(_) => 10,
+            Self::IntArray(_) => 11,
+            Self::LongArray(_) => 12,
+        }
+    }
+}
+
+/// Reads the root tag and its name.
+pub fn read(data: &[u8]) -> FormatResult<(String, Tag)> {
+    let mut reader = ByteReader::new(data);
+    let id = reader.u8()?;
+    if id != 10 {
+        return Err(FormatError::corrupt("NBT root isn't a compound"));
+    }
+    let name = read_string(&mut reader)?;
+    Ok((name, read_payload(&mut reader, id, 0)?))
+}
+
+fn read_payload(reader: &mut ByteReader, id: u8, depth: usize) -> FormatResult<Tag> {
+    if depth > MAX_DEPTH {
+        return Err(FormatError::corrupt("NBT nests too deep"));
+    }
+    Ok(match id {
+        1 => Tag::Byte(reader.u8()? as i8),
+        2 => Tag::Short(i16::from_be_bytes(reader.array()?)),
+        3 => Tag::Int(read_i32(reader)?),
+        4 => Tag::Long(i64::from_be_bytes(reader.array()?)),
+        5 => Tag::Float(f32::from_be_bytes(reader.array()?)),
+        6 => Tag::Double(f64::from_be_bytes(reader.array()?)),
+        7 => {
+            let len = read_len(reader)?;
+            Tag::ByteArray(reader.take(len)?.iter().map(|byte| *byte as i8).collect())
+        }
+        8 => Tag::String(read_string(reader)?),
+        9 => {
+            let id = reader.u8()?;
+            let len = read_len(reader)?;
+            if id == 0 && len > 0 {
+                return Err(FormatError::corrupt("NBT list of end tags"));
+            }
+            Tag::List(
+                (0..len)
+                    .map(|_| read_payload(reader, id, depth + 1))
+                    .collect::<FormatResult<_>>()?,
+            )
+        }
+        10 => {
+            let mut children = HashMap::new();
+            loop {
+                let id = reader.u8()?;
+                if id == 0 {
+                    break;
+                }
+                let name = read_string(reader)?;
+                children.insert(name, read_payload(reader, id, depth + 1)?);
+            }
+            Tag::Compound(children)
+        }
+        11 => {
+            let len = read_len(reader)?;
+            Tag::IntArray(
+                (0..len)
+                    .map(|_| read_i32(reader))
+                    .collect::<FormatResult<_>>()?,
+            )
+        }
+        12 => {
+            let len = read_len(reader)?;
+            Tag::LongArray(
+                (0..len)
+                    .map(|_| Ok(i64::from_be_bytes(reader.array()?)))
+                    .collect::<FormatResult<_>>()?,
+            )
+        }
+        _ => return Err(FormatError::corrupt(format!("unknown NBT tag {id}"))),
+    })
+}
+
+fn read_i32(reader: &mut ByteReader) -> FormatResult<i32> {
+    Ok(i32::from_be_bytes(reader.array()?))
+}
+
+fn read_len(reader: &mut ByteReader) -> FormatResult<usize> {
+    usize::try_from(read_i32(reader)?).map_err(|_| FormatError::corrupt("negative NBT length"))
+}
+
+/// Strings are modified UTF-8, which only differs from UTF-8 for null and characters outside of
+/// the basic plane. Neither shows up in the names we read.
+fn read_string(reader: &mut ByteReader) -> FormatResult<String> {
+    let len = u16::from_be_bytes(reader.array()?) as usize;
+    Ok(String::from_utf8_lossy(reader.take(len)?).into_owned())
+}
+
+/// Writes a root compound. Used to build test files.
+#[cfg(test)]
+pub fn write(name: &str, root: &Tag) -> Vec<u8> {
+    let mut out = vec![root.id()];
+    write_string(&mut out, name);
+    write_payload(&mut out, root);
+    out
+}
+
+#[cfg(test)]
+fn write_payload(out: &mut Vec<u8>, tag: &Tag) {
+    match tag {
+        Tag::Byte(value) => out.push(*value as u8),
+        Tag::Short(value) => out.extend_from_slice(&value.to_be_bytes()),
+        Tag::Int(value) => out.extend_from_slice(&value.to_be_bytes()),
+        Tag::Long(value) => out.extend_from_slice(&value.to_be_bytes()),
+        Tag::Float(value) => out.extend_from_slice(&value.to_be_bytes()),
+        Tag::Double(value) => out.extend_from_slice(&value.to_be_bytes()),
+        Tag::ByteArray(values) => {
+            out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            out.extend(values.iter().map(|value| *value as u8));
+        }
+        Tag::String(value) => write_string(out, value),
+        Tag::List(values) => {
+            out.push(values.first().map_or(0, Tag::id));
+            out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            for value in values {
+                write_payload(out, value);
+            }
+        }
+        Tag::Compound(children) => {
+            for (name, child) in children {
+                out.push(child.id());
+                write_string(out, name);
+                write_payload(out, child);
+            }
+            out.push(0);
+        }
+        Tag::IntArray(values) => {
+            out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            for value in values {
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        Tag::LongArray(values) => {
+            out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            for value in values {
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Tag, read, write};
+
+    #[test]
+    fn tags_round_trip() {
+        let root = Tag::Compound(HashMap::from([
+            ("byte".to_string(), Tag::Byte(-3)),
+            ("name".to_string(), Tag::String("minecraft:stone".into())),
+            (
+                "list".to_string(),
+                Tag::List(vec![Tag::Short(1), Tag::Short(-2)]),
+            ),
+            ("longs".to_string(), Tag::LongArray(vec![i64::MIN, 7])),
+            (
+                "nested".to_string(),
+                Tag::Compound(HashMap::from([("x".to_string(), Tag::Double(0.5))])),
+            ),
+        ]));
+        let (name, tag) = read(&write("root", &root)).unwrap();
+        assert_eq!(name, "root");
+        assert_eq!(tag, root);
+        assert_eq!(
+            tag.get("list").unwrap().as_list().unwrap()[1].as_int(),
+            Some(-2)
+        );
+
+        let data = write("root", &root);
+        assert!(read(&data[..data.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+//! Wavefront OBJ export of meshes, to look at them in any 3D viewer. The light of each face is
+//! written as a gray vertex color, which most viewers understand.
+
+use std::io::{self, Write};
+
+use glam::Vec3;
+
+use crate::{ChunkID, mesh::MeshUpload};
+
+const NORMALS: [Vec3; 6] = [
+    Vec3::NEG_X,
+    Vec3::X,
+    Vec3::NEG_Y,
+    Vec3::Y,
+    Vec3::NEG_Z,
+    Vec3::Z,
+];
+
+/// Writes the meshes at their chunk's place in the world, one object per chunk and one material
+/// name per texture.
+pub fn write_obj(meshes: &[(ChunkID, &MeshUpload)], mut out: impl Write) -> io::Result<()> {
+    writeln!(out, "# voxine mesh export")?;
+    for uv in ["0 0", "1 0", "1 1", "0 1"] {
+        writeln!(out, "vt {uv}")?;
+    }
+    for normal in NORMALS {
+        writeln!(out, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+    }
+
+    let mut next_vertex = 1;
+    for (chunk, mesh) in meshes {
+        let ChunkID { pos, lod } = chunk;
+        writeln!(out, "o chunk_lod{lod}_{}_{}_{}", pos.x, pos.y, pos.z)?;
+
+        let mut quads = mesh.quads(*chunk);
+        quads.sort_by_key(|quad| quad.texture);
+        let mut texture = None;
+        for quad in quads {
+            if texture != Some(quad.texture) {
+                texture = Some(quad.texture);
+                writeln!(out, "usemtl texture_{}", quad.texture)?;
+            }
+            let brightness = quad.brightness();
+            for corner in quad.corners {
+                writeln!(
+                    out,
+                    "v {} {} {} {brightness} {brightness} {brightness}",
+                    corner.x, corner.y, corner.z
+                )?;
+            }
+            let normal = NORMALS
+                .iter()
+                .position(|normal| *normal == quad.normal)
+                .unwrap_or_default()
+                + 1;
+            let v = next_vertex;
+            writeln!(
+                out,
+                "f {}/1/{normal} {}/2/{normal} {}/3/{normal} {}/4/{normal}",
+                v,
+                v + 1,
+                v + 2,
+                v + 3
+            )?;
+            next_vertex += 4;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3};
+
+    use crate::{ChunkID, mesh::Mesh};
+
+    use super::write_obj;
+
+    #[test]
+    fn writes_a_face_per_instance() {
+        let mut mesh = Mesh::with_capacity(2);
+        mesh.add_px(UVec3::new(0, 0, 0), 3, 0xF0);
+        mesh.add_ny(UVec3::new(4, 0, 0), 3, 0x00);
+        mesh.add_ny(UVec3::new(5, 0, 0), 1, 0x00);
+        let mesh = mesh.bytes();
+
+        let mut out = Vec::new();
+        write_obj(
+            &[
+                (ChunkID::new(0, IVec3::ZERO), &mesh),
+                (ChunkID::new(0, IVec3::X), &mesh),
+            ],
+            &mut out,
+        )
+        .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let count = |prefix| text.lines().filter(|l| l.starts_with(prefix)).count();
+
+        assert_eq!(count("o "), 2);
+        assert_eq!(count("v "), 2 * 3 * 4);
+        assert_eq!(count("f "), 2 * 3);
+        assert_eq!(count("usemtl "), 2 * 2);
+        assert!(text.contains("v 33 0 0 1 1 1"));
+        assert!(text.contains("f 21/1/3 22/2/3 23/3/3 24/4/3"));
+    }
+}
@@ -0,0 +1,331 @@
+//! Just enough of PNG to read heightmaps and material maps: every non-interlaced color type with a
+//! bit depth of 8 or 16.
+
+use crate::{
+    error::{FormatError, FormatResult},
+    formats::inflate::zlib_decompress,
+};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// Larger images are rejected before their rows get allocated.
+const MAX_PIXELS: usize = 1 << 28;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Gray = 0,
+    Rgb = 2,
+    Indexed = 3,
+    GrayAlpha = 4,
+    Rgba = 6,
+}
+
+impl ColorType {
+    fn channels(self) -> usize {
+        match self {
+            Self::Gray | Self::Indexed => 1,
+            Self::GrayAlpha => 2,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+}
+
+/// A decoded image. Every sample is widened to 16 bits, palettes are resolved to RGB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    pub samples: Vec<u16>,
+}
+
+impl Image {
+    /// The samples of the pixel at `(x, y)`.
+    pub fn pixel(&self, x: usize, y: usize) -> &[u16] {
+        let start = (y * self.width + x) * self.channels;
+        &self.samples[start..start + self.channels]
+    }
+}
+
+pub fn decode(data: &[u8]) -> FormatResult<Image> {
+    if data.get(..8) != Some(&SIGNATURE) {
+        return Err(FormatError::corrupt("missing PNG signature"));
+    }
+
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut compressed = Vec::new();
+
+    let mut pos = 8;
+    while pos + 8 <= data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let body = data
+            .get(pos + 8..pos + 8 + len)
+            .ok_or_else(|| FormatError::corrupt("PNG chunk ended early"))?;
+        pos += 12 + len;
+
+        match kind {
+            b"IHDR" => header = Some(Header::parse(body)?),
+            b"PLTE" => palette = body,
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or_else(|| FormatError::corrupt("missing IHDR chunk"))?;
+    let raw = zlib_decompress(&compressed)?;
+    let rows = unfilter(&header, &raw)?;
+
+    let bytes_per_sample = header.bit_depth as usize / 8;
+    let samples: Vec<u16> = rows
+        .chunks_exact(bytes_per_sample)
+        .map(|sample| match sample {
+            [byte] => *byte as u16 * 257,
+            [high, low] => u16::from_be_bytes([*high, *low]),
+            _ => unreachable!(),
+        })
+        .collect();
+
+    if header.color_type != ColorType::Indexed {
+        return Ok(Image {
+            width: header.width,
+            height: header.height,
+            channels: header.color_type.channels(),
+            samples,
+        });
+    }
+
+    let mut resolved = Vec::with_capacity(samples.len() * 3);
+    for index in samples {
+        let index = (index / 257) as usize * 3;
+        let rgb = palette
+            .get(index..index + 3)
+            .ok_or_else(|| FormatError::corrupt("palette index out of range"))?;
+        resolved.extend(rgb.iter().map(|channel| *channel as u16 * 257));
+    }
+    Ok(Image {
+        width: header.width,
+        height: header.height,
+        channels: 3,
+        samples: resolved,
+    })
+}
+
+/// Encodes a 16-bit image without compression.
+#[cfg(test)]
+pub fn encode(image: &Image) -> Vec<u8> {
+    let color_type = match image.channels {
+        1 => ColorType::Gray,
+        2 => ColorType::GrayAlpha,
+        3 => ColorType::Rgb,
+        _ => ColorType::Rgba,
+    };
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(image.width as u32).to_be_bytes());
+    header.extend_from_slice(&(image.height as u32).to_be_bytes());
+    header.extend_from_slice(&[16, color_type as u8, 0, 0, 0]);
+
+    let mut raw = Vec::with_capacity(image.samples.len() * 2 + image.height);
+    for row in image.samples.chunks(image.width * image.channels) {
+        raw.push(0); // no filter
+        row.iter()
+            .for_each(|sample| raw.extend_from_slice(&sample.to_be_bytes()));
+    }
+
+    let mut out = SIGNATURE.to_vec();
+    write_chunk(&mut out, b"IHDR", &header);
+    write_chunk(&mut out, b"IDAT", &super::inflate::zlib_store(&raw));
+    write_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+#[cfg(test)]
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: ColorType,
+}
+
+impl Header {
+    fn parse(body: &[u8]) -> FormatResult<Self> {
+        if body.len() != 13 {
+            return Err(FormatError::corrupt("IHDR has the wrong size"));
+        }
+        let width = u32::from_be_bytes(body[0..4].try_into().unwrap()) as usize;
+        let height = u32::from_be_bytes(body[4..8].try_into().unwrap()) as usize;
+        let (bit_depth, color_type, interlace) = (body[8], body[9], body[12]);
+
+        let color_type = match color_type {
+            0 => ColorType::Gray,
+            2 => ColorType::Rgb,
+            3 => ColorType::Indexed,
+            4 => ColorType::GrayAlpha,
+            6 => ColorType::Rgba,
+            _ => return Err(FormatError::corrupt("invalid color type")),
+        };
+        if bit_depth != 8 && !(bit_depth == 16 && color_type != ColorType::Indexed) {
+            return Err(FormatError::unsupported(format!("bit depth {bit_depth}")));
+        }
+        if interlace != 0 {
+            return Err(FormatError::unsupported("interlacing"));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+        })
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize / 8
+    }
+}
+
+/// Reverts the per-row filters and returns the rows without their filter bytes.
+fn unfilter(header: &Header, raw: &[u8]) -> FormatResult<Vec<u8>> {
+    let bpp = header.bytes_per_pixel();
+    let too_large = || FormatError::corrupt("the image is too large");
+    if (header.width.checked_mul(header.height)).is_none_or(|pixels| pixels > MAX_PIXELS) {
+        return Err(too_large());
+    }
+    let stride = header.width.checked_mul(bpp).ok_or_else(too_large)?;
+    let filtered_len = (stride + 1)
+        .checked_mul(header.height)
+        .ok_or_else(too_large)?;
+    if raw.len() < filtered_len {
+        return Err(FormatError::corrupt("image data ended early"));
+    }
+
+    let mut out = vec![0_u8; stride.checked_mul(header.height).ok_or_else(too_large)?];
+    for y in 0..header.height {
+        let filter = raw[y * (stride + 1)];
+        let line = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (previous, current) = out.split_at_mut(y * stride);
+        let previous = previous.get(previous.len().saturating_sub(stride)..);
+        let current = &mut current[..stride];
+
+        for x in 0..stride {
+            let left = if x >= bpp { current[x - bpp] } else { 0 };
+            let up = if y > 0 { previous.unwrap()[x] } else { 0 };
+            let up_left = if y > 0 && x >= bpp {
+                previous.unwrap()[x - bpp]
+            } else {
+                0
+            };
+
+            current[x] = match filter {
+                0 => line[x],
+                1 => line[x].wrapping_add(left),
+                2 => line[x].wrapping_add(up),
+                3 => line[x].wrapping_add(((left as u16 + up as u16) / 2) as u8),
+                4 => line[x].wrapping_add(paeth(left, up, up_left)),
+                _ => return Err(FormatError::corrupt("invalid filter type")),
+            };
+        }
+    }
+    Ok(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formats::inflate::zlib_store;
+
+    use super::{Image, crc32, decode, encode};
+
+    #[test]
+    fn crc_matches_reference() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+
+    #[test]
+    fn round_trip_16_bit_gray() {
+        let image = Image {
+            width: 37,
+            height: 11,
+            channels: 1,
+            samples: (0..37 * 11).map(|i| (i * 997) as u16).collect(),
+        };
+        assert_eq!(decode(&encode(&image)).unwrap(), image);
+    }
+
+    #[test]
+    fn decodes_filtered_8_bit_rgb() {
+        // 2x2 RGB image with a `Sub` filtered and an `Up` filtered row
+        let raw = [
+            1, 10, 20, 30, 5, 5, 5, //
+            2, 1, 1, 1, 1, 1, 1,
+        ];
+        let mut data = super::SIGNATURE.to_vec();
+        super::write_chunk(&mut data, b"IHDR", &[0, 0, 0, 2, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+        super::write_chunk(&mut data, b"IDAT", &zlib_store(&raw));
+        super::write_chunk(&mut data, b"IEND", &[]);
+
+        let image = decode(&data).unwrap();
+        let expected: Vec<u16> = [10, 20, 30, 15, 25, 35, 11, 21, 31, 16, 26, 36]
+            .iter()
+            .map(|v| v * 257)
+            .collect();
+        assert_eq!(image.samples, expected);
+    }
+
+    #[test]
+    fn huge_images_are_rejected() {
+        for (width, height) in [(u32::MAX, u32::MAX), (1 << 15, 1 << 14)] {
+            let mut header = [0; 13];
+            header[0..4].copy_from_slice(&width.to_be_bytes());
+            header[4..8].copy_from_slice(&height.to_be_bytes());
+            header[8..10].copy_from_slice(&[16, 6]);
+            let mut data = super::SIGNATURE.to_vec();
+            super::write_chunk(&mut data, b"IHDR", &header);
+            super::write_chunk(&mut data, b"IDAT", &zlib_store(&[0; 64]));
+            super::write_chunk(&mut data, b"IEND", &[]);
+
+            assert!(decode(&data).is_err());
+        }
+    }
+}
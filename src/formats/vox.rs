@@ -0,0 +1,730 @@
+//! MagicaVoxel `.vox` files. Reads the models and where the scene graph places them, materials,
+//! layers and every animation frame but the first are ignored. Rotations aren't supported either,
+//! rotated models are read unrotated.
+//!
+//! MagicaVoxel is z-up, so a voxel at `(x, y, z)` in the file is at `(x, z, -y - 1)` in the world.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use glam::{IVec3, UVec3};
+
+use crate::{
+    Chunk, VoxelType, VoxelTypes,
+    chunk::{CHUNK_SIZE, coords_to_1d_index},
+    error::{FormatError, FormatResult},
+    formats::bytes::ByteReader,
+    schematic::{MAX_SCHEMATIC_VOLUME, Schematic},
+    voxel::{self, fill},
+    world::World,
+};
+
+const VERSION: u32 = 150;
+
+/// The largest model MagicaVoxel can open, along every axis.
+pub const MAX_MODEL_SIZE: u32 = 256;
+
+const AIR: VoxelType = VoxelTypes::Air as u16;
+
+/// A model in file coordinates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxModel {
+    /// The lowest corner of the model in the scene.
+    pub offset: IVec3,
+    pub size: UVec3,
+    /// Position and color index of every non-empty voxel.
+    pub voxels: Vec<([u8; 3], u8)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    /// The color of each index. Index `0` means empty.
+    pub palette: Box<[[u8; 4]; 256]>,
+}
+
+/// Which voxel type each color index stands for.
+#[derive(Clone, Debug)]
+pub struct VoxMapping {
+    voxels: [VoxelType; 256],
+    indices: HashMap<VoxelType, u8>,
+    colors: Box<[[u8; 4]; 256]>,
+}
+
+impl VoxMapping {
+    /// Maps every color index to air.
+    pub fn empty() -> Self {
+        Self {
+            voxels: [AIR; 256],
+            indices: HashMap::new(),
+            colors: Box::new([[0; 4]; 256]),
+        }
+    }
+
+    /// Imports the color index as `voxel`. Exports `voxel` as this index with `color`, unless an
+    /// earlier index was mapped to it already.
+    pub fn map(&mut self, index: u8, voxel: VoxelType, color: [u8; 4]) -> &mut Self {
+        if index == 0 {
+            return self;
+        }
+        self.voxels[index as usize] = voxel;
+        if voxel != AIR && !self.indices.contains_key(&voxel) {
+            self.indices.insert(voxel, index);
+            self.colors[index as usize] = color;
+        }
+        self
+    }
+
+    pub fn voxel(&self, index: u8) -> VoxelType {
+        self.voxels[index as usize]
+    }
+
+    /// The color index a voxel gets exported as. Fluids lose their level. Types without an index
+    /// are left empty.
+    pub fn index(&self, voxel: VoxelType) -> Option<u8> {
+        self.indices.get(&voxel::kind(voxel)).copied()
+    }
+}
+
+impl Default for VoxMapping {
+    /// One index per voxel type, in the order of [`VoxelTypes`].
+    fn default() -> Self {
+        let mut mapping = Self::empty();
+        for (index, voxel, [r, g, b]) in [
+            (1, VoxelTypes::CrackedStone, [110, 110, 110]),
+            (2, VoxelTypes::Stone, [128, 128, 128]),
+            (3, VoxelTypes::Dirt0, [134, 96, 67]),
+            (4, VoxelTypes::Dirt1, [120, 85, 60]),
+            (5, VoxelTypes::Lamp, [255, 220, 120]),
+            (6, VoxelTypes::Water, [50, 90, 220]),
+            (7, VoxelTypes::Lava, [230, 90, 20]),
+            (8, VoxelTypes::Grass, [90, 160, 60]),
+            (9, VoxelTypes::Sand, [220, 200, 140]),
+            (10, VoxelTypes::Gravel, [140, 130, 125]),
+        ] {
+            mapping.map(index, voxel as u16, [r, g, b, 255]);
+        }
+        mapping
+    }
+}
+
+impl VoxModel {
+    /// The size in world coordinates.
+    pub fn world_size(&self) -> UVec3 {
+        UVec3::new(self.size.x, self.size.z, self.size.y)
+    }
+
+    /// The lowest corner in world coordinates, or an error if it doesn't fit into an [`IVec3`].
+    pub fn world_offset(&self) -> FormatResult<IVec3> {
+        let z = i32::try_from(-(self.offset.y as i64) - self.size.y as i64)
+            .map_err(|_| FormatError::corrupt("model offset out of range"))?;
+        Ok(IVec3::new(self.offset.x, self.offset.z, z))
+    }
+
+    /// The voxels relative to [`VoxModel::world_offset`].
+    fn world_voxels<'a>(
+        &'a self,
+        mapping: &'a VoxMapping,
+    ) -> impl Iterator<Item = (UVec3, VoxelType)> + 'a {
+        self.voxels.iter().map(|([x, y, z], index)| {
+            let pos = UVec3::new(*x as u32, *z as u32, self.size.y - 1 - *y as u32);
+            (pos, mapping.voxel(*index))
+        })
+    }
+
+    /// Turns a model that fits into a chunk into one, with the model at the chunk's origin.
+    pub fn to_chunk(&self, mapping: &VoxMapping) -> FormatResult<Chunk> {
+        if self.world_size().max_element() > CHUNK_SIZE as u32 {
+            return Err(FormatError::unsupported(format!(
+                "a {} model doesn't fit into a chunk",
+                self.size
+            )));
+        }
+        let mut buffer = Box::new(fill(AIR));
+        for (pos, voxel) in self.world_voxels(mapping) {
+            buffer[coords_to_1d_index(pos)] = voxel;
+        }
+        Ok(Chunk::from_buffer(&buffer))
+    }
+}
+
+impl VoxFile {
+    /// All models merged into one schematic, starting at their lowest corner. Returns `None` for
+    /// files without models, and an error when the models spread over more than
+    /// [`MAX_SCHEMATIC_VOLUME`] voxels.
+    pub fn to_schematic(&self, mapping: &VoxMapping) -> FormatResult<Option<Schematic>> {
+        if self.models.is_empty() {
+            return Ok(None);
+        }
+        // offsets come straight from the file, so the extent may not fit into an i32
+        let (mut min, mut max) = ([i64::MAX; 3], [i64::MIN; 3]);
+        for model in &self.models {
+            let (offset, size) = (model.world_offset()?, model.world_size());
+            for axis in 0..3 {
+                min[axis] = min[axis].min(offset[axis] as i64);
+                max[axis] = max[axis].max(offset[axis] as i64 + size[axis] as i64);
+            }
+        }
+        let extent: [usize; 3] = std::array::from_fn(|axis| (max[axis] - min[axis]) as usize);
+        let volume = extent
+            .iter()
+            .try_fold(1_usize, |volume, side| volume.checked_mul(*side))
+            .filter(|volume| *volume <= MAX_SCHEMATIC_VOLUME && *volume > 0)
+            .ok_or_else(|| {
+                FormatError::unsupported(format!(
+                    "models spread over {}x{}x{} voxels",
+                    extent[0], extent[1], extent[2]
+                ))
+            })?;
+        let index = |pos: [usize; 3]| (pos[0] * extent[1] + pos[1]) * extent[2] + pos[2];
+
+        let mut voxels = vec![AIR; volume];
+        for model in &self.models {
+            let offset = model.world_offset()?;
+            let offset: [usize; 3] =
+                std::array::from_fn(|axis| (offset[axis] as i64 - min[axis]) as usize);
+            for (pos, voxel) in model.world_voxels(mapping) {
+                voxels[index(std::array::from_fn(|axis| {
+                    offset[axis] + pos[axis] as usize
+                }))] = voxel;
+            }
+        }
+        // every side is at most the volume, so it fits into a u32
+        let size = UVec3::from_array(extent.map(|side| side as u32));
+        Ok(Some(Schematic::from_fn(size, |pos| {
+            voxels[index(pos.to_array().map(|axis| axis as usize))]
+        })))
+    }
+
+    /// Exports the voxels between `min` and `max` inclusive, split into models of at most
+    /// [`MAX_MODEL_SIZE`] voxels per axis. Voxels without a color index and the ones that aren't
+    /// loaded are left empty. Regions of more than [`MAX_SCHEMATIC_VOLUME`] voxels are refused.
+    pub fn export(
+        world: &World,
+        min: IVec3,
+        max: IVec3,
+        mapping: &VoxMapping,
+    ) -> FormatResult<Self> {
+        let (min, max) = (min.min(max), min.max(max));
+        let extent: [usize; 3] =
+            std::array::from_fn(|axis| (max[axis] as i64 - min[axis] as i64 + 1) as usize);
+        if (extent.iter())
+            .try_fold(1_usize, |volume, side| volume.checked_mul(*side))
+            .is_none_or(|volume| volume > MAX_SCHEMATIC_VOLUME)
+        {
+            return Err(FormatError::unsupported(format!(
+                "exporting {}x{}x{} voxels",
+                extent[0], extent[1], extent[2]
+            )));
+        }
+        let steps = |axis: usize| (min[axis]..=max[axis]).step_by(MAX_MODEL_SIZE as usize);
+
+        let mut models = Vec::new();
+        for x in steps(0) {
+            for y in steps(1) {
+                for z in steps(2) {
+                    let start = IVec3::new(x, y, z);
+                    let end = start
+                        .to_array()
+                        .map(|a| a.saturating_add(MAX_MODEL_SIZE as i32 - 1));
+                    let end = IVec3::from_array(end).min(max);
+                    models.push(export_model(world, start, end, mapping));
+                }
+            }
+        }
+        Ok(Self {
+            models,
+            palette: mapping.colors.clone(),
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> FormatResult<Self> {
+        decode(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> FormatResult<()> {
+        Ok(fs::write(path, encode(self))?)
+    }
+}
+
+fn export_model(world: &World, min: IVec3, max: IVec3, mapping: &VoxMapping) -> VoxModel {
+    let size = (max - min + 1).as_uvec3();
+    let mut voxels = Vec::new();
+    for x in 0..size.x {
+        for y in 0..size.y {
+            for z in 0..size.z {
+                let Some(index) = world
+                    .get(min + UVec3::new(x, y, z).as_ivec3())
+                    .and_then(|voxel| mapping.index(voxel))
+                else {
+                    continue;
+                };
+                voxels.push(([x as u8, (size.z - 1 - z) as u8, y as u8], index));
+            }
+        }
+    }
+    VoxModel {
+        // `-min.z - size.z`, which overflows for `min.z == i32::MIN`
+        offset: IVec3::new(min.x, -1 - max.z, min.y),
+        size: UVec3::new(size.x, size.z, size.y),
+        voxels,
+    }
+}
+
+enum Node {
+    Transform { child: i32, translation: IVec3 },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<i32> },
+}
+
+pub fn decode(data: &[u8]) -> FormatResult<VoxFile> {
+    let mut reader = ByteReader::new(data);
+    if reader.take(4)? != b"VOX " {
+        return Err(FormatError::corrupt("missing VOX header"));
+    }
+    let _version = reader.u32()?;
+    let (id, _, children) = read_chunk(&mut reader)?;
+    if id != *b"MAIN" {
+        return Err(FormatError::corrupt("missing MAIN chunk"));
+    }
+
+    let mut models = Vec::new();
+    let mut size = None;
+    let mut nodes = HashMap::new();
+    let mut palette = Box::new([[0; 4]; 256]);
+
+    let mut reader = ByteReader::new(children);
+    while !reader.is_empty() {
+        let (id, content, _) = read_chunk(&mut reader)?;
+        let mut content = ByteReader::new(content);
+        match &id {
+            b"SIZE" => {
+                size = Some(UVec3::new(content.u32()?, content.u32()?, content.u32()?));
+            }
+            b"XYZI" => {
+                let size = size
+                    .take()
+                    .ok_or_else(|| FormatError::corrupt("XYZI chunk without SIZE"))?;
+                if size.max_element() > MAX_MODEL_SIZE {
+                    return Err(FormatError::corrupt("model is too large"));
+                }
+                let count = content.u32()? as usize;
+                let voxels = (0..count)
+                    .map(|_| {
+                        let [x, y, z, index] = content.array()?;
+                        if UVec3::new(x as u32, y as u32, z as u32).cmpge(size).any() {
+                            return Err(FormatError::corrupt("voxel outside of its model"));
+                        }
+                        Ok(([x, y, z], index))
+                    })
+                    .collect::<FormatResult<_>>()?;
+                models.push(VoxModel {
+                    offset: IVec3::ZERO,
+                    size,
+                    voxels,
+                });
+            }
+            b"RGBA" => {
+                for index in 1..256 {
+                    palette[index] = content.array()?;
+                }
+            }
+            b"nTRN" => {
+                let id = content.i32()?;
+                read_dict(&mut content)?;
+                let child = content.i32()?;
+                let _reserved = content.i32()?;
+                let _layer = content.i32()?;
+                let frames = content.u32()?;
+                let mut translation = IVec3::ZERO;
+                for frame in 0..frames {
+                    let attributes = read_dict(&mut content)?;
+                    if frame == 0
+                        && let Some(t) = attributes.get("_t")
+                    {
+                        translation = parse_translation(t)?;
+                    }
+                }
+                nodes.insert(id, Node::Transform { child, translation });
+            }
+            b"nGRP" => {
+                let id = content.i32()?;
+                read_dict(&mut content)?;
+                let count = content.u32()?;
+                let children = (0..count)
+                    .map(|_| content.i32())
+                    .collect::<FormatResult<_>>()?;
+                nodes.insert(id, Node::Group { children });
+            }
+            b"nSHP" => {
+                let id = content.i32()?;
+                read_dict(&mut content)?;
+                let count = content.u32()?;
+                let mut shape_models = Vec::new();
+                for _ in 0..count {
+                    shape_models.push(content.i32()?);
+                    read_dict(&mut content)?;
+                }
+                nodes.insert(
+                    id,
+                    Node::Shape {
+                        models: shape_models,
+                    },
+                );
+            }
+            _ => {}
+        }
+    }
+
+    if nodes.is_empty() {
+        return Ok(VoxFile { models, palette });
+    }
+
+    // models referenced by several shapes get copied, unreferenced ones dropped
+    let mut placed = Vec::new();
+    place(&nodes, &models, 0, IVec3::ZERO, 0, &mut placed)?;
+    Ok(VoxFile {
+        models: placed,
+        palette,
+    })
+}
+
+fn place(
+    nodes: &HashMap<i32, Node>,
+    models: &[VoxModel],
+    id: i32,
+    translation: IVec3,
+    depth: usize,
+    placed: &mut Vec<VoxModel>,
+) -> FormatResult<()> {
+    if depth > 64 {
+        return Err(FormatError::corrupt("scene graph is too deep"));
+    }
+    match nodes.get(&id) {
+        Some(Node::Transform {
+            child,
+            translation: t,
+        }) => {
+            let translation = checked_add(translation, *t)?;
+            place(nodes, models, *child, translation, depth + 1, placed)?
+        }
+        Some(Node::Group { children }) => {
+            for child in children {
+                place(nodes, models, *child, translation, depth + 1, placed)?;
+            }
+        }
+        Some(Node::Shape { models: ids }) => {
+            for id in ids {
+                let mut model = usize::try_from(*id)
+                    .ok()
+                    .and_then(|id| models.get(id))
+                    .ok_or_else(|| FormatError::corrupt("shape references a missing model"))?
+                    .clone();
+                // translations point at the center of the model
+                model.offset = checked_add(translation, -(model.size / 2).as_ivec3())?;
+                placed.push(model);
+            }
+        }
+        None => {
+            return Err(FormatError::corrupt(
+                "scene graph references a missing node",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Adds up translations from the file.
+fn checked_add(a: IVec3, b: IVec3) -> FormatResult<IVec3> {
+    match [
+        a.x.checked_add(b.x),
+        a.y.checked_add(b.y),
+        a.z.checked_add(b.z),
+    ] {
+        [Some(x), Some(y), Some(z)] => Ok(IVec3::new(x, y, z)),
+        _ => Err(FormatError::corrupt("translation out of range")),
+    }
+}
+
+/// Reads a chunk's id, content and children.
+fn read_chunk<'a>(reader: &mut ByteReader<'a>) -> FormatResult<([u8; 4], &'a [u8], &'a [u8])> {
+    let id = reader.array()?;
+    let content_len = reader.u32()? as usize;
+    let children_len = reader.u32()? as usize;
+    Ok((id, reader.take(content_len)?, reader.take(children_len)?))
+}
+
+fn read_string(reader: &mut ByteReader) -> FormatResult<String> {
+    let len = reader.u32()? as usize;
+    Ok(String::from_utf8_lossy(reader.take(len)?).into_owned())
+}
+
+fn read_dict(reader: &mut ByteReader) -> FormatResult<HashMap<String, String>> {
+    let count = reader.u32()?;
+    (0..count)
+        .map(|_| Ok((read_string(reader)?, read_string(reader)?)))
+        .collect()
+}
+
+fn parse_translation(text: &str) -> FormatResult<IVec3> {
+    let axes: Vec<i32> = text
+        .split_whitespace()
+        .map(|axis| axis.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| FormatError::corrupt("invalid translation"))?;
+    match axes[..] {
+        [x, y, z] => Ok(IVec3::new(x, y, z)),
+        _ => Err(FormatError::corrupt("invalid translation")),
+    }
+}
+
+/// Writes every model with a transform node holding its position, so MagicaVoxel shows them
+/// where they are in the world.
+pub fn encode(file: &VoxFile) -> Vec<u8> {
+    let mut children = Vec::new();
+    for model in &file.models {
+        let mut size = Vec::new();
+        for axis in model.size.to_array() {
+            size.extend_from_slice(&axis.to_le_bytes());
+        }
+        write_chunk(&mut children, b"SIZE", &size);
+
+        let mut xyzi = (model.voxels.len() as u32).to_le_bytes().to_vec();
+        for ([x, y, z], index) in &model.voxels {
+            xyzi.extend_from_slice(&[*x, *y, *z, *index]);
+        }
+        write_chunk(&mut children, b"XYZI", &xyzi);
+    }
+
+    // root transform -> group -> a transform and a shape per model
+    let group_children: Vec<i32> = (0..file.models.len() as i32).map(|i| 2 + 2 * i).collect();
+    write_chunk(&mut children, b"nTRN", &transform_node(0, 1, None));
+    let mut group = node_header(1);
+    group.extend_from_slice(&(group_children.len() as u32).to_le_bytes());
+    for child in &group_children {
+        group.extend_from_slice(&child.to_le_bytes());
+    }
+    write_chunk(&mut children, b"nGRP", &group);
+    for (model_id, model) in file.models.iter().enumerate() {
+        let id = group_children[model_id];
+        let center = model.offset + (model.size / 2).as_ivec3();
+        write_chunk(
+            &mut children,
+            b"nTRN",
+            &transform_node(id, id + 1, Some(center)),
+        );
+        let mut shape = node_header(id + 1);
+        shape.extend_from_slice(&1_u32.to_le_bytes());
+        shape.extend_from_slice(&(model_id as i32).to_le_bytes());
+        shape.extend_from_slice(&0_u32.to_le_bytes());
+        write_chunk(&mut children, b"nSHP", &shape);
+    }
+
+    let rgba: Vec<u8> = (1..=256)
+        .flat_map(|index| file.palette.get(index).copied().unwrap_or([0; 4]))
+        .collect();
+    write_chunk(&mut children, b"RGBA", &rgba);
+
+    let mut out = b"VOX ".to_vec();
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(b"MAIN");
+    out.extend_from_slice(&0_u32.to_le_bytes());
+    out.extend_from_slice(&(children.len() as u32).to_le_bytes());
+    out.extend_from_slice(&children);
+    out
+}
+
+/// A node id and an empty attribute dictionary.
+fn node_header(id: i32) -> Vec<u8> {
+    let mut out = id.to_le_bytes().to_vec();
+    out.extend_from_slice(&0_u32.to_le_bytes());
+    out
+}
+
+fn transform_node(id: i32, child: i32, translation: Option<IVec3>) -> Vec<u8> {
+    let mut out = node_header(id);
+    out.extend_from_slice(&child.to_le_bytes());
+    out.extend_from_slice(&(-1_i32).to_le_bytes());
+    out.extend_from_slice(&(-1_i32).to_le_bytes());
+    out.extend_from_slice(&1_u32.to_le_bytes());
+    match translation {
+        Some(t) => {
+            out.extend_from_slice(&1_u32.to_le_bytes());
+            write_string(&mut out, "_t");
+            write_string(&mut out, &format!("{} {} {}", t.x, t.y, t.z));
+        }
+        None => out.extend_from_slice(&0_u32.to_le_bytes()),
+    }
+    out
+}
+
+fn write_string(out: &mut Vec<u8>, string: &str) {
+    out.extend_from_slice(&(string.len() as u32).to_le_bytes());
+    out.extend_from_slice(string.as_bytes());
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    out.extend_from_slice(&0_u32.to_le_bytes());
+    out.extend_from_slice(content);
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3};
+
+    use crate::{Chunk, ChunkID, VoxelTypes, voxel::fill, world::World};
+
+    use super::{VoxFile, VoxMapping, VoxModel, decode, encode};
+
+    const AIR: u16 = VoxelTypes::Air as u16;
+    const STONE: u16 = VoxelTypes::Stone as u16;
+    const SAND: u16 = VoxelTypes::Sand as u16;
+
+    /// A minimal file as older MagicaVoxel versions write it: one model, no scene graph.
+    fn fixture() -> Vec<u8> {
+        let mut children = Vec::new();
+        super::write_chunk(
+            &mut children,
+            b"SIZE",
+            &[2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0],
+        );
+        super::write_chunk(
+            &mut children,
+            b"XYZI",
+            &[2, 0, 0, 0, 0, 0, 0, 2, 1, 2, 3, 9],
+        );
+        let mut data = b"VOX ".to_vec();
+        data.extend_from_slice(&150_u32.to_le_bytes());
+        data.extend_from_slice(b"MAIN");
+        data.extend_from_slice(&0_u32.to_le_bytes());
+        data.extend_from_slice(&(children.len() as u32).to_le_bytes());
+        data.extend_from_slice(&children);
+        data
+    }
+
+    #[test]
+    fn reads_models_into_chunks() {
+        let file = decode(&fixture()).unwrap();
+        assert_eq!(file.models.len(), 1);
+        let chunk = file.models[0].to_chunk(&VoxMapping::default()).unwrap();
+
+        let mut world = World::default();
+        world.insert_chunk(ChunkID::new(0, IVec3::ZERO), chunk);
+        // z-up to y-up: (x, y, z) in the file is (x, z, size.y - 1 - y) in the model
+        assert_eq!(world.get(IVec3::new(0, 0, 2)), Some(STONE));
+        assert_eq!(world.get(IVec3::new(1, 3, 0)), Some(SAND));
+        assert_eq!(world.get(IVec3::new(1, 1, 1)), Some(AIR));
+
+        assert!(decode(b"VOX \x96\0\0\0").is_err());
+    }
+
+    #[test]
+    fn exported_regions_round_trip_through_files() {
+        let mut world = World::default();
+        for x in -1..9 {
+            world.insert_chunk(
+                ChunkID::new(0, IVec3::new(x, 0, 0)),
+                Chunk::from_buffer(&fill(AIR)),
+            );
+        }
+        let (min, max) = (IVec3::new(-10, 2, 3), IVec3::new(270, 4, 8));
+        for x in min.x..=max.x {
+            world.set(IVec3::new(x, 3, 4 + x.rem_euclid(4)), STONE);
+        }
+        world.set(max, SAND);
+        world.set(min, SAND);
+
+        let path = std::env::temp_dir().join(format!("voxine-{}.vox", std::process::id()));
+        let mapping = VoxMapping::default();
+        let file = VoxFile::export(&world, min, max, &mapping).unwrap();
+        assert_eq!(file.models.len(), 2);
+        file.save(&path).unwrap();
+        let loaded = VoxFile::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, file);
+        assert_eq!(decode(&encode(&loaded)).unwrap(), file);
+
+        let schematic = loaded.to_schematic(&mapping).unwrap().unwrap();
+        assert_eq!(schematic.size(), (max - min + 1).as_uvec3());
+        let mut copy = World::default();
+        for x in -1..9 {
+            copy.insert_chunk(
+                ChunkID::new(0, IVec3::new(x, 0, 0)),
+                Chunk::from_buffer(&fill(AIR)),
+            );
+        }
+        schematic.paste(&mut copy, min, false);
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let pos = IVec3::new(x, y, z);
+                    assert_eq!(copy.get(pos), world.get(pos), "{pos}");
+                }
+            }
+        }
+        assert_eq!(schematic.get(UVec3::ZERO), SAND);
+    }
+
+    #[test]
+    fn schematics_of_scattered_models_are_refused() {
+        let mut file = decode(&fixture()).unwrap();
+        let mapping = VoxMapping::default();
+        let model = |offset| VoxModel {
+            offset,
+            size: UVec3::splat(1),
+            voxels: vec![([0; 3], 1)],
+        };
+        file.models = vec![
+            model(IVec3::splat(-i32::MAX)),
+            model(IVec3::splat(i32::MAX)),
+        ];
+        assert!(file.to_schematic(&mapping).is_err());
+
+        file.models.clear();
+        assert!(file.to_schematic(&mapping).unwrap().is_none());
+    }
+
+    #[test]
+    fn translations_out_of_range_are_corrupt() {
+        // two nested transforms moving the model along x
+        let translated = |translations: [i32; 2]| {
+            let mut data = fixture();
+            let mut children = Vec::new();
+            let [outer, inner] = translations.map(|x| Some(IVec3::new(x, 0, 0)));
+            super::write_chunk(&mut children, b"nTRN", &super::transform_node(0, 1, outer));
+            super::write_chunk(&mut children, b"nTRN", &super::transform_node(1, 2, inner));
+            let mut shape = super::node_header(2);
+            shape.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            super::write_chunk(&mut children, b"nSHP", &shape);
+            data.extend_from_slice(&children);
+            let len = data.len() as u32 - 20;
+            data[16..20].copy_from_slice(&len.to_le_bytes());
+            decode(&data)
+        };
+
+        let file = translated([i32::MAX - 1, 1]).unwrap();
+        assert_eq!(file.models[0].offset, IVec3::new(i32::MAX - 1, -1, -2));
+        assert!(translated([i32::MAX, 1]).is_err());
+        assert!(translated([i32::MIN, 0]).is_err());
+    }
+
+    #[test]
+    fn exports_at_the_edge_of_the_world() {
+        let world = World::default();
+        let mapping = VoxMapping::default();
+        let (min, max) = (
+            IVec3::new(i32::MAX - 300, i32::MIN, i32::MAX - 1),
+            IVec3::splat(i32::MAX),
+        );
+        let file = VoxFile::export(&world, min, IVec3::new(max.x, min.y + 1, max.z), &mapping);
+        let file = file.unwrap();
+        assert_eq!(file.models.len(), 2);
+        let offsets: Vec<IVec3> = (file.models.iter())
+            .map(|model| model.world_offset().unwrap())
+            .collect();
+        assert_eq!(offsets, [min, IVec3::new(i32::MAX - 44, min.y, min.z)]);
+
+        assert!(VoxFile::export(&world, IVec3::splat(i32::MIN), max, &mapping).is_err());
+    }
+}
@@ -43,15 +43,8 @@ impl FrustumAllocations {
 }
 
 impl Frustum {
-    pub fn flood_fill<'a>(
-        self,
-        buffers: &'a mut FrustumAllocations,
-        ready_meshes: &HashMap<ChunkID, impl Any>,
-    ) -> &'a [ChunkID] {
-        if self.max_chunks == 0 {
-            return &[];
-        }
-
+    /// Whether any part of the chunk is inside the view.
+    pub fn contains(&self, chunk: ChunkID) -> bool {
         let forward = if self.direction.length_squared() > 0.0 {
             self.direction.normalize()
         } else {
@@ -68,34 +61,41 @@ impl Frustum {
         let tan_half_fov_x = tan_half_fov * self.aspect_ratio;
         let max_distance = self.max_distance.max(0.0);
 
-        let in_frustum = |c: ChunkID| -> bool {
-            let size = (1 << c.lod) as f32;
-            let center = c.total_pos().as_vec3() + Vec3::splat(size * 0.5);
-            let delta = center - self.cam_pos;
-            let half_extent = size * 0.5;
-
-            let outside_plane = |normal: Vec3, offset: f32| {
-                let signed_center = delta.dot(normal) + offset;
-                let projected_radius =
-                    half_extent * (normal.x.abs() + normal.y.abs() + normal.z.abs());
-                (signed_center - projected_radius) > 0.0
-            };
-
-            let near_normal = -forward;
-            let far_normal = forward;
-            let left_normal = -right - forward * tan_half_fov_x;
-            let right_normal = right - forward * tan_half_fov_x;
-            let bottom_normal = -up - forward * tan_half_fov;
-            let top_normal = up - forward * tan_half_fov;
-
-            !outside_plane(near_normal, 0.0)
-                && !outside_plane(far_normal, -max_distance)
-                && !outside_plane(left_normal, 0.0)
-                && !outside_plane(right_normal, 0.0)
-                && !outside_plane(bottom_normal, 0.0)
-                && !outside_plane(top_normal, 0.0)
+        let size = (1 << chunk.lod) as f32;
+        let center = chunk.total_pos().as_vec3() + Vec3::splat(size * 0.5);
+        let delta = center - self.cam_pos;
+        let half_extent = size * 0.5;
+
+        let outside_plane = |normal: Vec3, offset: f32| {
+            let signed_center = delta.dot(normal) + offset;
+            let projected_radius = half_extent * (normal.x.abs() + normal.y.abs() + normal.z.abs());
+            (signed_center - projected_radius) > 0.0
         };
 
+        let near_normal = -forward;
+        let far_normal = forward;
+        let left_normal = -right - forward * tan_half_fov_x;
+        let right_normal = right - forward * tan_half_fov_x;
+        let bottom_normal = -up - forward * tan_half_fov;
+        let top_normal = up - forward * tan_half_fov;
+
+        !outside_plane(near_normal, 0.0)
+            && !outside_plane(far_normal, -max_distance)
+            && !outside_plane(left_normal, 0.0)
+            && !outside_plane(right_normal, 0.0)
+            && !outside_plane(bottom_normal, 0.0)
+            && !outside_plane(top_normal, 0.0)
+    }
+
+    pub fn flood_fill<'a>(
+        self,
+        buffers: &'a mut FrustumAllocations,
+        ready_meshes: &HashMap<ChunkID, impl Any>,
+    ) -> &'a [ChunkID] {
+        if self.max_chunks == 0 {
+            return &[];
+        }
+
         buffers.already_queued.clear();
         buffers.candidates.clear();
         buffers.next_lod_candidates.clear();
@@ -106,7 +106,7 @@ impl Frustum {
         buffers.already_queued.insert(base_chunk);
 
         while let Some(chunk) = buffers.candidates.pop_front() {
-            if in_frustum(chunk) {
+            if self.contains(chunk) {
                 buffers.chunks.push(chunk);
                 if buffers.chunks.len() >= self.max_chunks {
                     break;
@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+
+use glam::IVec3;
+
+use crate::{
+    VoxelType,
+    world::{VoxelEdit, World},
+};
+
+/// The edits that get undone and redone together.
+#[derive(Clone, Debug, Default)]
+pub struct Transaction {
+    pub edits: Vec<VoxelEdit>,
+}
+
+impl Transaction {
+    fn memory_usage(&self) -> usize {
+        self.edits.len() * size_of::<VoxelEdit>()
+    }
+}
+
+/// The undo and redo history of the edits made through it.
+///
+/// Edits between [`Journal::begin`] and [`Journal::commit`] form one transaction, every other
+/// edit is a transaction of its own. Once the history takes more than `memory_cap` bytes the
+/// oldest transactions get dropped.
+#[derive(Debug)]
+pub struct Journal {
+    undo: VecDeque<Transaction>,
+    redo: Vec<Transaction>,
+    open: Option<Transaction>,
+
+    memory_usage: usize,
+    memory_cap: usize,
+}
+
+impl Journal {
+    pub fn new(memory_cap: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: None,
+            memory_usage: 0,
+            memory_cap,
+        }
+    }
+
+    /// Starts a transaction. Starting one while another is open continues the open one.
+    pub fn begin(&mut self) {
+        self.open.get_or_insert_default();
+    }
+
+    /// Closes the open transaction, so it can be undone.
+    pub fn commit(&mut self) {
+        if let Some(transaction) = self.open.take() {
+            self.push(transaction);
+        }
+    }
+
+    /// Sets the voxel and records the edit. Returns the previous voxel or `None` if the voxel
+    /// isn't loaded, in which case nothing gets recorded.
+    pub fn set(&mut self, world: &mut World, pos: IVec3, voxel: VoxelType) -> Option<VoxelType> {
+        let old = world.set(pos, voxel)?;
+        if old != voxel {
+            self.record(VoxelEdit {
+                pos,
+                old,
+                new: voxel,
+            });
+        }
+        Some(old)
+    }
+
+    /// Records an edit that was made to the world directly.
+    pub fn record(&mut self, edit: VoxelEdit) {
+        match &mut self.open {
+            Some(transaction) => transaction.edits.push(edit),
+            None => self.push(Transaction { edits: vec![edit] }),
+        }
+    }
+
+    /// Records edits that belong together, e.g. the ones of a brush stroke.
+    pub fn record_all(&mut self, edits: Vec<VoxelEdit>) {
+        match &mut self.open {
+            Some(transaction) => transaction.edits.extend(edits),
+            None => self.push(Transaction { edits }),
+        }
+    }
+
+    fn push(&mut self, transaction: Transaction) {
+        if transaction.edits.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.memory_usage += transaction.memory_usage();
+        self.undo.push_back(transaction);
+
+        while self.memory_usage > self.memory_cap
+            && let Some(oldest) = self.undo.pop_front()
+        {
+            self.memory_usage -= oldest.memory_usage();
+        }
+    }
+
+    /// Reverts the last transaction. Commits the open transaction first. Returns whether there
+    /// was anything to undo.
+    pub fn undo(&mut self, world: &mut World) -> bool {
+        self.commit();
+        let Some(transaction) = self.undo.pop_back() else {
+            return false;
+        };
+        world.set_many(
+            transaction
+                .edits
+                .iter()
+                .rev()
+                .map(|edit| (edit.pos, edit.old)),
+        );
+        self.memory_usage -= transaction.memory_usage();
+        self.redo.push(transaction);
+        true
+    }
+
+    /// Applies the last undone transaction again. Returns whether there was anything to redo.
+    pub fn redo(&mut self, world: &mut World) -> bool {
+        let Some(transaction) = self.redo.pop() else {
+            return false;
+        };
+        world.set_many(transaction.edits.iter().map(|edit| (edit.pos, edit.new)));
+        self.memory_usage += transaction.memory_usage();
+        self.undo.push_back(transaction);
+        true
+    }
+
+    /// The bytes taken by the transactions that can be undone.
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::{
+        Chunk, ChunkID, VoxelTypes,
+        voxel::fill,
+        world::{VoxelEdit, World},
+    };
+
+    use super::Journal;
+
+    const AIR: u16 = VoxelTypes::Air as u16;
+    const STONE: u16 = VoxelTypes::Stone as u16;
+    const DIRT: u16 = VoxelTypes::Dirt0 as u16;
+
+    fn world() -> World {
+        let mut world = World::default();
+        world.insert_chunk(ChunkID::new(0, IVec3::ZERO), Chunk::from_buffer(&fill(AIR)));
+        world
+    }
+
+    #[test]
+    fn transactions_are_undone_and_redone_as_a_whole() {
+        let mut world = world();
+        let mut journal = Journal::new(usize::MAX);
+
+        journal.set(&mut world, IVec3::new(1, 1, 1), STONE);
+        journal.begin();
+        journal.set(&mut world, IVec3::new(2, 2, 2), STONE);
+        journal.set(&mut world, IVec3::new(2, 2, 2), DIRT);
+        journal.set(&mut world, IVec3::new(3, 3, 3), DIRT);
+        journal.commit();
+
+        assert!(journal.undo(&mut world));
+        assert_eq!(world.get(IVec3::new(2, 2, 2)), Some(AIR));
+        assert_eq!(world.get(IVec3::new(3, 3, 3)), Some(AIR));
+        assert_eq!(world.get(IVec3::new(1, 1, 1)), Some(STONE));
+
+        assert!(journal.redo(&mut world));
+        assert_eq!(world.get(IVec3::new(2, 2, 2)), Some(DIRT));
+        assert_eq!(world.get(IVec3::new(3, 3, 3)), Some(DIRT));
+
+        assert!(journal.undo(&mut world));
+        assert!(journal.undo(&mut world));
+        assert!(!journal.undo(&mut world));
+        assert_eq!(world.get(IVec3::new(1, 1, 1)), Some(AIR));
+    }
+
+    #[test]
+    fn new_edits_clear_the_redo_history() {
+        let mut world = world();
+        let mut journal = Journal::new(usize::MAX);
+        journal.set(&mut world, IVec3::new(1, 1, 1), STONE);
+        journal.undo(&mut world);
+        journal.set(&mut world, IVec3::new(4, 4, 4), STONE);
+        assert!(!journal.redo(&mut world));
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let mut world = world();
+        let mut journal = Journal::new(10 * size_of::<VoxelEdit>());
+        for x in 0..20 {
+            journal.set(&mut world, IVec3::new(x, 0, 0), STONE);
+        }
+        assert_eq!(journal.memory_usage(), 10 * size_of::<VoxelEdit>());
+
+        while journal.undo(&mut world) {}
+        assert_eq!(world.get(IVec3::new(9, 0, 0)), Some(STONE));
+        assert_eq!(world.get(IVec3::new(10, 0, 0)), Some(AIR));
+    }
+
+    #[test]
+    fn undoing_marks_the_chunk_for_remeshing() {
+        let mut world = world();
+        let mut journal = Journal::new(usize::MAX);
+        journal.set(&mut world, IVec3::new(5, 5, 5), STONE);
+        world.take_dirty();
+
+        journal.undo(&mut world);
+        assert_eq!(world.take_dirty(), vec![ChunkID::new(0, IVec3::ZERO)]);
+    }
+}
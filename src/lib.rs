@@ -9,22 +9,31 @@ pub mod mpsc;
 pub mod physics;
 
 mod bitvec;
+mod block_updates;
+mod brush;
 mod chunk;
 #[allow(dead_code)]
 // mod sampling;
 #[allow(dead_code)]
 mod data_structures;
-#[macro_use]
-#[allow(dead_code)]
-mod debug;
 mod engine;
+mod falling;
 mod flood_fill;
+mod fluids;
+mod formats;
+mod journal;
+mod light;
 mod mesh;
 mod meshing;
+mod metrics;
 mod random;
+mod requests;
+mod scheduler;
+mod schematic;
 mod worker;
 mod worker_pool;
 mod worker_spsc;
+mod world;
 mod world_gen;
 
 #[cfg(test)]
@@ -43,17 +52,37 @@ pub fn block_coord(n: f32) -> i32 {
 
 pub type MeshReceiver = MpscReceiver<(ChunkID, MeshUpload)>;
 
+pub use brush::{Brush, Shape};
 pub use chunk::{Chunk, ChunkID, Lod, VoxelType};
 pub use engine::{RenderThreadChannels, Update, engine_thread};
 pub use flood_fill::SphereGeneratorAllocations;
+pub use formats::vox::{MAX_MODEL_SIZE, VoxFile, VoxMapping, VoxModel};
 pub use frustum::{Frustum, FrustumAllocations};
-pub use mesh::{Instance, MeshUpload, TextureID};
+pub use mesh::{Instance, MAX_TEXTURES, MeshUpload, Quad, TextureID};
+pub use metrics::{MemoryUsage, Metrics, PrometheusExporter, StageTiming, serve_prometheus};
 pub use mpsc::{Receiver as MpscReceiver, Sender as MpscSender, new as mpsc_channel};
-pub use random::Noise;
+pub use random::{DeterministicRng, Noise, cell_rng, chunk_rng, chunk_seed, derive_seed};
+pub use requests::ChunkRequests;
+pub use schematic::{Axis, MAX_SCHEMATIC_VOLUME, RegionTooLarge, Schematic};
 pub use time::{DeltaTime, DeltaTimeMeter};
 pub use voxel::VoxelTypes;
-pub use world_gen::{ComposableGenerator, Gen2D, Gen3D, GenBox, Generator, Seed};
+pub use world_gen::{
+    ComposableGenerator, Gen2D, Gen3D, GenBox, GenHeightmap, GenWorms, Generator, Heightmap,
+    HeightmapCache, MaterialMap, Seed, Tiling,
+};
 pub mod spsc {
     pub use rtrb::Consumer;
     pub use rtrb::Producer;
 }
+/// The worker pool and the task queues, only public for the benchmarks.
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::scheduler::{ChunkTask, SharedQueues, TaskQueue, steal};
+    pub use crate::worker_pool::{Runable, Threadpool, WorkerSignal};
+}
+pub mod minecraft {
+    pub use crate::formats::minecraft::{BlockTranslation, read_region, read_sponge_schematic};
+}
+pub mod mesh_export {
+    pub use crate::formats::{gltf::write_glb, obj::write_obj};
+}
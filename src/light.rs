@@ -0,0 +1,342 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use glam::{IVec3, UVec3};
+
+use crate::{
+    Chunk, ChunkID, VoxelType,
+    chunk::{CHUNK_VOLUME, coords_to_1d_index, idx_to_coord},
+    voxel,
+};
+
+pub const MAX_LIGHT: u8 = 15;
+
+/// Light of a voxel without any light data, used for everything that isn't full detail.
+pub const FULL_SKYLIGHT: u8 = MAX_LIGHT << 4;
+
+/// The light of a chunk including a one voxel border of its neighbors, indexed by
+/// `(x + 1) * 34 * 34 + (y + 1) * 34 + (z + 1)`. Every entry has the skylight in the upper and
+/// the block light in the lower four bits.
+pub type PaddedLight = [u8; 34 * 34 * 34];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Channel {
+    /// Falls down from open sky without getting weaker and spreads in every other direction.
+    Sky,
+    /// Spreads from emissive voxels.
+    Block,
+}
+
+impl Channel {
+    fn shift(self) -> u8 {
+        match self {
+            Self::Sky => 4,
+            Self::Block => 0,
+        }
+    }
+}
+
+const DIRECTIONS: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::X,
+    IVec3::NEG_Y,
+    IVec3::Y,
+    IVec3::NEG_Z,
+    IVec3::Z,
+];
+
+/// Light levels of every full detail chunk, kept up to date incrementally when chunks load and
+/// voxels change.
+///
+/// Chunks which aren't loaded are treated as opaque, except that a column without a loaded
+/// chunk above it counts as open sky.
+#[derive(Debug, Default)]
+pub struct Lighting {
+    levels: HashMap<ChunkID, Box<[u8; CHUNK_VOLUME]>>,
+
+    add_queue: VecDeque<IVec3>,
+    removal_queue: VecDeque<(IVec3, u8)>,
+
+    /// Chunks whose mesh samples light that changed.
+    changed: HashSet<ChunkID>,
+}
+
+impl Lighting {
+    pub fn get(&self, pos: IVec3, channel: Channel) -> u8 {
+        self.get_raw(pos)
+            .map_or(0, |raw| (raw >> channel.shift()) & 0xF)
+    }
+
+    fn get_raw(&self, pos: IVec3) -> Option<u8> {
+        let (chunk, local) = split(pos);
+        Some(self.levels.get(&chunk)?[coords_to_1d_index(local)])
+    }
+
+    fn set(&mut self, pos: IVec3, channel: Channel, level: u8) {
+        let (chunk, local) = split(pos);
+        let Some(levels) = self.levels.get_mut(&chunk) else {
+            return;
+        };
+        let raw = &mut levels[coords_to_1d_index(local)];
+        *raw = (*raw & !(0xF << channel.shift())) | (level << channel.shift());
+
+        self.changed.insert(chunk);
+        for (axis, dir) in [
+            (local.x, IVec3::X),
+            (local.y, IVec3::Y),
+            (local.z, IVec3::Z),
+        ] {
+            if axis == 0 {
+                self.changed.insert(ChunkID::new(0, chunk.pos - dir));
+            } else if axis == 31 {
+                self.changed.insert(ChunkID::new(0, chunk.pos + dir));
+            }
+        }
+    }
+
+    /// Returns the chunks whose mesh needs to be rebuilt since the last call.
+    pub fn take_changed(&mut self) -> HashSet<ChunkID> {
+        self.changed
+            .drain()
+            .filter(|chunk| self.levels.contains_key(chunk))
+            .collect()
+    }
+
+    /// The light of the chunk including the border of its neighbors.
+    pub fn padded(&self, chunk: ChunkID) -> Box<PaddedLight> {
+        let origin = chunk.pos * 32 - 1;
+        let mut padded = Box::new([FULL_SKYLIGHT; 34 * 34 * 34]);
+        for x in 0..34 {
+            for y in 0..34 {
+                for z in 0..34 {
+                    if let Some(raw) = self.get_raw(origin + IVec3::new(x, y, z)) {
+                        padded[(x * 34 * 34 + y * 34 + z) as usize] = raw;
+                    }
+                }
+            }
+        }
+        padded
+    }
+
+    /// Lights a chunk again after many of its voxels changed at once, which is cheaper than
+    /// updating the light voxel by voxel.
+    pub fn chunk_changed(&mut self, chunks: &HashMap<ChunkID, Chunk>, id: ChunkID) {
+        let Some(levels) = self.levels.get(&id) else {
+            return self.chunk_loaded(chunks, id);
+        };
+        let lit: Vec<(IVec3, u8)> = levels
+            .iter()
+            .enumerate()
+            .filter(|(_, raw)| **raw != 0)
+            .map(|(i, raw)| (id.pos * 32 + idx_to_coord(i).as_ivec3(), *raw))
+            .collect();
+
+        // take the light of the old voxels out of the neighbors
+        for channel in [Channel::Sky, Channel::Block] {
+            for (pos, raw) in &lit {
+                let level = (raw >> channel.shift()) & 0xF;
+                if level > 0 {
+                    self.set(*pos, channel, 0);
+                    self.removal_queue.push_back((*pos, level));
+                }
+            }
+            self.remove(chunks, channel);
+        }
+
+        self.levels.remove(&id);
+        self.chunk_loaded(chunks, id);
+    }
+
+    /// Lights a newly loaded chunk and spreads its light into the neighbors and theirs into it.
+    pub fn chunk_loaded(&mut self, chunks: &HashMap<ChunkID, Chunk>, id: ChunkID) {
+        let Some(chunk) = chunks.get(&id) else {
+            return;
+        };
+        let origin = id.pos * 32;
+        let above_loaded = chunks.contains_key(&ChunkID::new(0, id.pos + IVec3::Y));
+        let below_loaded = self
+            .levels
+            .contains_key(&ChunkID::new(0, id.pos - IVec3::Y));
+
+        let mut levels = Box::new([0_u8; CHUNK_VOLUME]);
+        for x in 0..32 {
+            for z in 0..32 {
+                let top = origin + IVec3::new(x as i32, 32, z as i32);
+                let mut sky = if !above_loaded || self.get(top, Channel::Sky) == MAX_LIGHT {
+                    MAX_LIGHT
+                } else {
+                    0
+                };
+
+                for y in (0..32).rev() {
+                    let local = UVec3::new(x, y, z);
+                    let voxel = chunk.get(local);
+                    if !voxel::is_transparent(voxel) {
+                        sky = 0;
+                    }
+                    levels[coords_to_1d_index(local)] = sky << 4 | voxel::emission(voxel);
+                }
+
+                // the chunk below assumed open sky until now
+                let bottom = origin + IVec3::new(x as i32, -1, z as i32);
+                if below_loaded && sky != MAX_LIGHT && self.get(bottom, Channel::Sky) == MAX_LIGHT {
+                    self.set(bottom, Channel::Sky, 0);
+                    self.removal_queue.push_back((bottom, MAX_LIGHT));
+                }
+            }
+        }
+
+        // open columns only need to spread at the border of the chunk or where they border a
+        // covered column
+        let mut sources = Vec::new();
+        for (i, raw) in levels.iter().enumerate() {
+            let local = idx_to_coord(i);
+            let spreads = *raw & 0xF != 0
+                || *raw != 0 && on_border(local)
+                || *raw >> 4 == MAX_LIGHT
+                    && [IVec3::NEG_X, IVec3::X, IVec3::NEG_Z, IVec3::Z]
+                        .into_iter()
+                        .map(|dir| (local.as_ivec3() + dir).as_uvec3())
+                        .any(|neighbor| {
+                            levels[coords_to_1d_index(neighbor)] >> 4 != MAX_LIGHT
+                                && voxel::is_transparent(chunk.get(neighbor))
+                        });
+            if spreads {
+                sources.push(origin + local.as_ivec3());
+            }
+        }
+
+        self.levels.insert(id, levels);
+        self.changed.insert(id);
+        self.remove(chunks, Channel::Sky);
+
+        // light from the neighbors flows in
+        for dir in DIRECTIONS {
+            for a in 0..32 {
+                for b in 0..32 {
+                    let layer = match dir {
+                        IVec3 { x: 0, y: 0, .. } => {
+                            IVec3::new(a, b, if dir.z < 0 { -1 } else { 32 })
+                        }
+                        IVec3 { x: 0, .. } => IVec3::new(a, if dir.y < 0 { -1 } else { 32 }, b),
+                        _ => IVec3::new(if dir.x < 0 { -1 } else { 32 }, a, b),
+                    };
+                    let pos = origin + layer;
+                    if self.get_raw(pos).is_some_and(|raw| raw != 0) {
+                        sources.push(pos);
+                    }
+                }
+            }
+        }
+
+        for channel in [Channel::Sky, Channel::Block] {
+            self.add_queue.extend(sources.iter().copied());
+            self.propagate(chunks, channel);
+        }
+    }
+
+    /// Updates the light after the voxel at `pos` changed. The chunk data has to contain the new
+    /// voxel already.
+    pub fn voxel_changed(&mut self, chunks: &HashMap<ChunkID, Chunk>, pos: IVec3) {
+        let Some(voxel) = voxel_at(chunks, pos) else {
+            return;
+        };
+
+        for channel in [Channel::Sky, Channel::Block] {
+            let old = self.get(pos, channel);
+            if old > 0 {
+                self.set(pos, channel, 0);
+                self.removal_queue.push_back((pos, old));
+                self.remove(chunks, channel);
+            }
+
+            if voxel::is_transparent(voxel) {
+                for dir in DIRECTIONS {
+                    if self.get(pos + dir, channel) > 0 {
+                        self.add_queue.push_back(pos + dir);
+                    }
+                }
+
+                let above = pos + IVec3::Y;
+                if channel == Channel::Sky && voxel_at(chunks, above).is_none() {
+                    self.set(pos, channel, MAX_LIGHT);
+                    self.add_queue.push_back(pos);
+                }
+            }
+
+            let emission = voxel::emission(voxel);
+            if channel == Channel::Block && emission > 0 {
+                self.set(pos, channel, emission);
+                self.add_queue.push_back(pos);
+            }
+
+            self.propagate(chunks, channel);
+        }
+    }
+
+    /// Removes every light that was fed by the voxels in the removal queue and queues the light
+    /// sources at the edge of the removed area to fill it up again.
+    fn remove(&mut self, chunks: &HashMap<ChunkID, Chunk>, channel: Channel) {
+        while let Some((pos, level)) = self.removal_queue.pop_front() {
+            for dir in DIRECTIONS {
+                let neighbor = pos + dir;
+                let neighbor_level = self.get(neighbor, channel);
+                if neighbor_level == 0 {
+                    continue;
+                }
+
+                let falls_down = channel == Channel::Sky
+                    && dir == IVec3::NEG_Y
+                    && level == MAX_LIGHT
+                    && neighbor_level == MAX_LIGHT;
+                if neighbor_level < level || falls_down {
+                    self.set(neighbor, channel, 0);
+                    self.removal_queue.push_back((neighbor, neighbor_level));
+                } else {
+                    self.add_queue.push_back(neighbor);
+                }
+            }
+        }
+        self.propagate(chunks, channel);
+    }
+
+    fn propagate(&mut self, chunks: &HashMap<ChunkID, Chunk>, channel: Channel) {
+        while let Some(pos) = self.add_queue.pop_front() {
+            let level = self.get(pos, channel);
+            if level == 0 {
+                continue;
+            }
+
+            for dir in DIRECTIONS {
+                let neighbor = pos + dir;
+                if !voxel_at(chunks, neighbor).is_some_and(voxel::is_transparent) {
+                    continue;
+                }
+
+                let spread = if channel == Channel::Sky && dir == IVec3::NEG_Y && level == MAX_LIGHT
+                {
+                    MAX_LIGHT
+                } else {
+                    level - 1
+                };
+                if self.get(neighbor, channel) < spread {
+                    self.set(neighbor, channel, spread);
+                    self.add_queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+}
+
+/// Splits a world position into the full detail chunk and the position inside of it.
+pub fn split(pos: IVec3) -> (ChunkID, UVec3) {
+    (ChunkID::new(0, pos >> 5), (pos & 31).as_uvec3())
+}
+
+fn on_border(local: UVec3) -> bool {
+    local.cmpeq(UVec3::ZERO).any() || local.cmpeq(UVec3::splat(31)).any()
+}
+
+fn voxel_at(chunks: &HashMap<ChunkID, Chunk>, pos: IVec3) -> Option<VoxelType> {
+    let (chunk, local) = split(pos);
+    Some(chunks.get(&chunk)?.get(local))
+}
@@ -1,4 +1,6 @@
-use glam::UVec3;
+use glam::{UVec3, Vec3};
+
+use crate::ChunkID;
 
 #[derive(Debug, Clone)]
 pub struct MeshUpload {
@@ -14,15 +16,117 @@ impl MeshUpload {
     pub fn len(&self) -> u64 {
         (self.buf.len() << 2) as u64
     }
+
+    /// The instances of one face direction, in the order `-x`, `+x`, `-y`, `+y`, `-z`, `+z`.
+    pub fn instances(&self, face: usize) -> &[Instance] {
+        let start = (self.offsets[face] >> 2) as usize;
+        let end = self
+            .offsets
+            .get(face + 1)
+            .map_or(self.buf.len(), |offset| (offset >> 2) as usize);
+        &self.buf[start..end]
+    }
+
+    /// Turns the instances into quads in world space, the way the shader places them.
+    pub fn quads(&self, chunk: ChunkID) -> Vec<Quad> {
+        let scale = (1 << chunk.lod) as f32;
+        let origin = (chunk.total_pos() * 32).as_vec3();
+        let mut quads = Vec::with_capacity(self.buf.len());
+        for (face, [normal, corners @ ..]) in FACE_CORNERS.iter().enumerate() {
+            for instance in self.instances(face) {
+                let pos = instance.pos().as_vec3();
+                quads.push(Quad {
+                    corners: corners.map(|corner| origin + (pos + corner) * scale),
+                    normal: *normal,
+                    texture: instance.texture(),
+                    light: instance.light(),
+                });
+            }
+        }
+        quads
+    }
+}
+
+/// A face of a voxel. The corners wind counterclockwise when looking at the front.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quad {
+    pub corners: [Vec3; 4],
+    pub normal: Vec3,
+    pub texture: TextureID,
+    /// Sky light in the upper, block light in the lower four bits.
+    pub light: u8,
 }
 
+impl Quad {
+    /// How lit the face is, from `0` to `1`.
+    pub fn brightness(&self) -> f32 {
+        (self.light >> 4).max(self.light & 15) as f32 / 15.
+    }
+}
+
+/// The normal followed by the corners relative to the voxel, for every face direction.
+const FACE_CORNERS: [[Vec3; 5]; 6] = {
+    const fn v(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3::new(x, y, z)
+    }
+    [
+        [
+            v(-1., 0., 0.),
+            v(0., 0., 0.),
+            v(0., 0., 1.),
+            v(0., 1., 1.),
+            v(0., 1., 0.),
+        ],
+        [
+            v(1., 0., 0.),
+            v(1., 0., 0.),
+            v(1., 1., 0.),
+            v(1., 1., 1.),
+            v(1., 0., 1.),
+        ],
+        [
+            v(0., -1., 0.),
+            v(0., 0., 0.),
+            v(1., 0., 0.),
+            v(1., 0., 1.),
+            v(0., 0., 1.),
+        ],
+        [
+            v(0., 1., 0.),
+            v(0., 1., 0.),
+            v(0., 1., 1.),
+            v(1., 1., 1.),
+            v(1., 1., 0.),
+        ],
+        [
+            v(0., 0., -1.),
+            v(0., 0., 0.),
+            v(0., 1., 0.),
+            v(1., 1., 0.),
+            v(1., 0., 0.),
+        ],
+        [
+            v(0., 0., 1.),
+            v(0., 0., 1.),
+            v(1., 0., 1.),
+            v(1., 1., 1.),
+            v(0., 1., 1.),
+        ],
+    ]
+};
+
 pub type TextureID = u16;
 
-/// The kind states the orientation and the texture.
+/// Texture ids have to be below this to fit into an [`Instance`].
+pub const MAX_TEXTURES: TextureID = 1 << 9;
+
+/// The kind states the orientation, the light in front of the face and the texture.
 /// It has the following layout:
-/// |x x x x x y y y y y z z z z z| texture
+/// |x x x x x y y y y y z z z z z| sky   | block | texture
 /// |0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|0|
 ///
+/// The light took 8 of the 17 bits the texture used to have, so shaders reading the texture have
+/// to mask the lowest 9 bits, and there are at most [`MAX_TEXTURES`] textures.
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct Instance {
@@ -32,6 +136,19 @@ unsafe impl bytemuck::Pod for Instance {}
 unsafe impl bytemuck::Zeroable for Instance {}
 
 impl Instance {
+    pub fn pos(&self) -> UVec3 {
+        UVec3::new(self.kind >> 27, self.kind >> 22 & 31, self.kind >> 17 & 31)
+    }
+
+    pub fn light(&self) -> u8 {
+        (self.kind >> 9) as u8
+    }
+
+    pub fn texture(&self) -> TextureID {
+        (self.kind & 0x1FF) as TextureID
+    }
+
+    #[cfg(feature = "renderer")]
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
         wgpu::VertexBufferLayout {
@@ -109,43 +226,99 @@ impl Mesh {
         }
     }
 
-    pub(crate) fn add_nx(&mut self, pos: UVec3, texture: TextureID) {
+    pub(crate) fn add_nx(&mut self, pos: UVec3, texture: TextureID, light: u8) {
         self.nx.push(Instance {
-            kind: compress_data(pos, texture),
+            kind: compress_data(pos, texture, light),
         });
     }
 
-    pub(crate) fn add_px(&mut self, pos: UVec3, texture: TextureID) {
+    pub(crate) fn add_px(&mut self, pos: UVec3, texture: TextureID, light: u8) {
         self.px.push(Instance {
-            kind: compress_data(pos, texture),
+            kind: compress_data(pos, texture, light),
         });
     }
 
-    pub(crate) fn add_ny(&mut self, pos: UVec3, texture: TextureID) {
+    pub(crate) fn add_ny(&mut self, pos: UVec3, texture: TextureID, light: u8) {
         self.ny.push(Instance {
-            kind: compress_data(pos, texture),
+            kind: compress_data(pos, texture, light),
         });
     }
 
-    pub(crate) fn add_py(&mut self, pos: UVec3, texture: TextureID) {
+    pub(crate) fn add_py(&mut self, pos: UVec3, texture: TextureID, light: u8) {
         self.py.push(Instance {
-            kind: compress_data(pos, texture),
+            kind: compress_data(pos, texture, light),
         });
     }
 
-    pub(crate) fn add_nz(&mut self, pos: UVec3, texture: TextureID) {
+    pub(crate) fn add_nz(&mut self, pos: UVec3, texture: TextureID, light: u8) {
         self.nz.push(Instance {
-            kind: compress_data(pos, texture),
+            kind: compress_data(pos, texture, light),
         });
     }
 
-    pub(crate) fn add_pz(&mut self, pos: UVec3, texture: TextureID) {
+    pub(crate) fn add_pz(&mut self, pos: UVec3, texture: TextureID, light: u8) {
         self.pz.push(Instance {
-            kind: compress_data(pos, texture),
+            kind: compress_data(pos, texture, light),
         });
     }
 }
 
-fn compress_data(pos: UVec3, texture: TextureID) -> u32 {
-    (pos.x << 27) | (pos.y << 22) | (pos.z << 17) | texture as u32
+/// `texture` has to be below [`MAX_TEXTURES`], which [`crate::voxel::texture_id`] makes sure of.
+fn compress_data(pos: UVec3, texture: TextureID, light: u8) -> u32 {
+    let texture = texture as u32 & (MAX_TEXTURES as u32 - 1);
+    (pos.x << 27) | (pos.y << 22) | (pos.z << 17) | (light as u32) << 9 | texture
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3, Vec3};
+
+    use crate::{
+        ChunkID, VoxelTypes,
+        voxel::{MISSING_TEXTURE, texture_id},
+    };
+
+    use super::{MAX_TEXTURES, Mesh};
+
+    #[test]
+    fn quads_face_outwards_in_world_space() {
+        let mut mesh = Mesh::with_capacity(1);
+        mesh.add_nx(UVec3::new(1, 2, 3), 7, 0xF3);
+        mesh.add_py(UVec3::new(1, 2, 3), 8, 0);
+        mesh.add_pz(UVec3::new(31, 31, 31), 9, 0x05);
+        let quads = mesh.bytes().quads(ChunkID::new(1, IVec3::new(1, 0, -1)));
+
+        assert_eq!(quads.len(), 3);
+        for quad in &quads {
+            let [a, b, c, _] = quad.corners;
+            assert_eq!((b - a).cross(c - a).normalize(), quad.normal);
+        }
+        // LOD 1 doubles everything and moves the chunk to (64, 0, -64)
+        assert_eq!(quads[0].corners[0], Vec3::new(66., 4., -58.));
+        assert_eq!(quads[0].texture, 7);
+        assert_eq!(quads[0].brightness(), 1.);
+        assert_eq!(quads[1].corners[0].y, 6.);
+        assert_eq!(quads[2].corners[2], Vec3::new(128., 64., 0.));
+        assert_eq!(quads[2].brightness(), 5. / 15.);
+    }
+
+    #[test]
+    fn textures_use_all_of_their_bits() {
+        let mut mesh = Mesh::with_capacity(1);
+        mesh.add_px(UVec3::new(31, 0, 31), MAX_TEXTURES - 1, 0xFF);
+        let quads = mesh.bytes().quads(ChunkID::new(0, IVec3::ZERO));
+        assert_eq!(quads[0].texture, MAX_TEXTURES - 1);
+        assert_eq!(quads[0].brightness(), 1.);
+    }
+
+    #[test]
+    fn unknown_voxel_types_get_the_missing_texture() {
+        assert_eq!(
+            texture_id(VoxelTypes::Sand as u16, 0),
+            VoxelTypes::Sand as u16 - 2
+        );
+        for voxel in [0, 0x0FFF, MAX_TEXTURES + 2, u16::MAX] {
+            assert_eq!(texture_id(voxel, 0), MISSING_TEXTURE, "{voxel}");
+        }
+    }
 }
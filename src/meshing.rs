@@ -1,7 +1,8 @@
-use glam::UVec3;
+use glam::{IVec3, UVec3};
 
 use crate::{
     chunk::{DenseChunk, coords_to_1d_index, idx_to_coord},
+    light::{FULL_SKYLIGHT, PaddedLight},
     mesh::Mesh,
     voxel::{self, VoxelTypes},
 };
@@ -76,7 +77,16 @@ fn bit_index(x: u32, i: usize) -> bool {
     x & (FIRST_BIT >> i) != 0
 }
 
-pub fn generate_mesh(data: &DenseChunk, faces: [BitMap3D; 6]) -> Mesh {
+/// Each face gets the light of the voxel in front of it. Without light every face is fully lit by
+/// the sky.
+pub fn generate_mesh(data: &DenseChunk, faces: [BitMap3D; 6], light: Option<&PaddedLight>) -> Mesh {
+    let light_in_front = |pos: UVec3, dir: IVec3| {
+        light.map_or(FULL_SKYLIGHT, |light| {
+            let padded = pos.as_ivec3() + dir + 1;
+            light[(padded.x * 34 * 34 + padded.y * 34 + padded.z) as usize]
+        })
+    };
+
     let mut mesh = Mesh::with_capacity(100);
     for x in 0..32_usize {
         for y in 0..32_usize {
@@ -89,22 +99,46 @@ pub fn generate_mesh(data: &DenseChunk, faces: [BitMap3D; 6]) -> Mesh {
                 }
 
                 if bit_index(faces[0][y][z], x) {
-                    mesh.add_nx(pos, voxel::texture_id(voxel, 0))
+                    mesh.add_nx(
+                        pos,
+                        voxel::texture_id(voxel, 0),
+                        light_in_front(pos, IVec3::NEG_X),
+                    )
                 }
                 if bit_index(faces[1][y][z], x) {
-                    mesh.add_px(pos, voxel::texture_id(voxel, 1))
+                    mesh.add_px(
+                        pos,
+                        voxel::texture_id(voxel, 1),
+                        light_in_front(pos, IVec3::X),
+                    )
                 }
                 if bit_index(faces[2][z][x], y) {
-                    mesh.add_ny(pos, voxel::texture_id(voxel, 2))
+                    mesh.add_ny(
+                        pos,
+                        voxel::texture_id(voxel, 2),
+                        light_in_front(pos, IVec3::NEG_Y),
+                    )
                 }
                 if bit_index(faces[3][z][x], y) {
-                    mesh.add_py(pos, voxel::texture_id(voxel, 3))
+                    mesh.add_py(
+                        pos,
+                        voxel::texture_id(voxel, 3),
+                        light_in_front(pos, IVec3::Y),
+                    )
                 }
                 if bit_index(faces[4][x][y], z) {
-                    mesh.add_nz(pos, voxel::texture_id(voxel, 4))
+                    mesh.add_nz(
+                        pos,
+                        voxel::texture_id(voxel, 4),
+                        light_in_front(pos, IVec3::NEG_Z),
+                    )
                 }
                 if bit_index(faces[5][x][y], z) {
-                    mesh.add_pz(pos, voxel::texture_id(voxel, 5))
+                    mesh.add_pz(
+                        pos,
+                        voxel::texture_id(voxel, 5),
+                        light_in_front(pos, IVec3::Z),
+                    )
                 }
             }
         }
@@ -0,0 +1,374 @@
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use parking_lot::RwLock;
+use serde::Serialize;
+
+/// Counters the engine and the workers bump concurrently.
+#[derive(Debug, Default)]
+pub struct Counters {
+    /// Results a worker had to wait with because the engine didn't drain an output queue in time.
+    pub output_stalls: AtomicU64,
+    /// Ticks in which the engine held back tasks because the task queues were full.
+    pub submission_stalls: AtomicU64,
+    /// Config updates a worker wasn't ready to take right away.
+    pub config_stalls: AtomicU64,
+    /// Tasks workers dropped because the chunk's LOD changed in the meantime.
+    pub cancellations: AtomicU64,
+
+    pub generate: StageTimer,
+    pub mesh: StageTimer,
+    pub collider: StageTimer,
+}
+
+impl Counters {
+    pub fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+}
+
+/// How often and how long a stage of the workers ran.
+#[derive(Debug, Default)]
+pub struct StageTimer {
+    runs: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl StageTimer {
+    pub fn time<R>(&self, stage: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let out = stage();
+        self.nanos
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        out
+    }
+
+    pub fn snapshot(&self) -> StageTiming {
+        StageTiming {
+            runs: self.runs.load(Ordering::Relaxed),
+            seconds: Duration::from_nanos(self.nanos.load(Ordering::Relaxed)).as_secs_f64(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct StageTiming {
+    pub runs: u64,
+    /// Summed over all workers since the start.
+    pub seconds: f64,
+}
+
+impl StageTiming {
+    pub fn average_ms(&self) -> f64 {
+        if self.runs == 0 {
+            0.
+        } else {
+            self.seconds * 1000. / self.runs as f64
+        }
+    }
+}
+
+/// Bytes the engine thread holds, by what they're for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct MemoryUsage {
+    pub chunks: usize,
+    pub solid_maps: usize,
+    pub colliders: usize,
+    pub edit_history: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.chunks + self.solid_maps + self.colliders + self.edit_history
+    }
+}
+
+/// What the engine was up to, refreshed a few times a second. Counters count since the start.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Metrics {
+    pub tps: f64,
+    pub workers: usize,
+
+    pub generate: StageTiming,
+    pub mesh: StageTiming,
+    pub collider: StageTiming,
+
+    /// Pending tasks, indexed by the LOD of their chunk.
+    pub queued_tasks_per_lod: Vec<usize>,
+    /// Chunks the workers delivered, indexed by LOD: loaded into the world at full detail, meshed
+    /// below that. Queued chunks are in `queued_tasks_per_lod`.
+    pub chunks_per_lod: Vec<usize>,
+    pub memory: MemoryUsage,
+    /// Chunks and meshes someone awaits through [`crate::ChunkRequests`].
+    pub pending_requests: usize,
+
+    pub cancellations: u64,
+    pub output_stalls: u64,
+    pub submission_stalls: u64,
+    pub config_stalls: u64,
+}
+
+impl Metrics {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("metrics are plain data")
+    }
+
+    /// The Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, f64)]| {
+            _ = writeln!(out, "# HELP voxine_{name} {help}");
+            _ = writeln!(out, "# TYPE voxine_{name} {kind}");
+            for (labels, value) in samples {
+                _ = writeln!(out, "voxine_{name}{labels} {value}");
+            }
+        };
+        let single = |value: f64| [(String::new(), value)];
+        let per_lod = |values: &[usize]| -> Vec<(String, f64)> {
+            values
+                .iter()
+                .enumerate()
+                .map(|(lod, n)| (format!("{{lod=\"{lod}\"}}"), *n as f64))
+                .collect()
+        };
+        let stages = [
+            ("generate", self.generate),
+            ("mesh", self.mesh),
+            ("collider", self.collider),
+        ];
+
+        metric(
+            "ticks_per_second",
+            "gauge",
+            "Engine ticks per second.",
+            &single(self.tps),
+        );
+        metric(
+            "workers",
+            "gauge",
+            "Running worker threads.",
+            &single(self.workers as f64),
+        );
+        metric(
+            "stage_runs_total",
+            "counter",
+            "Times a worker stage ran.",
+            &stages.map(|(stage, t)| (format!("{{stage=\"{stage}\"}}"), t.runs as f64)),
+        );
+        metric(
+            "stage_seconds_total",
+            "counter",
+            "Time the workers spent in a stage.",
+            &stages.map(|(stage, t)| (format!("{{stage=\"{stage}\"}}"), t.seconds)),
+        );
+        metric(
+            "queued_tasks",
+            "gauge",
+            "Pending worker tasks.",
+            &per_lod(&self.queued_tasks_per_lod),
+        );
+        metric(
+            "chunks",
+            "gauge",
+            "Chunks generated or being generated.",
+            &per_lod(&self.chunks_per_lod),
+        );
+        metric(
+            "memory_bytes",
+            "gauge",
+            "Memory held by the engine thread.",
+            &[
+                ("chunks", self.memory.chunks),
+                ("solid_maps", self.memory.solid_maps),
+                ("colliders", self.memory.colliders),
+                ("edit_history", self.memory.edit_history),
+            ]
+            .map(|(category, bytes)| (format!("{{category=\"{category}\"}}"), bytes as f64)),
+        );
+        metric(
+            "pending_requests",
+            "gauge",
+            "Awaited chunks and meshes.",
+            &single(self.pending_requests as f64),
+        );
+        metric(
+            "cancellations_total",
+            "counter",
+            "Tasks dropped because their LOD changed.",
+            &single(self.cancellations as f64),
+        );
+        metric(
+            "stalls_total",
+            "counter",
+            "Times a queue was full.",
+            &[
+                ("output", self.output_stalls),
+                ("submission", self.submission_stalls),
+                ("config", self.config_stalls),
+            ]
+            .map(|(queue, n)| (format!("{{queue=\"{queue}\"}}"), n as f64)),
+        );
+        out
+    }
+}
+
+/// How long the exporter sleeps when nobody is connecting, which is also how long it takes to
+/// notice it should stop.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The thread [`serve_prometheus`] answers requests on. Stops when dropped.
+#[derive(Debug)]
+pub struct PrometheusExporter {
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl PrometheusExporter {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Stops accepting requests and waits for the current one, which frees the address.
+    pub fn shutdown(mut self) {
+        self.stop_and_join();
+    }
+
+    fn stop_and_join(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
+
+impl Drop for PrometheusExporter {
+    fn drop(&mut self) {
+        self.stop_and_join();
+    }
+}
+
+/// Answers every HTTP request on `listener` with the latest metrics in the Prometheus text
+/// format. Meant for a local scraper, so requests are handled one after another.
+pub fn serve_prometheus(
+    listener: TcpListener,
+    metrics: Arc<RwLock<Metrics>>,
+) -> Result<PrometheusExporter, io::Error> {
+    let address = listener.local_addr()?;
+    // polled, as a blocking accept can't be interrupted
+    listener.set_nonblocking(true)?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let exporter_stop = stop.clone();
+    let thread = thread::Builder::new()
+        .name("metrics exporter".to_owned())
+        .spawn(move || {
+            while !exporter_stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => answer(stream, &metrics),
+                    Err(_) => thread::sleep(ACCEPT_POLL_INTERVAL),
+                }
+            }
+        })?;
+    Ok(PrometheusExporter {
+        address,
+        stop,
+        thread: Some(thread),
+    })
+}
+
+fn answer(mut stream: TcpStream, metrics: &RwLock<Metrics>) {
+    if stream.set_nonblocking(false).is_err() {
+        return;
+    }
+    _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+    // the request doesn't matter, but it has to be read before answering
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+        line.clear();
+    }
+
+    let body = metrics.read().to_prometheus();
+    _ = write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::Arc,
+    };
+
+    use parking_lot::RwLock;
+
+    use super::{Metrics, StageTiming, serve_prometheus};
+
+    fn metrics() -> Metrics {
+        Metrics {
+            tps: 60.,
+            workers: 2,
+            mesh: StageTiming {
+                runs: 4,
+                seconds: 0.01,
+            },
+            queued_tasks_per_lod: vec![3, 0, 1],
+            cancellations: 7,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exports_text_and_json() {
+        let metrics = metrics();
+        assert_eq!(metrics.mesh.average_ms(), 2.5);
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains("voxine_ticks_per_second 60\n"));
+        assert!(text.contains("voxine_stage_runs_total{stage=\"mesh\"} 4\n"));
+        assert!(text.contains("voxine_queued_tasks{lod=\"2\"} 1\n"));
+        assert!(text.contains("# TYPE voxine_cancellations_total counter\n"));
+
+        let json: serde_json::Value = serde_json::from_str(&metrics.to_json()).unwrap();
+        assert_eq!(json["queued_tasks_per_lod"][0], 3);
+        assert_eq!(json["mesh"]["runs"], 4);
+    }
+
+    #[test]
+    fn serves_prometheus_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let exporter = serve_prometheus(listener, Arc::new(RwLock::new(metrics()))).unwrap();
+        let address = exporter.local_addr();
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("voxine_workers 2\n"));
+
+        // a restarted engine gets the same address
+        exporter.shutdown();
+        TcpListener::bind(address).unwrap();
+    }
+}
@@ -1,6 +1,6 @@
-use std::sync::Arc;
+use std::{sync::Arc, thread, time::Duration};
 
-use crossbeam::queue::ArrayQueue;
+use crossbeam::{queue::ArrayQueue, utils::Backoff};
 use rtrb::{PopError, PushError};
 
 pub fn new<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
@@ -16,11 +16,20 @@ pub fn new<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
     )
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Sender<T> {
     inner: Arc<Inner<T>>,
 }
 
+/// Derived, it would require `T: Clone`.
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Receiver<T> {
     inner: Arc<Inner<T>>,
@@ -56,6 +65,29 @@ impl<T> Sender<T> {
             Err(value) => Err(PushError::Full(value)),
         }
     }
+
+    /// Pushes the value, backing off while the queue is full: spinning first, then yielding and
+    /// finally sleeping until the receiver makes room. Returns whether it had to wait, or gives the
+    /// value back once `stopped` says so, as the receiver might never make room again.
+    pub fn push_with_backoff(&self, mut value: T, stopped: impl Fn() -> bool) -> Result<bool, T> {
+        let backoff = Backoff::new();
+        let mut stalled = false;
+        loop {
+            match self.inner.queue.push(value) {
+                Ok(()) => return Ok(stalled),
+                Err(v) => value = v,
+            }
+            if stopped() {
+                return Err(value);
+            }
+            stalled = true;
+            if backoff.is_completed() {
+                thread::sleep(Duration::from_micros(100));
+            } else {
+                backoff.snooze();
+            }
+        }
+    }
 }
 
 impl<T> Receiver<T> {
@@ -157,6 +189,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn backoff_waits_for_room() {
+        let (tx, rx) = channel(1);
+        assert_eq!(tx.push_with_backoff(1, || false), Ok(false));
+
+        let consumer = thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(5));
+            (rx.pop().unwrap(), rx)
+        });
+        assert_eq!(tx.push_with_backoff(2, || false), Ok(true));
+        let (first, rx) = consumer.join().unwrap();
+        assert_eq!((first, rx.pop().unwrap()), (1, 2));
+
+        tx.push(3).unwrap();
+        assert_eq!(tx.push_with_backoff(4, || true), Err(4));
+    }
+
     #[test]
     fn drain_collects_all_current_items() {
         let (tx, rx) = channel(8);
@@ -40,6 +40,10 @@ impl Aabb {
         }
     }
 
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
     pub fn player_pos(&self) -> Vec3 {
         self.min + PLAYER_HALF_EXTENTS
     }
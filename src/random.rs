@@ -1,10 +1,67 @@
-use rand::Rng;
+use glam::IVec3;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{ChunkID, Seed};
 
 #[allow(unused)]
 pub fn get_random<T: Ord + rand::distributions::uniform::SampleUniform>(min: T, max: T) -> T {
     rand::thread_rng().gen_range(min..=max)
 }
 
+/// A random number stream that produces the same numbers for the same seed on every platform.
+pub type DeterministicRng = ChaCha8Rng;
+
+/// Hashes the seed together with `parts`. Uses blake3, so sub-seeds don't depend on the platform,
+/// the Rust version or the order in which they are derived.
+fn hash_seed(seed: Seed, parts: &[&[u8]]) -> Seed {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&seed.to_le_bytes());
+    for part in parts {
+        hasher.update(&(part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    u64::from_le_bytes(hasher.finalize().as_bytes()[..8].try_into().unwrap())
+}
+
+/// Derives an independent seed for a named sub-generator, e.g. `derive_seed(world_seed, "caves")`.
+pub fn derive_seed(seed: Seed, name: &str) -> Seed {
+    hash_seed(seed, &[b"name", name.as_bytes()])
+}
+
+/// The seed of a single chunk.
+pub fn chunk_seed(seed: Seed, chunk: ChunkID) -> Seed {
+    hash_seed(
+        seed,
+        &[
+            b"chunk",
+            &chunk.pos.x.to_le_bytes(),
+            &chunk.pos.y.to_le_bytes(),
+            &chunk.pos.z.to_le_bytes(),
+            &chunk.lod.to_le_bytes(),
+        ],
+    )
+}
+
+/// A random number stream only used for this chunk.
+pub fn chunk_rng(seed: Seed, chunk: ChunkID) -> DeterministicRng {
+    DeterministicRng::seed_from_u64(chunk_seed(seed, chunk))
+}
+
+/// The `index`th random number stream of a cell in an arbitrary grid over the world.
+pub fn cell_rng(seed: Seed, cell: IVec3, index: u32) -> DeterministicRng {
+    DeterministicRng::seed_from_u64(hash_seed(
+        seed,
+        &[
+            b"cell",
+            &cell.x.to_le_bytes(),
+            &cell.y.to_le_bytes(),
+            &cell.z.to_le_bytes(),
+            &index.to_le_bytes(),
+        ],
+    ))
+}
+
 use noise::{NoiseFn, Perlin};
 
 #[derive(Clone, Debug)]
@@ -19,6 +76,12 @@ impl Noise {
         }
     }
 
+    /// Folds all 64 bits of the seed into the 32 bits the noise takes, instead of dropping the
+    /// upper half.
+    pub fn from_seed(seed: Seed) -> Self {
+        Self::new((seed ^ (seed >> 32)) as u32)
+    }
+
     pub fn get(&self, x: f64, y: f64, z: f64, space_scale: f64) -> f64 {
         let animated_x = x / space_scale;
         let animated_y = y / space_scale;
@@ -60,3 +123,47 @@ impl Noise {
         value / max_value
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+    use rand::Rng;
+
+    use crate::ChunkID;
+
+    use super::{chunk_rng, chunk_seed, derive_seed};
+
+    #[test]
+    fn derived_seeds_are_stable() {
+        // changing these values breaks every existing world
+        assert_eq!(derive_seed(0, "dirt"), 0xd160_b23e_157d_9696);
+        assert_eq!(
+            chunk_seed(42, ChunkID::new(3, IVec3::new(-1, 2, -3))),
+            0x98d0_b3ea_b763_6fd6
+        );
+    }
+
+    #[test]
+    fn derived_seeds_differ() {
+        assert_ne!(derive_seed(7, "dirt"), derive_seed(7, "caves"));
+        assert_ne!(derive_seed(7, "dirt"), derive_seed(8, "dirt"));
+        assert_ne!(
+            chunk_seed(7, ChunkID::new(0, IVec3::X)),
+            chunk_seed(7, ChunkID::new(1, IVec3::X))
+        );
+    }
+
+    #[test]
+    fn chunk_rngs_are_reproducible() {
+        let chunk = ChunkID::new(0, IVec3::new(4, -8, 15));
+        let a: Vec<u32> = chunk_rng(1, chunk)
+            .sample_iter(rand::distributions::Standard)
+            .take(16)
+            .collect();
+        let b: Vec<u32> = chunk_rng(1, chunk)
+            .sample_iter(rand::distributions::Standard)
+            .take(16)
+            .collect();
+        assert_eq!(a, b);
+    }
+}
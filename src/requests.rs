@@ -0,0 +1,234 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{Chunk, ChunkID, MeshUpload, world::World};
+
+/// Lets tools and servers await single chunks, generated by the worker pool of the engine, without
+/// streaming everything around the camera.
+#[derive(Debug, Clone)]
+pub struct ChunkRequests {
+    tx: mpsc::UnboundedSender<Request>,
+}
+
+impl ChunkRequests {
+    pub(crate) fn new() -> (Self, mpsc::UnboundedReceiver<Request>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self { tx }, rx)
+    }
+
+    /// Resolves to the voxels of the chunk, or `None` if the engine shut down first. The request
+    /// gets sent right away and canceled once nobody awaits the chunk anymore.
+    pub fn request_chunk(&self, chunk: ChunkID) -> impl Future<Output = Option<Chunk>> + use<> {
+        let (reply, rx) = oneshot::channel();
+        _ = self.tx.send(Request::Chunk(chunk, reply));
+        async move { rx.await.ok() }
+    }
+
+    /// Like [`ChunkRequests::request_chunk`], but resolves to the mesh of the chunk. Chunks the
+    /// engine loaded are meshed with their edits and light, others come unlit from the generator.
+    pub fn request_mesh(&self, chunk: ChunkID) -> impl Future<Output = Option<MeshUpload>> + use<> {
+        let (reply, rx) = oneshot::channel();
+        _ = self.tx.send(Request::Mesh(chunk, reply));
+        async move { rx.await.ok() }
+    }
+}
+
+#[derive(Debug)]
+pub enum Request {
+    Chunk(ChunkID, oneshot::Sender<Chunk>),
+    Mesh(ChunkID, oneshot::Sender<MeshUpload>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RequestKind {
+    Chunk,
+    Mesh,
+}
+
+/// What a worker made for a request.
+#[derive(Debug)]
+pub enum Requested {
+    Chunk(Chunk),
+    Mesh(MeshUpload),
+}
+
+#[derive(Debug)]
+struct Pending<T> {
+    replies: Vec<oneshot::Sender<T>>,
+    /// Tells the worker to skip the task once nobody waits for it anymore.
+    canceled: Arc<AtomicBool>,
+    submitted: bool,
+}
+
+impl<T> Default for Pending<T> {
+    fn default() -> Self {
+        Self {
+            replies: vec![],
+            canceled: Arc::default(),
+            submitted: false,
+        }
+    }
+}
+
+/// Drops the replies nobody waits for and cancels the tasks without any left.
+fn prune<T>(pending: &mut HashMap<ChunkID, Pending<T>>) {
+    pending.retain(|_, pending| {
+        pending.replies.retain(|reply| !reply.is_closed());
+        if pending.replies.is_empty() {
+            pending.canceled.store(true, Ordering::Relaxed);
+        }
+        !pending.replies.is_empty()
+    });
+}
+
+fn complete<T: Clone>(pending: &mut HashMap<ChunkID, Pending<T>>, chunk: ChunkID, value: T) {
+    for reply in pending.remove(&chunk).into_iter().flat_map(|p| p.replies) {
+        _ = reply.send(value.clone());
+    }
+}
+
+/// The requests the engine is working on, one task per chunk and kind no matter how many wait
+/// for it.
+#[derive(Debug, Default)]
+pub struct PendingRequests {
+    chunks: HashMap<ChunkID, Pending<Chunk>>,
+    meshes: HashMap<ChunkID, Pending<MeshUpload>>,
+}
+
+impl PendingRequests {
+    /// Answers right away if the engine already has the chunk, otherwise waits for a task.
+    pub fn add(&mut self, request: Request, world: &World) {
+        match request {
+            Request::Chunk(chunk, reply) => match world.chunk(chunk) {
+                Some(data) => _ = reply.send(data.clone()),
+                None => self.chunks.entry(chunk).or_default().replies.push(reply),
+            },
+            Request::Mesh(chunk, reply) => {
+                self.meshes.entry(chunk).or_default().replies.push(reply)
+            }
+        }
+    }
+
+    /// Forgets the requests whose futures got dropped and cancels their tasks.
+    pub fn prune(&mut self) {
+        prune(&mut self.chunks);
+        prune(&mut self.meshes);
+    }
+
+    /// Hands out the requests without a task yet until `submit` returns false, e.g. because the
+    /// task queues are full.
+    pub fn submit(
+        &mut self,
+        mut submit: impl FnMut(ChunkID, RequestKind, Arc<AtomicBool>) -> bool,
+    ) {
+        let chunks = (self.chunks.iter_mut())
+            .map(|(c, p)| (*c, RequestKind::Chunk, &p.canceled, &mut p.submitted));
+        let meshes = (self.meshes.iter_mut())
+            .map(|(c, p)| (*c, RequestKind::Mesh, &p.canceled, &mut p.submitted));
+        for (chunk, kind, canceled, submitted) in chunks.chain(meshes) {
+            if *submitted {
+                continue;
+            }
+            if !submit(chunk, kind, canceled.clone()) {
+                return;
+            }
+            *submitted = true;
+        }
+    }
+
+    /// Submits the request again with the next [`PendingRequests::submit`], e.g. because its task
+    /// got dropped.
+    pub fn retry(&mut self, chunk: ChunkID, kind: RequestKind) {
+        let submitted = match kind {
+            RequestKind::Chunk => self.chunks.get_mut(&chunk).map(|p| &mut p.submitted),
+            RequestKind::Mesh => self.meshes.get_mut(&chunk).map(|p| &mut p.submitted),
+        };
+        if let Some(submitted) = submitted {
+            *submitted = false;
+        }
+    }
+
+    /// Answers everyone waiting for what a worker made.
+    pub fn complete(&mut self, chunk: ChunkID, requested: Requested) {
+        match requested {
+            Requested::Chunk(data) => complete(&mut self.chunks, chunk, data),
+            Requested::Mesh(mesh) => complete(&mut self.meshes, chunk, mesh),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len() + self.meshes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use glam::IVec3;
+
+    use crate::{Chunk, ChunkID, VoxelTypes, world::World};
+
+    use super::{ChunkRequests, PendingRequests, RequestKind, Requested};
+
+    #[tokio::test]
+    async fn same_chunk_is_generated_once() {
+        let (requests, mut rx) = ChunkRequests::new();
+        let chunk = ChunkID::new(1, IVec3::new(2, 0, -1));
+        let first = requests.request_chunk(chunk);
+        let second = requests.request_chunk(chunk);
+
+        let mut pending = PendingRequests::default();
+        let world = World::with_capacity(0);
+        while let Ok(request) = rx.try_recv() {
+            pending.add(request, &world);
+        }
+        let mut tasks = vec![];
+        pending.submit(|chunk, kind, _| {
+            tasks.push((chunk, kind));
+            true
+        });
+        assert_eq!(tasks, vec![(chunk, RequestKind::Chunk)]);
+        pending.submit(|_, _, _| panic!("submitted twice"));
+
+        let data = Chunk::from_buffer(&[VoxelTypes::Stone as u16; 32 * 32 * 32]);
+        pending.complete(chunk, Requested::Chunk(data));
+        assert_eq!(pending.len(), 0);
+        for chunk in [first.await.unwrap(), second.await.unwrap()] {
+            assert_eq!(chunk.to_buffer()[0], VoxelTypes::Stone as u16);
+        }
+    }
+
+    #[tokio::test]
+    async fn dropping_the_future_cancels_the_task() {
+        let (requests, mut rx) = ChunkRequests::new();
+        let chunk = ChunkID::new(0, IVec3::ZERO);
+        let mesh = requests.request_mesh(chunk);
+
+        let mut pending = PendingRequests::default();
+        pending.add(rx.try_recv().unwrap(), &World::with_capacity(0));
+        let mut canceled = None;
+        pending.submit(|_, _, flag| {
+            canceled = Some(flag);
+            true
+        });
+        let canceled = canceled.unwrap();
+
+        pending.prune();
+        assert!(!canceled.load(Ordering::Relaxed));
+        drop(mesh);
+        pending.prune();
+        assert!(canceled.load(Ordering::Relaxed));
+        assert_eq!(pending.len(), 0);
+
+        drop(rx);
+        assert!(requests.request_chunk(chunk).await.is_none());
+    }
+}
@@ -0,0 +1,258 @@
+use std::{cmp::Ordering, collections::BinaryHeap, sync::Arc};
+
+use parking_lot::{Mutex, RwLock};
+
+use crate::{ChunkID, Frustum};
+
+/// Chunks outside of the view count as this many times farther away than they are.
+const OUT_OF_VIEW_PENALTY: f32 = 4.;
+
+/// Work that belongs to a chunk, so the view can tell how important it is.
+pub trait ChunkTask {
+    fn chunk(&self) -> ChunkID;
+
+    /// Lower is more important. Defaults to the priority of the task's chunk.
+    fn priority(&self, view: &View) -> f32 {
+        view.priority(self.chunk())
+    }
+}
+
+/// The queues of all workers, shared so idle workers can steal from busy ones.
+pub type SharedQueues<T> = Arc<RwLock<Vec<Arc<TaskQueue<T>>>>>;
+
+/// Where the camera is and looks at, in chunks. Decides which tasks are the most important.
+#[derive(Debug, Clone)]
+pub struct View {
+    pub frustum: Frustum,
+}
+
+impl View {
+    /// Lower is more important: the distance to the chunk, penalized if it can't be seen.
+    pub fn priority(&self, chunk: ChunkID) -> f32 {
+        let distance = self.frustum.cam_pos.distance(chunk.center());
+        if self.frustum.contains(chunk) {
+            distance
+        } else {
+            distance * OUT_OF_VIEW_PENALTY
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+    priority: f32,
+    /// Keeps equally important tasks in submission order.
+    seq: u64,
+    task: T,
+}
+
+impl<T> Ord for Entry<T> {
+    /// The most important entry is the greatest, so it's on top of the heap.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .priority
+            .total_cmp(&self.priority)
+            .then(other.seq.cmp(&self.seq))
+    }
+}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+#[derive(Debug)]
+struct Heap<T> {
+    entries: BinaryHeap<Entry<T>>,
+    next_seq: u64,
+}
+
+/// The pending tasks of one worker, handed out most important first.
+#[derive(Debug)]
+pub struct TaskQueue<T> {
+    heap: Mutex<Heap<T>>,
+    cap: usize,
+}
+
+impl<T: ChunkTask> TaskQueue<T> {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            heap: Mutex::new(Heap {
+                entries: BinaryHeap::new(),
+                next_seq: 0,
+            }),
+            cap,
+        }
+    }
+
+    /// Gives the task back if the queue is full.
+    pub fn push(&self, task: T, priority: f32) -> Result<(), T> {
+        let mut heap = self.heap.lock();
+        if heap.entries.len() >= self.cap {
+            return Err(task);
+        }
+        let seq = heap.next_seq;
+        heap.next_seq += 1;
+        heap.entries.push(Entry {
+            priority,
+            seq,
+            task,
+        });
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        self.heap.lock().entries.pop().map(|entry| entry.task)
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.lock().entries.is_empty()
+    }
+
+    /// Drops every pending task.
+    pub fn clear(&self) {
+        self.heap.lock().entries.clear();
+    }
+
+    /// Calls `f` with the chunk of every pending task, in no particular order.
+    pub fn for_each_chunk(&self, mut f: impl FnMut(ChunkID)) {
+        for entry in self.heap.lock().entries.iter() {
+            f(entry.task.chunk());
+        }
+    }
+
+    /// Takes every pending task, in no particular order.
+    pub fn drain(&self) -> Vec<T> {
+        let entries = std::mem::take(&mut self.heap.lock().entries);
+        entries
+            .into_vec()
+            .into_iter()
+            .map(|entry| entry.task)
+            .collect()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// Scores every pending task again, e.g. after the camera turned.
+    pub fn reprioritize(&self, view: &View) {
+        let mut heap = self.heap.lock();
+        let mut entries = std::mem::take(&mut heap.entries).into_vec();
+        for entry in &mut entries {
+            entry.priority = entry.task.priority(view);
+        }
+        heap.entries = entries.into();
+    }
+}
+
+/// Takes the most important task of the longest queue other than the thief's own. Workers only do
+/// this once their own queue ran dry, so tasks mostly stay with the worker of their region.
+pub fn steal<T: ChunkTask>(queues: &[Arc<TaskQueue<T>>], thief: usize) -> Option<T> {
+    let (len, victim) = queues
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != thief)
+        .map(|(_, queue)| (queue.len(), queue))
+        .max_by_key(|(len, _)| *len)?;
+    if len == 0 {
+        return None;
+    }
+    victim.pop()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use std::sync::Arc;
+
+    use glam::{IVec3, Vec3};
+
+    use crate::{ChunkID, Frustum, worker::Task};
+
+    use super::{ChunkTask, TaskQueue, View, steal};
+
+    fn view(direction: Vec3) -> View {
+        View {
+            frustum: Frustum {
+                cam_pos: Vec3::splat(0.5),
+                direction,
+                fov: FRAC_PI_2,
+                aspect_ratio: 1.,
+                max_chunks: 0,
+                max_distance: 64.,
+                full_detail_range: 0.,
+            },
+        }
+    }
+
+    fn task(x: i32) -> Task {
+        let chunk = ChunkID::new(0, IVec3::new(x, 0, 0));
+        Task::GenerateChunkAndMesh {
+            chunk,
+            neighbors: Box::new([[0; 32]; 6]),
+        }
+    }
+
+    fn pop_all(queue: &TaskQueue<Task>) -> Vec<i32> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|task| task.chunk().pos.x)
+            .collect()
+    }
+
+    #[test]
+    fn visible_and_close_chunks_come_first() {
+        let view = view(Vec3::X);
+        let queue = TaskQueue::new(16);
+        for x in [-2, 5, -1, 2, 0] {
+            queue.push(task(x), view.priority(task(x).chunk())).unwrap();
+        }
+        // -1 is behind the camera, 1 away counts as 4, which still beats 5 straight ahead
+        assert_eq!(pop_all(&queue), vec![0, 2, -1, 5, -2]);
+    }
+
+    #[test]
+    fn turning_around_reorders_pending_tasks() {
+        let queue = TaskQueue::new(3);
+        let ahead = view(Vec3::X);
+        for x in [3, -3, 1] {
+            queue
+                .push(task(x), ahead.priority(task(x).chunk()))
+                .unwrap();
+        }
+        assert!(queue.push(task(9), 0.).is_err());
+
+        queue.reprioritize(&view(Vec3::NEG_X));
+        assert_eq!(pop_all(&queue), vec![-3, 1, 3]);
+    }
+
+    #[test]
+    fn thieves_take_from_the_longest_other_queue() {
+        let queues: Vec<_> = (0..3).map(|_| Arc::new(TaskQueue::new(8))).collect();
+        for x in [4, 1, 6] {
+            queues[1].push(task(x), x as f32).unwrap();
+        }
+        queues[2].push(task(9), 9.).unwrap();
+
+        assert_eq!(steal(&queues, 0).map(|task| task.chunk().pos.x), Some(1));
+        assert_eq!(steal(&queues, 1).map(|task| task.chunk().pos.x), Some(9));
+        assert_eq!(queues[1].len(), 2);
+
+        queues[1].clear();
+        assert!(steal(&queues, 2).is_none());
+    }
+}
@@ -0,0 +1,378 @@
+use std::{collections::HashMap, fmt, fs, path::Path};
+
+use glam::{IVec3, UVec3};
+
+use crate::{
+    VoxelType, VoxelTypes,
+    bitvec::PackedVec32,
+    error::{FormatError, FormatResult},
+    formats::bytes::ByteReader,
+    world::{VoxelEdit, World},
+};
+
+const MAGIC: [u8; 4] = *b"VXSC";
+const VERSION: u16 = 1;
+
+/// The most voxels a schematic holds, 512 MiB of voxel types.
+pub const MAX_SCHEMATIC_VOLUME: usize = 1 << 28;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// A copied region of the world, stored as a palette and bit-packed palette indices indexed by
+/// `x * size.y * size.z + y * size.z + z`.
+#[derive(Clone, Debug)]
+pub struct Schematic {
+    size: UVec3,
+    palette: Vec<VoxelType>,
+    indices: PackedVec32,
+}
+
+/// The region given to [`Schematic::copy`] holds more than [`MAX_SCHEMATIC_VOLUME`] voxels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegionTooLarge {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+impl fmt::Display for RegionTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the region from {} to {} is too large for a schematic",
+            self.min, self.max
+        )
+    }
+}
+
+impl std::error::Error for RegionTooLarge {}
+
+impl PartialEq for Schematic {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size && self.voxels().eq(other.voxels())
+    }
+}
+
+impl Schematic {
+    /// Copies the voxels between `min` and `max` inclusive. Voxels that aren't loaded are copied
+    /// as air.
+    pub fn copy(world: &World, min: IVec3, max: IVec3) -> Result<Self, RegionTooLarge> {
+        let (min, max) = (min.min(max), min.max(max));
+        // the corners may lie on opposite ends of the i32 range
+        let side = |axis: usize| (max[axis] as i64 - min[axis] as i64 + 1) as usize;
+        let sides = [side(0), side(1), side(2)];
+        sides
+            .iter()
+            .try_fold(1_usize, |volume, side| volume.checked_mul(*side))
+            .filter(|volume| *volume <= MAX_SCHEMATIC_VOLUME)
+            .ok_or(RegionTooLarge { min, max })?;
+
+        // every side is at most the volume, so it fits into a u32, and `min + pos` stays below max
+        let size = UVec3::from_array(sides.map(|side| side as u32));
+        Ok(Self::from_fn(size, |pos| {
+            world
+                .get(min + pos.as_ivec3())
+                .unwrap_or(VoxelTypes::Air as u16)
+        }))
+    }
+
+    pub(crate) fn from_fn(size: UVec3, mut voxel_at: impl FnMut(UVec3) -> VoxelType) -> Self {
+        let mut palette = Vec::new();
+        let mut palette_ids = HashMap::new();
+        let voxels: Vec<u32> = positions(size)
+            .map(|pos| {
+                let voxel = voxel_at(pos);
+                *palette_ids.entry(voxel).or_insert_with(|| {
+                    palette.push(voxel);
+                    palette.len() as u32 - 1
+                })
+            })
+            .collect();
+
+        let mut indices = PackedVec32::new(voxels.len(), bits_for(palette.len()));
+        for (i, id) in voxels.into_iter().enumerate() {
+            indices.set(i, id);
+        }
+        Self {
+            size,
+            palette,
+            indices,
+        }
+    }
+
+    pub fn size(&self) -> UVec3 {
+        self.size
+    }
+
+    pub fn get(&self, pos: UVec3) -> VoxelType {
+        self.palette[self.indices.get(self.index(pos)) as usize]
+    }
+
+    fn index(&self, pos: UVec3) -> usize {
+        let [x, y, z] = pos.to_array().map(|axis| axis as usize);
+        (x * self.size.y as usize + y) * self.size.z as usize + z
+    }
+
+    fn voxels(&self) -> impl Iterator<Item = VoxelType> + '_ {
+        positions(self.size).map(|pos| self.get(pos))
+    }
+
+    /// Rotates the schematic by `quarter_turns` times 90° counterclockwise around the axis.
+    pub fn rotated(&self, axis: Axis, quarter_turns: u8) -> Self {
+        let mut rotated = self.clone();
+        for _ in 0..quarter_turns % 4 {
+            let UVec3 {
+                x: sx,
+                y: sy,
+                z: sz,
+            } = rotated.size;
+            let (size, source): (UVec3, fn(UVec3, UVec3) -> UVec3) = match axis {
+                Axis::X => (UVec3::new(sx, sz, sy), |pos, old| {
+                    UVec3::new(pos.x, pos.z, old.z - 1 - pos.y)
+                }),
+                Axis::Y => (UVec3::new(sz, sy, sx), |pos, old| {
+                    UVec3::new(old.x - 1 - pos.z, pos.y, pos.x)
+                }),
+                Axis::Z => (UVec3::new(sy, sx, sz), |pos, old| {
+                    UVec3::new(pos.y, old.y - 1 - pos.x, pos.z)
+                }),
+            };
+            let old_size = rotated.size;
+            rotated = Self::from_fn(size, |pos| rotated.get(source(pos, old_size)));
+        }
+        rotated
+    }
+
+    /// Mirrors the schematic along the axis.
+    pub fn mirrored(&self, axis: Axis) -> Self {
+        let size = self.size;
+        Self::from_fn(size, |mut pos| {
+            match axis {
+                Axis::X => pos.x = size.x - 1 - pos.x,
+                Axis::Y => pos.y = size.y - 1 - pos.y,
+                Axis::Z => pos.z = size.z - 1 - pos.z,
+            }
+            self.get(pos)
+        })
+    }
+
+    /// Pastes the schematic with its minimum corner at `origin`. With `keep_existing` air in the
+    /// schematic leaves the world as it is. Returns the voxels that changed.
+    pub fn paste(&self, world: &mut World, origin: IVec3, keep_existing: bool) -> Vec<VoxelEdit> {
+        world.set_many(
+            positions(self.size)
+                .map(|pos| (origin + pos.as_ivec3(), self.get(pos)))
+                .filter(|(_, voxel)| !keep_existing || *voxel != VoxelTypes::Air as u16),
+        )
+    }
+
+    /// The header is the magic `VXSC`, a version, the size and the palette, followed by the bits
+    /// per index and the packed words. Everything is little endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        for axis in self.size.to_array() {
+            out.extend_from_slice(&axis.to_le_bytes());
+        }
+        out.extend_from_slice(&(self.palette.len() as u32).to_le_bytes());
+        for voxel in &self.palette {
+            out.extend_from_slice(&voxel.to_le_bytes());
+        }
+        out.push(self.indices.bits_per_elem());
+        out.extend_from_slice(&(self.indices.words().len() as u32).to_le_bytes());
+        for word in self.indices.words() {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> FormatResult<Self> {
+        let mut reader = ByteReader::new(data);
+        if reader.take(4)? != MAGIC {
+            return Err(FormatError::corrupt("not a schematic"));
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(FormatError::unsupported(format!(
+                "schematic version {version}"
+            )));
+        }
+
+        let size = UVec3::new(reader.u32()?, reader.u32()?, reader.u32()?);
+        let palette_len = reader.u32()? as usize;
+        let palette = (0..palette_len)
+            .map(|_| reader.u16())
+            .collect::<FormatResult<Vec<VoxelType>>>()?;
+        let bits = reader.u8()?;
+        let word_count = reader.u32()? as usize;
+        let words = (0..word_count)
+            .map(|_| reader.u32())
+            .collect::<FormatResult<Vec<u32>>>()?;
+
+        if palette.is_empty() || !(1..=32).contains(&bits) {
+            return Err(FormatError::corrupt("invalid palette"));
+        }
+        let len = (size.x as usize)
+            .checked_mul(size.y as usize)
+            .and_then(|len| len.checked_mul(size.z as usize))
+            .filter(|len| *len <= MAX_SCHEMATIC_VOLUME)
+            .ok_or_else(|| FormatError::corrupt(format!("a {size} schematic is too large")))?;
+        let indices = PackedVec32::from_words(len, bits, words)
+            .ok_or_else(|| FormatError::corrupt("too few voxels"))?;
+        if (0..len).any(|i| indices.get(i) as usize >= palette.len()) {
+            return Err(FormatError::corrupt("palette index out of range"));
+        }
+
+        Ok(Self {
+            size,
+            palette,
+            indices,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> FormatResult<()> {
+        Ok(fs::write(path, self.to_bytes())?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> FormatResult<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+fn positions(size: UVec3) -> impl Iterator<Item = UVec3> {
+    (0..size.x).flat_map(move |x| {
+        (0..size.y).flat_map(move |y| (0..size.z).map(move |z| UVec3::new(x, y, z)))
+    })
+}
+
+fn bits_for(palette_len: usize) -> u8 {
+    (usize::BITS - palette_len.saturating_sub(1).leading_zeros()).max(1) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3};
+
+    use crate::{Chunk, ChunkID, VoxelTypes, voxel::fill, world::World};
+
+    use super::{Axis, Schematic};
+
+    const AIR: u16 = VoxelTypes::Air as u16;
+    const STONE: u16 = VoxelTypes::Stone as u16;
+    const DIRT: u16 = VoxelTypes::Dirt0 as u16;
+    const LAMP: u16 = VoxelTypes::Lamp as u16;
+
+    fn world() -> World {
+        let mut world = World::default();
+        world.insert_chunk(ChunkID::new(0, IVec3::ZERO), Chunk::from_buffer(&fill(AIR)));
+        world
+    }
+
+    /// An L shape of stone with a lamp on top, 3 by 2 by 2.
+    fn sample() -> Schematic {
+        let mut world = world();
+        world.set(IVec3::new(0, 0, 0), STONE);
+        world.set(IVec3::new(1, 0, 0), STONE);
+        world.set(IVec3::new(2, 0, 0), STONE);
+        world.set(IVec3::new(2, 0, 1), DIRT);
+        world.set(IVec3::new(0, 1, 0), LAMP);
+        Schematic::copy(&world, IVec3::ZERO, IVec3::new(2, 1, 1)).unwrap()
+    }
+
+    #[test]
+    fn copy_and_paste_round_trip() {
+        let schematic = sample();
+        assert_eq!(schematic.size(), UVec3::new(3, 2, 2));
+
+        let mut world = world();
+        let edits = schematic.paste(&mut world, IVec3::new(10, 10, 10), false);
+        assert_eq!(edits.len(), 5);
+        assert_eq!(world.get(IVec3::new(12, 10, 11)), Some(DIRT));
+        assert_eq!(
+            Schematic::copy(&world, IVec3::splat(10), IVec3::new(12, 11, 11)).unwrap(),
+            schematic
+        );
+    }
+
+    #[test]
+    fn air_can_keep_the_existing_voxels() {
+        let mut world = world();
+        world.set(IVec3::new(1, 1, 1), DIRT);
+        sample().paste(&mut world, IVec3::ZERO, true);
+        assert_eq!(world.get(IVec3::new(1, 1, 1)), Some(DIRT));
+        sample().paste(&mut world, IVec3::ZERO, false);
+        assert_eq!(world.get(IVec3::new(1, 1, 1)), Some(AIR));
+    }
+
+    #[test]
+    fn rotations_move_voxels_around_the_axis() {
+        let schematic = sample();
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            assert_eq!(schematic.rotated(axis, 4), schematic);
+            assert_eq!(
+                schematic.rotated(axis, 1).rotated(axis, 3),
+                schematic,
+                "{axis:?}"
+            );
+        }
+
+        let rotated = schematic.rotated(Axis::Y, 1);
+        assert_eq!(rotated.size(), UVec3::new(2, 2, 3));
+        // the row along +x now runs along -z
+        assert_eq!(rotated.get(UVec3::new(0, 0, 0)), STONE);
+        assert_eq!(rotated.get(UVec3::new(0, 0, 2)), STONE);
+        assert_eq!(rotated.get(UVec3::new(1, 0, 0)), DIRT);
+        assert_eq!(rotated.get(UVec3::new(0, 1, 2)), LAMP);
+    }
+
+    #[test]
+    fn mirroring_twice_is_the_identity() {
+        let schematic = sample();
+        let mirrored = schematic.mirrored(Axis::X);
+        assert_eq!(mirrored.get(UVec3::new(0, 0, 1)), DIRT);
+        assert_eq!(mirrored.get(UVec3::new(2, 1, 0)), LAMP);
+        assert_eq!(mirrored.mirrored(Axis::X), schematic);
+    }
+
+    #[test]
+    fn files_round_trip_and_reject_garbage() {
+        let schematic = sample();
+        assert_eq!(
+            Schematic::from_bytes(&schematic.to_bytes()).unwrap(),
+            schematic
+        );
+
+        let bytes = schematic.to_bytes();
+        assert!(Schematic::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Schematic::from_bytes(b"not a schematic").is_err());
+    }
+
+    #[test]
+    fn oversized_files_are_corrupt() {
+        let bytes = sample().to_bytes();
+        assert_eq!(Schematic::from_bytes(&bytes).unwrap(), sample());
+
+        // sizes follow the magic and the version
+        for side in [u32::MAX, 1 << 21] {
+            let mut bytes = bytes.clone();
+            for axis in 0..3 {
+                let start = 6 + axis * 4;
+                bytes[start..start + 4].copy_from_slice(&side.to_le_bytes());
+            }
+            assert!(Schematic::from_bytes(&bytes).is_err(), "{side}");
+        }
+    }
+
+    #[test]
+    fn huge_regions_arent_copied() {
+        let world = world();
+        assert!(Schematic::copy(&world, IVec3::splat(i32::MIN), IVec3::splat(i32::MAX)).is_err());
+        assert!(Schematic::copy(&world, IVec3::ZERO, IVec3::splat(1 << 10)).is_err());
+        let max = Schematic::copy(&world, IVec3::splat(i32::MAX), IVec3::splat(i32::MAX)).unwrap();
+        assert_eq!(max.size(), UVec3::ONE);
+    }
+}
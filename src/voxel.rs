@@ -1,17 +1,60 @@
-use crate::{VoxelType, chunk::CHUNK_VOLUME, mesh::TextureID};
+use glam::IVec3;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    VoxelType,
+    block_updates::{Behaviour, UpdateContext},
+    chunk::CHUNK_VOLUME,
+    mesh::{MAX_TEXTURES, TextureID},
+};
 
 #[repr(u16)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 pub enum VoxelTypes {
     Air = 1,
     CrackedStone,
     Stone,
     Dirt0,
     Dirt1,
+    Lamp,
+    Water,
+    Lava,
+    Grass,
+    Sand,
+    Gravel,
+}
+
+/// Fluids keep their fill level in the upper four bits, the lower twelve bits are the type. A
+/// level of `0` in the voxel means the fluid fills the whole voxel.
+const LEVEL_SHIFT: u16 = 12;
+
+pub const MAX_FLUID_LEVEL: u8 = 8;
+
+/// The type of the voxel without any state.
+pub fn kind(voxel: VoxelType) -> VoxelType {
+    voxel & 0x0FFF
+}
+
+pub fn is_fluid(voxel: VoxelType) -> bool {
+    let kind = kind(voxel);
+    kind == VoxelTypes::Water as u16 || kind == VoxelTypes::Lava as u16
+}
+
+/// A fluid voxel filled to `level`, which ranges from 1 to `MAX_FLUID_LEVEL`.
+pub fn fluid(kind: VoxelType, level: u8) -> VoxelType {
+    kind | ((level % MAX_FLUID_LEVEL) as u16) << LEVEL_SHIFT
+}
+
+pub fn fluid_level(voxel: VoxelType) -> u8 {
+    match (voxel >> LEVEL_SHIFT) as u8 {
+        0 => MAX_FLUID_LEVEL,
+        level => level,
+    }
 }
 
 pub fn is_physically_solid_u32(voxel: VoxelType) -> u32 {
-    if voxel != VoxelTypes::Air as u16 {
+    if voxel != VoxelTypes::Air as u16 && !is_fluid(voxel) {
         0b1000_0000_0000_0000_0000_0000_0000_0000
     } else {
         0
@@ -26,6 +69,81 @@ pub fn is_solid_u32(voxel: VoxelType) -> u32 {
     }
 }
 
+/// Whether light passes through the voxel.
+pub fn is_transparent(voxel: VoxelType) -> bool {
+    voxel == VoxelTypes::Air as u16 || kind(voxel) == VoxelTypes::Water as u16
+}
+
+/// The block light level the voxel emits.
+pub fn emission(voxel: VoxelType) -> u8 {
+    match kind(voxel) {
+        kind if kind == VoxelTypes::Lamp as u16 => 14,
+        kind if kind == VoxelTypes::Lava as u16 => 12,
+        _ => 0,
+    }
+}
+
+/// How the voxels of a type change over time.
+pub fn behaviour(voxel: VoxelType) -> Behaviour {
+    match kind(voxel) {
+        kind if kind == VoxelTypes::Grass as u16 => Behaviour {
+            random_tick: Some(grass_random_tick),
+            scheduled: Some((grass_neighbor_changed, 10)),
+        },
+        kind if kind == VoxelTypes::Sand as u16 || kind == VoxelTypes::Gravel as u16 => Behaviour {
+            random_tick: None,
+            scheduled: Some((fall_if_unsupported, 2)),
+        },
+        _ => Behaviour::default(),
+    }
+}
+
+/// Grass spreads onto uncovered dirt next to it.
+fn grass_random_tick(ctx: &mut UpdateContext, pos: IVec3) {
+    if grass_covered(ctx, pos) {
+        return;
+    }
+    let target = pos
+        + IVec3::new(
+            ctx.rng.gen_range(-1..=1),
+            ctx.rng.gen_range(-1..=1),
+            ctx.rng.gen_range(-1..=1),
+        );
+    let is_dirt = |voxel| voxel == VoxelTypes::Dirt0 as u16 || voxel == VoxelTypes::Dirt1 as u16;
+    if ctx.world.get(target).is_some_and(is_dirt)
+        && ctx.world.get(target + IVec3::Y).is_some_and(is_transparent)
+    {
+        ctx.world.set(target, VoxelTypes::Grass as u16);
+    }
+}
+
+fn grass_neighbor_changed(ctx: &mut UpdateContext, pos: IVec3) {
+    grass_covered(ctx, pos);
+}
+
+/// Turns grass without light from above into dirt. Returns whether it did.
+fn grass_covered(ctx: &mut UpdateContext, pos: IVec3) -> bool {
+    let covered = ctx
+        .world
+        .get(pos + IVec3::Y)
+        .is_some_and(|above| !is_transparent(above));
+    if covered {
+        ctx.world.set(pos, VoxelTypes::Dirt0 as u16);
+    }
+    covered
+}
+
+/// Turns the voxel into a falling block if there is nothing below it.
+fn fall_if_unsupported(ctx: &mut UpdateContext, pos: IVec3) {
+    let unsupported = ctx
+        .world
+        .get(pos - IVec3::Y)
+        .is_some_and(|below| is_physically_solid_u32(below) == 0);
+    if unsupported {
+        ctx.falling.spawn(ctx.world, pos);
+    }
+}
+
 #[allow(unused)]
 /// orientations
 /// 0 = -x
@@ -34,10 +152,19 @@ pub fn is_solid_u32(voxel: VoxelType) -> u32 {
 /// 3 = +y
 /// 4 = -z
 /// 5 = +z
+///
+/// Voxels can hold any type, e.g. from a schematic or an import. Types without a texture that
+/// fits into an instance get [`MISSING_TEXTURE`].
 pub fn texture_id(voxel: VoxelType, orientation: u8) -> TextureID {
-    voxel as u16 - 2
+    match kind(voxel).checked_sub(2) {
+        Some(texture) if texture < MAX_TEXTURES => texture,
+        _ => MISSING_TEXTURE,
+    }
 }
 
+/// What unknown voxel types look like.
+pub const MISSING_TEXTURE: TextureID = VoxelTypes::Stone as u16 - 2;
+
 pub fn fill(fill: VoxelType) -> [VoxelType; CHUNK_VOLUME] {
     [fill; _]
 }
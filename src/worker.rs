@@ -1,19 +1,28 @@
-use std::sync::Arc;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
 
 use glam::UVec3;
 use parking_lot::RwLock;
+use tracing::trace_span;
 
 use crate::{
     Chunk, ChunkID, ComposableGenerator, Generator,
     cam_controller::CamController,
     chunk::{DenseChunk, idx_to_coord, lod_at_dst},
     config::WorkerConfig,
+    light::PaddedLight,
     mesh::MeshUpload,
     meshing::{
         BitMap2D, BitMap3D, generate_mesh, get_axis_aligned_solid_maps, get_edges, map_visible,
     },
-    mpsc, spsc, voxel,
-    worker_pool::Runable,
+    metrics::Counters,
+    mpsc,
+    requests::{RequestKind, Requested},
+    scheduler::{ChunkTask, SharedQueues, TaskQueue, View, steal},
+    spsc, voxel,
+    worker_pool::{Runable, WorkerID, WorkerSignal},
 };
 
 #[derive(Debug)]
@@ -21,7 +30,10 @@ pub struct Context {
     pub config: WorkerConfig,
     pub config_queue: spsc::Consumer<WorkerConfig>,
 
-    pub task_queues: Vec<spsc::Consumer<Task>>,
+    pub id: WorkerID,
+    pub task_queue: Arc<TaskQueue<Task>>,
+    /// The queues of every worker, indexed by their id, to steal from once `task_queue` is empty.
+    pub peers: SharedQueues<Task>,
     pub player_pos: Arc<RwLock<CamController>>,
 
     pub world_generator: ComposableGenerator,
@@ -34,6 +46,12 @@ pub struct Context {
     pub solid_map_tx: mpsc::Sender<(ChunkID, Box<[BitMap2D; 6]>)>,
 
     pub meshes: mpsc::Sender<(ChunkID, MeshUpload)>,
+    pub requests: mpsc::Sender<(ChunkID, Requested)>,
+
+    pub counters: Arc<Counters>,
+    /// Retired when the pool shrinks, stopped when it shuts down. Results only get dropped once
+    /// stopped.
+    pub signal: Arc<WorkerSignal>,
 }
 
 #[derive(Debug)]
@@ -42,6 +60,47 @@ pub enum Task {
         chunk: ChunkID,
         neighbors: Box<[BitMap2D; 6]>,
     },
+    /// Meshes a full detail chunk again after its voxels or its light changed.
+    MeshChunk {
+        chunk: ChunkID,
+        data: Box<DenseChunk>,
+        light: Box<PaddedLight>,
+        neighbors: Box<[BitMap2D; 6]>,
+    },
+    /// Rebuilds the collider of an edited full detail chunk without meshing it, for headless
+    /// engines.
+    Collider {
+        chunk: ChunkID,
+        data: Box<DenseChunk>,
+    },
+    /// Someone awaits this chunk, see [`crate::ChunkRequests`].
+    Request {
+        chunk: ChunkID,
+        kind: RequestKind,
+        /// The voxels and light of the chunk if the engine loaded it, so meshes show its edits.
+        loaded: Option<(Box<DenseChunk>, Box<PaddedLight>)>,
+        neighbors: Box<[BitMap2D; 6]>,
+        canceled: Arc<AtomicBool>,
+    },
+}
+
+impl ChunkTask for Task {
+    fn chunk(&self) -> ChunkID {
+        match self {
+            Self::GenerateChunkAndMesh { chunk, .. }
+            | Self::MeshChunk { chunk, .. }
+            | Self::Collider { chunk, .. }
+            | Self::Request { chunk, .. } => *chunk,
+        }
+    }
+
+    /// Awaited chunks go before everything the camera streams in.
+    fn priority(&self, view: &View) -> f32 {
+        match self {
+            Self::Request { .. } => f32::NEG_INFINITY,
+            _ => view.priority(self.chunk()),
+        }
+    }
 }
 
 impl Runable for Context {
@@ -51,21 +110,49 @@ impl Runable for Context {
                 self.config = config_update
             }
 
-            let mut task_queues = self.task_queues.iter_mut();
-
-            let task = loop {
-                let Some(task_queue) = task_queues.next() else {
-                    return if i == 0 { false } else { true };
-                };
-
-                if let Ok(task) = task_queue.pop() {
-                    break task;
-                }
+            if self.signal.is_retired() {
+                return i != 0;
+            }
+            let Some(task) = self
+                .task_queue
+                .pop()
+                .or_else(|| steal(&self.peers.read(), self.id))
+            else {
+                return i != 0;
             };
 
             use Task::*;
             match task {
-                GenerateChunkAndMesh { chunk, neighbors } => self.generate_chunk(chunk, neighbors),
+                GenerateChunkAndMesh { chunk, neighbors } => {
+                    let _span = trace_span!("generate_chunk", ?chunk).entered();
+                    self.generate_chunk(chunk, neighbors)
+                }
+                MeshChunk {
+                    chunk,
+                    data,
+                    light,
+                    neighbors,
+                } => {
+                    let _span = trace_span!("mesh_chunk", ?chunk).entered();
+                    self.mesh_chunk(chunk, &data, &light, &neighbors)
+                }
+                Collider { chunk, data } => {
+                    let _span = trace_span!("collider", ?chunk).entered();
+                    self.submit_colliders(chunk, &data);
+                }
+                Request {
+                    chunk,
+                    kind,
+                    loaded,
+                    neighbors,
+                    canceled,
+                } => {
+                    if canceled.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    let _span = trace_span!("request", ?chunk, ?kind).entered();
+                    self.serve_request(chunk, kind, loaded, &neighbors)
+                }
             }
         }
         unreachable!()
@@ -73,6 +160,15 @@ impl Runable for Context {
 }
 
 impl Context {
+    /// Hands a result to the engine, waiting for room if it falls behind. Retired workers still
+    /// deliver, as the engine considers the chunk in flight until then; only once the pool shuts
+    /// down the result gets dropped.
+    fn send<T>(&self, queue: &mpsc::Sender<T>, value: T) {
+        if let Ok(true) = queue.push_with_backoff(value, || self.signal.is_stopped()) {
+            Counters::count(&self.counters.output_stalls);
+        }
+    }
+
     fn gets_canceled(&self, chunk: ChunkID) -> bool {
         let actual_lod = lod_at_dst(
             self.config.full_detail_distance,
@@ -82,9 +178,8 @@ impl Context {
         if chunk.lod >= actual_lod + self.config.task_cancelation_lod_threshold
             || chunk.lod + self.config.task_cancelation_lod_threshold <= actual_lod
         {
-            self.canceled_tasks
-                .push(chunk)
-                .expect("the canceled task submission queue is full (shouldn't)");
+            Counters::count(&self.counters.cancellations);
+            self.send(&self.canceled_tasks, chunk);
             true
         } else {
             false
@@ -96,29 +191,81 @@ impl Context {
             return;
         }
 
-        let data = self.world_generator.generate(chunk);
+        let data = (self.counters.generate).time(|| self.world_generator.generate(chunk));
 
-        let collider = Box::new(get_z_aligned_collider(&data));
-        self.collider_tx
-            .push((chunk, collider))
-            .expect("the collider submission queue is full (shouldn't)");
+        // full detail chunks get meshed by the engine once they are lit
+        if chunk.lod == 0 {
+            self.submit_colliders(chunk, &data);
+            self.send(&self.chunk_tx, (chunk, Chunk::from_buffer(&data)));
+        } else {
+            self.mesh(chunk, &data, None, &neighbors);
+        }
+    }
 
-        let solid_maps = Box::new(get_axis_aligned_solid_maps(&data));
-        let mesh = generate_mesh(&data, map_visible(&solid_maps, &neighbors));
+    pub fn mesh_chunk(
+        &mut self,
+        chunk: ChunkID,
+        data: &DenseChunk,
+        light: &PaddedLight,
+        neighbors: &[BitMap2D; 6],
+    ) {
+        self.mesh(chunk, data, Some(light), neighbors);
+    }
+
+    fn serve_request(
+        &self,
+        chunk: ChunkID,
+        kind: RequestKind,
+        loaded: Option<(Box<DenseChunk>, Box<PaddedLight>)>,
+        neighbors: &[BitMap2D; 6],
+    ) {
+        let (data, light) = match loaded {
+            Some((data, light)) => (data, Some(light)),
+            None => (
+                Box::new((self.counters.generate).time(|| self.world_generator.generate(chunk))),
+                None,
+            ),
+        };
+        let requested = match kind {
+            RequestKind::Chunk => Requested::Chunk(Chunk::from_buffer(&data)),
+            RequestKind::Mesh => {
+                let solid_maps = get_axis_aligned_solid_maps(&data);
+                let mesh = (self.counters.mesh).time(|| {
+                    generate_mesh(&data, map_visible(&solid_maps, neighbors), light.as_deref())
+                });
+                Requested::Mesh(mesh.bytes())
+            }
+        };
+        self.send(&self.requests, (chunk, requested));
+    }
 
-        self.meshes
-            .push((chunk, mesh.bytes()))
-            .expect("the mesh submission queue is full (shouldn't)");
+    fn mesh(
+        &self,
+        chunk: ChunkID,
+        data: &DenseChunk,
+        light: Option<&PaddedLight>,
+        neighbors: &[BitMap2D; 6],
+    ) {
+        let solid_maps = self.submit_colliders(chunk, data);
+        let mesh = (self.counters.mesh)
+            .time(|| generate_mesh(data, map_visible(&solid_maps, neighbors), light));
 
-        self.solid_map_tx
-            .push((chunk, Box::new(get_edges(*solid_maps))))
-            .expect("the solid map submission queue is full (shouldn't)");
+        self.send(&self.meshes, (chunk, mesh.bytes()));
+    }
 
-        if chunk.lod == 0 {
-            self.chunk_tx
-                .push((chunk, Chunk::from_buffer(&data)))
-                .expect("the chunk submission queue is full (shouldn't)");
-        }
+    /// Submits the collider and the solid edges of the chunk and returns its solid maps.
+    fn submit_colliders(&self, chunk: ChunkID, data: &DenseChunk) -> [BitMap3D; 3] {
+        let (collider, solid_maps, edges) = self.counters.collider.time(|| {
+            let solid_maps = get_axis_aligned_solid_maps(data);
+            (
+                Box::new(get_z_aligned_collider(data)),
+                solid_maps,
+                Box::new(get_edges(solid_maps)),
+            )
+        });
+        self.send(&self.collider_tx, (chunk, collider));
+        self.send(&self.solid_map_tx, (chunk, edges));
+        solid_maps
     }
 }
 
@@ -1,54 +1,285 @@
 use std::{
     io,
     marker::PhantomData,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
     thread,
     time::{Duration, Instant},
 };
 
+use parking_lot::{Condvar, Mutex};
+
+/// How long an idle worker sleeps if nobody wakes it. Only a safety net, submitting a task wakes
+/// its worker right away.
+const PARK_TIMEOUT: Duration = Duration::from_millis(100);
+
 pub trait Runable {
     fn execute_tasks(&mut self) -> bool; // returns true if it was able to do something
 }
 
 pub type WorkerID = usize;
 
+/// Wakes a parked worker when there's something new to do and tells it when to stop.
+#[derive(Debug, Default)]
+pub struct WorkerSignal {
+    /// The worker takes no new tasks, but still hands out the results of its current one.
+    retired: AtomicBool,
+    /// Nobody reads the results anymore, so the worker drops them instead of waiting.
+    stop: AtomicBool,
+    /// Set by `unpark` so a wake up right before parking isn't lost.
+    woken: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl WorkerSignal {
+    /// Blocks until `unpark` or the timeout, unless it was unparked since the last call.
+    pub fn park(&self, timeout: Duration) {
+        let mut woken = self.woken.lock();
+        if !*woken {
+            self.condvar.wait_for(&mut woken, timeout);
+        }
+        *woken = false;
+    }
+
+    pub fn unpark(&self) {
+        *self.woken.lock() = true;
+        self.condvar.notify_one();
+    }
+
+    /// Tells the worker to return after its current task.
+    pub fn retire(&self) {
+        self.retired.store(true, Ordering::Relaxed);
+        self.unpark();
+    }
+
+    /// Tells the worker to return after its current task and to drop results it can't hand out.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.retire();
+    }
+
+    pub fn is_retired(&self) -> bool {
+        self.retired.load(Ordering::Relaxed)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+}
+
 #[derive(Debug)]
 pub struct Threadpool<C: Runable> {
     _phantom: PhantomData<C>,
-    _workers: Vec<thread::JoinHandle<()>>,
+    workers: Vec<(thread::JoinHandle<()>, Arc<WorkerSignal>)>,
+    /// Retired workers that might still be finishing their last task.
+    retired: Vec<(thread::JoinHandle<()>, Arc<WorkerSignal>)>,
 }
 
 impl<C: Runable + Send + 'static> Threadpool<C> {
+    /// The context of each worker gets the signal of its worker, so whoever hands it tasks can
+    /// wake it and it can give up on long waits once stopped.
     pub fn new(
         num_of_workers: usize,
-        mut context: impl FnMut(usize) -> C,
+        mut context: impl FnMut(WorkerID, &Arc<WorkerSignal>) -> C,
     ) -> Result<Self, io::Error> {
-        let mut workers: Vec<thread::JoinHandle<()>> = Vec::with_capacity(num_of_workers);
-
-        for i in 0..num_of_workers {
-            let mut context = context(i);
-
-            let worker = thread::Builder::new()
-                .name(format!("worker {i}"))
-                .spawn(move || {
-                    let mut time_since_task = Instant::now();
-                    loop {
-                        if context.execute_tasks() {
-                            time_since_task = Instant::now();
-                        }
-
-                        let time_since_task = time_since_task.elapsed().as_micros();
-                        if time_since_task > 10 {
-                            thread::sleep(Duration::from_micros(time_since_task.min(1000) as u64));
-                        }
+        let mut pool = Self {
+            _phantom: PhantomData,
+            workers: Vec::with_capacity(num_of_workers),
+            retired: vec![],
+        };
+
+        // dropping the pool on error stops the workers spawned so far
+        for _ in 0..num_of_workers {
+            pool.spawn_worker(&mut context)?;
+        }
+
+        Ok(pool)
+    }
+
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    /// Adds a worker with the next id.
+    pub fn spawn_worker(
+        &mut self,
+        context: impl FnOnce(WorkerID, &Arc<WorkerSignal>) -> C,
+    ) -> Result<(), io::Error> {
+        let id = self.workers.len();
+        let signal = Arc::new(WorkerSignal::default());
+        let mut context = context(id, &signal);
+
+        let worker_signal = signal.clone();
+        let worker = thread::Builder::new()
+            .name(format!("worker {id}"))
+            .spawn(move || {
+                while !worker_signal.is_retired() {
+                    if !context.execute_tasks() {
+                        worker_signal.park(PARK_TIMEOUT);
                     }
-                })?;
+                }
+            })?;
+
+        self.workers.push((worker, signal));
+        Ok(())
+    }
+
+    /// Retires the worker with the highest id without waiting for it to finish its current task,
+    /// whose results still get handed out. Returns false if there's none left.
+    pub fn retire_worker(&mut self) -> bool {
+        self.retired.retain(|(worker, _)| !worker.is_finished());
+        let Some((worker, signal)) = self.workers.pop() else {
+            return false;
+        };
+        signal.retire();
+        self.retired.push((worker, signal));
+        true
+    }
 
-            workers.push(worker);
+    /// Stops the workers and joins them. Returns whether all of them finished within the timeout;
+    /// the rest are left to finish on their own.
+    pub fn shutdown(mut self, timeout: Duration) -> bool {
+        while self.retire_worker() {}
+        for (_, signal) in &self.retired {
+            signal.stop();
         }
 
-        Ok(Self {
-            _phantom: PhantomData::default(),
-            _workers: workers,
+        let deadline = Instant::now() + timeout;
+        while self.retired.iter().any(|(worker, _)| !worker.is_finished()) {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        // a worker that panicked has stopped too
+        self.retired
+            .drain(..)
+            .all(|(worker, _)| worker.join().is_ok())
+    }
+}
+
+impl<C: Runable> Drop for Threadpool<C> {
+    /// Doesn't wait for the workers, but makes sure they don't run forever.
+    fn drop(&mut self) {
+        for (_, signal) in self.workers.iter().chain(&self.retired) {
+            signal.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+        },
+        thread,
+        time::Duration,
+    };
+
+    use super::{Runable, Threadpool, WorkerSignal};
+
+    struct Counter {
+        runs: Arc<AtomicUsize>,
+        /// Keeps the worker busy, ignoring the stop signal, until set.
+        release: Arc<AtomicBool>,
+    }
+
+    impl Runable for Counter {
+        fn execute_tasks(&mut self) -> bool {
+            self.runs.fetch_add(1, Ordering::Relaxed);
+            while !self.release.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            true
+        }
+    }
+
+    fn pool(released: bool) -> (Threadpool<Counter>, Arc<AtomicUsize>, Arc<AtomicBool>) {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let release = Arc::new(AtomicBool::new(released));
+        let pool = Threadpool::new(3, |_, _| Counter {
+            runs: runs.clone(),
+            release: release.clone(),
+        })
+        .unwrap();
+        while runs.load(Ordering::Relaxed) < 3 {
+            thread::yield_now();
+        }
+        (pool, runs, release)
+    }
+
+    #[test]
+    fn shutdown_joins_all_workers() {
+        let (pool, runs, _) = pool(true);
+        assert!(pool.shutdown(Duration::from_secs(5)));
+
+        let after = runs.load(Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(runs.load(Ordering::Relaxed), after);
+    }
+
+    #[test]
+    fn unpark_before_park_isnt_lost() {
+        let signal = WorkerSignal::default();
+        signal.unpark();
+        let start = std::time::Instant::now();
+        signal.park(Duration::from_secs(5));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn shutdown_gives_up_on_busy_workers() {
+        let (pool, _, release) = pool(false);
+        assert!(!pool.shutdown(Duration::from_millis(10)));
+        release.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn workers_come_and_go() {
+        let (mut pool, runs, _) = pool(true);
+        assert!(pool.retire_worker());
+        assert!(pool.retire_worker());
+        assert_eq!(pool.len(), 1);
+
+        let spawned = Arc::new(AtomicUsize::new(usize::MAX));
+        pool.spawn_worker(|id, _| {
+            spawned.store(id, Ordering::Relaxed);
+            Counter {
+                runs: runs.clone(),
+                release: Arc::new(AtomicBool::new(true)),
+            }
+        })
+        .unwrap();
+        assert_eq!((pool.len(), spawned.load(Ordering::Relaxed)), (2, 1));
+        assert!(pool.shutdown(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn retired_workers_still_deliver_until_shutdown() {
+        let signals = std::sync::Mutex::new(vec![]);
+        let mut pool = Threadpool::new(2, |_, signal| {
+            signals.lock().unwrap().push(signal.clone());
+            Counter {
+                runs: Arc::new(AtomicUsize::new(0)),
+                release: Arc::new(AtomicBool::new(true)),
+            }
         })
+        .unwrap();
+        let signals = signals.into_inner().unwrap();
+
+        assert!(pool.retire_worker());
+        assert!(signals[1].is_retired() && !signals[1].is_stopped());
+        assert!(!signals[0].is_retired());
+
+        assert!(pool.shutdown(Duration::from_secs(5)));
+        assert!(signals.iter().all(|signal| signal.is_stopped()));
     }
 }
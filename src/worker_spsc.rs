@@ -1,66 +1,188 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use parking_lot::RwLock;
 use rtrb::PushError;
 
-use crate::{ChunkID, config::WorkerConfig, spsc, worker::Task, worker_pool::WorkerID};
+use crate::{
+    ChunkID,
+    config::WorkerConfig,
+    scheduler::{ChunkTask, SharedQueues, TaskQueue, View},
+    spsc,
+    worker::Task,
+    worker_pool::{WorkerID, WorkerSignal},
+};
 
 pub struct WorkerSPMC {
-    queues: Vec<Vec<spsc::Producer<Task>>>,
+    queues: SharedQueues<Task>,
+    /// Wakes the worker of the queue with the same index.
+    signals: Vec<Arc<WorkerSignal>>,
+    /// The worker woken next to steal from a backlog.
+    next_thief: AtomicUsize,
     config_queues: Vec<spsc::Producer<WorkerConfig>>,
+    /// The latest config each worker hasn't been able to take yet.
+    pending_configs: Vec<Option<WorkerConfig>>,
+    view: View,
 }
 
 impl WorkerSPMC {
-    pub fn new() -> Self {
+    pub fn new(view: View) -> Self {
         Self {
-            queues: vec![],
+            queues: Arc::new(RwLock::new(vec![])),
+            signals: vec![],
+            next_thief: AtomicUsize::new(0),
             config_queues: vec![],
+            pending_configs: vec![],
+            view,
         }
     }
 
-    pub fn add_task_queues(&mut self, cap: usize, max_lod: usize) -> Vec<spsc::Consumer<Task>> {
-        let (txs, rxs) = (0..max_lod).map(|_| rtrb::RingBuffer::new(cap)).unzip();
-        self.queues.push(txs);
-        rxs
+    /// Adds the queue of the worker `signal` wakes whenever it gets a task.
+    pub fn add_task_queue(
+        &mut self,
+        cap: usize,
+        signal: Arc<WorkerSignal>,
+    ) -> Arc<TaskQueue<Task>> {
+        let queue = Arc::new(TaskQueue::new(cap));
+        self.queues.write().push(queue.clone());
+        self.signals.push(signal);
+        queue
     }
 
     pub fn add_config_queue(&mut self, cap: usize) -> spsc::Consumer<WorkerConfig> {
         let (tx, rx) = rtrb::RingBuffer::new(cap);
         self.config_queues.push(tx);
+        self.pending_configs.push(None);
         rx
     }
 
-    pub fn submit_config_update(&mut self, mut config: WorkerConfig) {
-        for config_queue in &mut self.config_queues {
-            loop {
-                match config_queue.push(config) {
-                    Ok(()) => return,
-                    Err(PushError::Full(v)) => config = v,
-                }
-                std::hint::spin_loop();
+    /// Removes the queues of the worker with the highest id, which has to be stopped already, and
+    /// returns the tasks it didn't get to.
+    pub fn remove_worker(&mut self) -> Vec<Task> {
+        self.signals.pop();
+        self.config_queues.pop();
+        self.pending_configs.pop();
+        self.queues
+            .write()
+            .pop()
+            .map(|queue| queue.drain())
+            .unwrap_or_default()
+    }
+
+    /// Hands every pending task and `extra` to the worker its chunk belongs to now, e.g. after
+    /// workers were added or removed. Returns the tasks that didn't fit anymore.
+    pub fn redistribute(&mut self, extra: Vec<Task>) -> Vec<Task> {
+        let pending: Vec<Task> = self
+            .queues
+            .read()
+            .iter()
+            .flat_map(|queue| queue.drain())
+            .collect();
+        pending
+            .into_iter()
+            .chain(extra)
+            .filter_map(|task| self.try_submit_task(task.chunk(), task).err())
+            .collect()
+    }
+
+    /// Hands the config to every worker. Workers whose config queue is full get it with a later
+    /// [`WorkerSPMC::flush_config_updates`], as only the latest config matters. Returns whether
+    /// every worker got it right away.
+    pub fn submit_config_update(&mut self, config: WorkerConfig) -> bool {
+        self.pending_configs.fill(Some(config));
+        self.flush_config_updates()
+    }
+
+    /// Retries handing out the config updates workers weren't ready for. Returns whether none
+    /// are left.
+    pub fn flush_config_updates(&mut self) -> bool {
+        let mut flushed = true;
+        for (queue, pending) in self.config_queues.iter_mut().zip(&mut self.pending_configs) {
+            let Some(config) = pending.take() else {
+                continue;
+            };
+            if let Err(PushError::Full(config)) = queue.push(config) {
+                *pending = Some(config);
+                flushed = false;
             }
         }
+        flushed
     }
 
-    pub fn submit_task(&mut self, chunk: ChunkID, mut task: Task) {
-        let bucket = bucket(chunk, self.queues.len());
-        let queue = &mut self.queues[bucket][chunk.lod as usize];
+    /// The queues of all workers, for the workers to steal from each other.
+    pub fn queues(&self) -> SharedQueues<Task> {
+        self.queues.clone()
+    }
 
-        loop {
-            match queue.push(task) {
-                Ok(()) => return,
-                Err(PushError::Full(v)) => task = v,
-            }
-            std::hint::spin_loop();
+    /// Queues the task behind the more important ones of the worker its chunk belongs to. Gives
+    /// the task back if that worker's queue is full.
+    pub fn try_submit_task(&mut self, chunk: ChunkID, task: Task) -> Result<(), Task> {
+        let queues = self.queues.read();
+        let worker = bucket(chunk, queues.len());
+        let priority = task.priority(&self.view);
+        queues[worker].push(task, priority)?;
+        self.signals[worker].unpark();
+
+        // the worker is busy, wake another one in case it's idle and can steal
+        if queues[worker].len() > 1 {
+            let thief = self.next_thief.fetch_add(1, Ordering::Relaxed) % self.signals.len();
+            self.signals[thief].unpark();
         }
+        Ok(())
+    }
+
+    /// Changes the view the tasks get prioritized by and reorders the pending ones.
+    pub fn set_view(&mut self, view: View) {
+        for queue in self.queues.read().iter() {
+            queue.reprioritize(&view);
+        }
+        self.view = view;
+    }
+
+    pub fn view(&self) -> &View {
+        &self.view
     }
 
     pub fn len(&self) -> usize {
+        self.queues.read().iter().map(|queue| queue.len()).sum()
+    }
+
+    /// How many tasks are pending, indexed by the LOD of their chunk.
+    pub fn len_per_lod(&self) -> Vec<usize> {
+        let mut per_lod = vec![];
+        for queue in self.queues.read().iter() {
+            queue.for_each_chunk(|chunk| count_lod(&mut per_lod, chunk));
+        }
+        per_lod
+    }
+
+    /// Drops every pending task, e.g. when shutting down.
+    pub fn clear(&self) {
+        for queue in self.queues.read().iter() {
+            queue.clear();
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
         self.queues
+            .read()
             .iter()
-            .flat_map(|queue| queue.iter())
-            .map(|b| b.buffer().capacity() - b.slots())
+            .map(|queue| queue.capacity())
             .sum()
     }
 }
 
+/// Counts the chunk in the slot of its LOD.
+pub fn count_lod(per_lod: &mut Vec<usize>, chunk: ChunkID) {
+    let lod = chunk.lod as usize;
+    if per_lod.len() <= lod {
+        per_lod.resize(lod + 1, 0);
+    }
+    per_lod[lod] += 1;
+}
+
 /// Maps integer 3D coords to `0..values-1`.
 /// - Cheap integer math only
 /// - For any `values x values x values` region aligned to that grid,
@@ -95,3 +217,61 @@ pub fn bucket(chunk: ChunkID, values: usize) -> WorkerID {
     let shift = (h % values) as i32;
     ((base + shift).rem_euclid(n)) as usize
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, f32::consts::FRAC_PI_2, sync::Arc};
+
+    use glam::{IVec3, Vec3};
+
+    use crate::{
+        ChunkID, Frustum,
+        scheduler::{ChunkTask, View},
+        worker::Task,
+        worker_pool::WorkerSignal,
+    };
+
+    use super::WorkerSPMC;
+
+    #[test]
+    fn shrinking_keeps_every_task() {
+        let mut working_class = WorkerSPMC::new(View {
+            frustum: Frustum {
+                cam_pos: Vec3::ZERO,
+                direction: Vec3::X,
+                fov: FRAC_PI_2,
+                aspect_ratio: 1.,
+                max_chunks: 0,
+                max_distance: 64.,
+                full_detail_range: 0.,
+            },
+        });
+        for _ in 0..3 {
+            working_class.add_task_queue(4, Arc::new(WorkerSignal::default()));
+        }
+
+        let mut submitted = HashSet::new();
+        for x in 0..30 {
+            let chunk = ChunkID::new(0, IVec3::new(x, 0, 0));
+            let task = Task::GenerateChunkAndMesh {
+                chunk,
+                neighbors: Box::new([[0; 32]; 6]),
+            };
+            if working_class.try_submit_task(chunk, task).is_ok() {
+                submitted.insert(chunk);
+            }
+        }
+        assert!(submitted.len() > 4, "only {} tasks fit", submitted.len());
+
+        let mut orphans = working_class.remove_worker();
+        orphans.extend(working_class.remove_worker());
+        let returned = working_class.redistribute(orphans);
+        assert_eq!(working_class.queues().read().len(), 1);
+        assert_eq!(working_class.len(), 4);
+
+        let queued = working_class.queues().read()[0].drain();
+        let kept: Vec<ChunkID> = queued.iter().chain(&returned).map(Task::chunk).collect();
+        assert_eq!(kept.len(), submitted.len());
+        assert_eq!(kept.into_iter().collect::<HashSet<_>>(), submitted);
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::{HashMap, HashSet};
+
+use glam::{IVec3, UVec3};
+
+use crate::{
+    Chunk, ChunkID, VoxelType,
+    chunk::{coords_to_1d_index, idx_to_coord},
+    light::{Channel, Lighting, PaddedLight, split},
+};
+
+/// Chunks with more edits than this get rebuilt and relit as a whole.
+const BULK_EDIT_THRESHOLD: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoxelEdit {
+    pub pos: IVec3,
+    pub old: VoxelType,
+    pub new: VoxelType,
+}
+
+/// The full detail chunks the engine thread keeps to edit and light them.
+#[derive(Debug, Default)]
+pub struct World {
+    chunks: HashMap<ChunkID, Chunk>,
+    lighting: Lighting,
+
+    /// Chunks whose mesh is out of date.
+    dirty: HashSet<ChunkID>,
+    /// Voxels that changed since the simulations last looked at them.
+    edits: Vec<IVec3>,
+}
+
+impl World {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            chunks: HashMap::with_capacity(capacity),
+            ..Default::default()
+        }
+    }
+
+    pub fn insert_chunk(&mut self, id: ChunkID, chunk: Chunk) {
+        self.chunks.insert(id, chunk);
+        self.lighting.chunk_loaded(&self.chunks, id);
+    }
+
+    pub fn chunk(&self, id: ChunkID) -> Option<&Chunk> {
+        self.chunks.get(&id)
+    }
+
+    /// Bytes the voxels of all loaded chunks take.
+    pub fn memory_usage(&self) -> usize {
+        self.chunks
+            .values()
+            .map(Chunk::calculate_memory_usage)
+            .sum()
+    }
+
+    pub fn loaded_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn chunk_ids(&self) -> Vec<ChunkID> {
+        self.chunks.keys().copied().collect()
+    }
+
+    /// Returns `None` if the voxel isn't loaded at full detail.
+    pub fn get(&self, pos: IVec3) -> Option<VoxelType> {
+        let (chunk, local) = split(pos);
+        Some(self.chunks.get(&chunk)?.get(local))
+    }
+
+    /// Sets the voxel and updates the light around it. Returns the previous voxel or `None` if
+    /// the voxel isn't loaded at full detail, in which case nothing changes.
+    pub fn set(&mut self, pos: IVec3, voxel: VoxelType) -> Option<VoxelType> {
+        let (id, local) = split(pos);
+        let chunk = self.chunks.get_mut(&id)?;
+        let previous = chunk.get(local);
+        if previous == voxel {
+            return Some(previous);
+        }
+        chunk.set(local, voxel);
+
+        // the faces of the neighbors next to the voxel might be uncovered now
+        self.dirty.insert(id);
+        for (axis, dir) in [
+            (local.x, IVec3::X),
+            (local.y, IVec3::Y),
+            (local.z, IVec3::Z),
+        ] {
+            if axis == 0 {
+                self.dirty.insert(ChunkID::new(0, id.pos - dir));
+            } else if axis == 31 {
+                self.dirty.insert(ChunkID::new(0, id.pos + dir));
+            }
+        }
+
+        self.lighting.voxel_changed(&self.chunks, pos);
+        self.edits.push(pos);
+        Some(previous)
+    }
+
+    #[allow(unused)]
+    /// Sets many voxels, touching every chunk only once. Later voxels at the same position
+    /// overwrite earlier ones. Returns the voxels that changed.
+    pub fn set_many(
+        &mut self,
+        voxels: impl IntoIterator<Item = (IVec3, VoxelType)>,
+    ) -> Vec<VoxelEdit> {
+        let mut by_chunk: HashMap<ChunkID, Vec<(UVec3, VoxelType)>> = HashMap::new();
+        for (pos, voxel) in voxels {
+            let (chunk, local) = split(pos);
+            if self.chunks.contains_key(&chunk) {
+                by_chunk.entry(chunk).or_default().push((local, voxel));
+            }
+        }
+
+        let mut edits = Vec::new();
+        for (id, voxels) in by_chunk {
+            let origin = id.pos * 32;
+            if voxels.len() <= BULK_EDIT_THRESHOLD {
+                for (local, voxel) in voxels {
+                    let pos = origin + local.as_ivec3();
+                    if let Some(old) = self.set(pos, voxel)
+                        && old != voxel
+                    {
+                        edits.push(VoxelEdit {
+                            pos,
+                            old,
+                            new: voxel,
+                        });
+                    }
+                }
+                continue;
+            }
+
+            let old = self.chunks[&id].to_buffer();
+            let mut new = old;
+            for (local, voxel) in voxels {
+                new[coords_to_1d_index(local)] = voxel;
+            }
+            let first_edit = edits.len();
+            edits.extend(
+                old.iter()
+                    .zip(&new)
+                    .enumerate()
+                    .filter(|(_, (old, new))| old != new)
+                    .map(|(i, (old, new))| VoxelEdit {
+                        pos: origin + idx_to_coord(i).as_ivec3(),
+                        old: *old,
+                        new: *new,
+                    }),
+            );
+            if edits.len() > first_edit {
+                self.replace_chunk(id, Chunk::from_buffer(&new), &edits[first_edit..]);
+            }
+        }
+        edits
+    }
+
+    /// Fills a whole chunk with one voxel type. Returns the voxels that changed.
+    pub fn fill_chunk(&mut self, id: ChunkID, voxel: VoxelType) -> Vec<VoxelEdit> {
+        let Some(chunk) = self.chunks.get(&id) else {
+            return Vec::new();
+        };
+        if chunk.uniform_type() == Some(voxel) {
+            return Vec::new();
+        }
+
+        let origin = id.pos * 32;
+        let edits: Vec<VoxelEdit> = chunk
+            .to_buffer()
+            .iter()
+            .enumerate()
+            .filter(|(_, old)| **old != voxel)
+            .map(|(i, old)| VoxelEdit {
+                pos: origin + idx_to_coord(i).as_ivec3(),
+                old: *old,
+                new: voxel,
+            })
+            .collect();
+        self.replace_chunk(id, Chunk::uniform(voxel), &edits);
+        edits
+    }
+
+    fn replace_chunk(&mut self, id: ChunkID, chunk: Chunk, edits: &[VoxelEdit]) {
+        self.chunks.insert(id, chunk);
+        self.dirty.insert(id);
+        for dir in [
+            IVec3::NEG_X,
+            IVec3::X,
+            IVec3::NEG_Y,
+            IVec3::Y,
+            IVec3::NEG_Z,
+            IVec3::Z,
+        ] {
+            self.dirty.insert(ChunkID::new(0, id.pos + dir));
+        }
+        self.lighting.chunk_changed(&self.chunks, id);
+        self.edits.extend(edits.iter().map(|edit| edit.pos));
+    }
+
+    pub fn light(&self, pos: IVec3, channel: Channel) -> u8 {
+        self.lighting.get(pos, channel)
+    }
+
+    pub fn padded_light(&self, chunk: ChunkID) -> Box<PaddedLight> {
+        self.lighting.padded(chunk)
+    }
+
+    /// Returns the voxels that changed since the last call.
+    pub fn take_edits(&mut self) -> Vec<IVec3> {
+        std::mem::take(&mut self.edits)
+    }
+
+    /// Meshes the chunk again with the next [`World::take_dirty`], e.g. because its last mesh
+    /// task couldn't be submitted.
+    pub fn mark_dirty(&mut self, chunk: ChunkID) {
+        self.dirty.insert(chunk);
+    }
+
+    /// Returns the loaded chunks which need a new mesh since the last call.
+    pub fn take_dirty(&mut self) -> Vec<ChunkID> {
+        self.dirty.extend(self.lighting.take_changed());
+        self.dirty
+            .drain()
+            .filter(|chunk| self.chunks.contains_key(chunk))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::{Chunk, ChunkID, VoxelTypes, light::Channel, voxel::fill};
+
+    use super::World;
+
+    const AIR: u16 = VoxelTypes::Air as u16;
+    const STONE: u16 = VoxelTypes::Stone as u16;
+    const LAMP: u16 = VoxelTypes::Lamp as u16;
+
+    /// Two chunks of air on top of each other with stone below.
+    fn cave() -> World {
+        let mut world = World::default();
+        for y in [-1, 0] {
+            world.insert_chunk(
+                ChunkID::new(0, IVec3::new(0, y, 0)),
+                Chunk::from_buffer(&fill(if y < 0 { STONE } else { AIR })),
+            );
+        }
+        world
+    }
+
+    #[test]
+    fn open_sky_reaches_the_ground() {
+        let world = cave();
+        assert_eq!(world.light(IVec3::new(5, 0, 5), Channel::Sky), 15);
+        assert_eq!(world.light(IVec3::new(5, -1, 5), Channel::Sky), 0);
+    }
+
+    #[test]
+    fn roofs_cast_shadows_and_removing_them_restores_the_light() {
+        let mut world = cave();
+        for x in 0..32 {
+            for z in 0..32 {
+                world.set(IVec3::new(x, 20, z), STONE);
+            }
+        }
+        assert_eq!(world.light(IVec3::new(16, 10, 16), Channel::Sky), 0);
+
+        world.set(IVec3::new(16, 20, 16), AIR);
+        assert_eq!(world.light(IVec3::new(16, 10, 16), Channel::Sky), 15);
+        assert_eq!(world.light(IVec3::new(17, 10, 16), Channel::Sky), 14);
+        assert_eq!(world.light(IVec3::new(20, 10, 16), Channel::Sky), 11);
+
+        world.set(IVec3::new(16, 20, 16), STONE);
+        assert_eq!(world.light(IVec3::new(16, 10, 16), Channel::Sky), 0);
+    }
+
+    #[test]
+    fn block_light_spreads_and_gets_removed() {
+        let mut world = cave();
+        let lamp = IVec3::new(16, 10, 16);
+        world.set(lamp, LAMP);
+        assert_eq!(world.light(lamp, Channel::Block), 14);
+        assert_eq!(world.light(lamp + IVec3::X, Channel::Block), 13);
+        assert_eq!(world.light(lamp + IVec3::new(3, 2, -1), Channel::Block), 8);
+
+        world.set(lamp, AIR);
+        assert_eq!(world.light(lamp + IVec3::X, Channel::Block), 0);
+        assert_eq!(world.light(lamp + IVec3::new(3, 2, -1), Channel::Block), 0);
+    }
+
+    #[test]
+    fn light_crosses_chunk_borders() {
+        let mut world = cave();
+        let lamp = IVec3::new(31, 10, 16);
+        world.set(lamp, LAMP);
+        world.insert_chunk(
+            ChunkID::new(0, IVec3::new(1, 0, 0)),
+            Chunk::from_buffer(&fill(AIR)),
+        );
+        assert_eq!(world.light(lamp + IVec3::new(2, 0, 0), Channel::Block), 12);
+
+        world.set(lamp, AIR);
+        assert_eq!(world.light(lamp + IVec3::new(2, 0, 0), Channel::Block), 0);
+    }
+
+    #[test]
+    fn bulk_edits_relight_the_chunk() {
+        let mut world = cave();
+        let roof: Vec<_> = (0..32)
+            .flat_map(|x| (0..32).map(move |z| (IVec3::new(x, 20, z), STONE)))
+            .collect();
+        let edits = world.set_many(roof.iter().copied().chain([(IVec3::new(3, 3, 3), LAMP)]));
+        assert_eq!(edits.len(), 32 * 32 + 1);
+        assert_eq!(world.light(IVec3::new(16, 10, 16), Channel::Sky), 0);
+        assert_eq!(world.light(IVec3::new(4, 3, 3), Channel::Block), 13);
+
+        world.set_many(roof.iter().map(|(pos, _)| (*pos, AIR)));
+        assert_eq!(world.light(IVec3::new(16, 10, 16), Channel::Sky), 15);
+        assert_eq!(world.get(IVec3::new(3, 3, 3)), Some(LAMP));
+    }
+
+    #[test]
+    fn covering_a_chunk_darkens_it() {
+        let mut world = cave();
+        world.insert_chunk(
+            ChunkID::new(0, IVec3::new(0, 1, 0)),
+            Chunk::from_buffer(&fill(STONE)),
+        );
+        assert_eq!(world.light(IVec3::new(5, 0, 5), Channel::Sky), 0);
+        assert!(world.take_dirty().contains(&ChunkID::new(0, IVec3::ZERO)));
+    }
+}
@@ -4,7 +4,10 @@ use glam::IVec3;
 
 use super::{Layer, ShapeGenerator};
 use crate::{
-    ComposableGenerator, Gen2D, Gen3D, GenBox, random::Noise, voxel::VoxelTypes, world_gen::Seed,
+    ComposableGenerator, Gen2D, Gen3D, GenBox, GenHeightmap, GenWorms, HeightmapCache,
+    random::{Noise, derive_seed},
+    voxel::VoxelTypes,
+    world_gen::Seed,
 };
 
 impl Mul for ComposableGenerator {
@@ -35,6 +38,24 @@ impl ComposableGenerator {
         }
     }
 
+    pub fn gen_worms(worms: GenWorms, material: VoxelTypes) -> Self {
+        Self {
+            gen_stack: vec![Layer {
+                generator: ShapeGenerator::Worms(worms),
+                material,
+            }],
+        }
+    }
+
+    pub fn gen_heightmap(heightmap: GenHeightmap, material: VoxelTypes) -> Self {
+        Self {
+            gen_stack: vec![Layer {
+                generator: ShapeGenerator::Heightmap(heightmap),
+                material,
+            }],
+        }
+    }
+
     pub fn gen_box(min: IVec3, max: IVec3, material: VoxelTypes) -> Self {
         Self {
             gen_stack: vec![Layer {
@@ -74,13 +95,14 @@ impl ComposableGenerator {
         Self::full(VoxelTypes::Dirt0)
             * Self::gen_3d(
                 Gen3D {
-                    noise: Noise::new(seed as u32),
+                    noise: Noise::from_seed(derive_seed(seed, "dirt")),
                     octaves: 2,
                     x_scale: 30.,
                     y_scale: 30.,
                     z_scale: 30.,
                     exponent: 1.,
                     threshold: 0.5,
+                    sampling_step: 1,
                 },
                 VoxelTypes::Dirt1,
             )
@@ -92,12 +114,13 @@ impl ComposableGenerator {
                 Gen2D {
                     invert: true,
 
-                    noise: Noise::new(seed as u32),
+                    noise: Noise::from_seed(derive_seed(seed, "mountains_and_valleys")),
                     x_scale: 20.0,
                     z_scale: 20.0,
                     y_scale: 1200.0,
                     base_height: 0.,
                     octaves: 3,
+                    heightmap_cache: HeightmapCache::default(),
                 },
                 VoxelTypes::Air,
             )
@@ -107,13 +130,14 @@ impl ComposableGenerator {
         Self {
             gen_stack: vec![Layer {
                 generator: ShapeGenerator::Gen3D(Gen3D {
-                    noise: Noise::new(seed as u32),
+                    noise: Noise::from_seed(derive_seed(seed, "rain_drops")),
                     octaves: 1,
                     x_scale: 5.0,
                     y_scale: 5.0,
                     z_scale: 5.0,
                     exponent: 1.,
                     threshold: 0.8,
+                    sampling_step: 1,
                 }),
                 material: VoxelTypes::Stone,
             }],
@@ -124,16 +148,131 @@ impl ComposableGenerator {
         Self {
             gen_stack: vec![Layer {
                 generator: ShapeGenerator::Gen3D(Gen3D {
-                    noise: Noise::new(seed as u32),
+                    noise: Noise::from_seed(derive_seed(seed, "open_caves")),
                     x_scale: 32.0, // 8.0,
                     y_scale: 32.0,
                     z_scale: 32.0,
                     exponent: 1.,
                     threshold: 0.5,
                     octaves: 9,
+                    sampling_step: 1,
                 }),
                 material: VoxelTypes::Stone,
             }],
         }
     }
+
+    pub fn tunnels(seed: Seed) -> Self {
+        let seed = derive_seed(seed, "tunnels");
+        Self::full(VoxelTypes::Stone)
+            * Self::gen_worms(
+                GenWorms {
+                    seed,
+                    noise: Noise::from_seed(seed),
+                    region_size: 128,
+                    worms_per_region: 2,
+                    steps: 96,
+                    step_length: 2.,
+                    min_radius: 2.,
+                    max_radius: 5.,
+                    turn_scale: 24.,
+                    max_turn: 0.35,
+                    max_pitch: 0.6,
+                },
+                VoxelTypes::Air,
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::{ChunkID, ComposableGenerator, Generator};
+
+    const SEED: u64 = 0xC0FF_EE00_DEAD_BEEF;
+
+    const CHUNKS: [ChunkID; 3] = [
+        ChunkID {
+            lod: 0,
+            pos: IVec3::new(0, 0, 0),
+        },
+        ChunkID {
+            lod: 0,
+            pos: IVec3::new(-1, -1, 2),
+        },
+        ChunkID {
+            lod: 2,
+            pos: IVec3::new(3, -1, -5),
+        },
+    ];
+
+    /// Hashes the little endian bytes of the chunks, so the value doesn't depend on the platform.
+    fn hash_chunks(generator: &ComposableGenerator, chunks: &[ChunkID]) -> String {
+        let mut hasher = blake3::Hasher::new();
+        for &chunk in chunks {
+            for voxel in generator.generate(chunk) {
+                hasher.update(&voxel.to_le_bytes());
+            }
+        }
+        hasher.finalize().to_hex()[..16].to_owned()
+    }
+
+    /// Identical seeds have to produce byte identical chunks across runs and platforms. If one of
+    /// these changes on purpose, existing worlds change too.
+    #[test]
+    fn golden_chunks() {
+        // `2^(noise * 1200)` saturates for all but the lowest noise values, so the terrain only
+        // ends at the top of the world
+        let mut surface = CHUNKS.to_vec();
+        surface.push(ChunkID::new(0, IVec3::new(0, i32::MAX / 32, 0)));
+        assert_ne!(
+            hash_chunks(&ComposableGenerator::mountains_and_valleys(SEED), &surface),
+            hash_chunks(&ComposableGenerator::dirt(SEED), &surface),
+        );
+
+        for (name, generator, chunks, expected) in [
+            (
+                "dirt",
+                ComposableGenerator::dirt(SEED),
+                &CHUNKS[..],
+                "d4cf79f7fc6de67f",
+            ),
+            (
+                "mountains_and_valleys",
+                ComposableGenerator::mountains_and_valleys(SEED),
+                &surface,
+                "8eb61713bc208f6e",
+            ),
+            (
+                "rain_drops",
+                ComposableGenerator::rain_drops(SEED),
+                &CHUNKS,
+                "48349b0fe1d03564",
+            ),
+            (
+                "open_caves",
+                ComposableGenerator::open_caves(SEED),
+                &CHUNKS,
+                "5fa0836521d359d5",
+            ),
+            (
+                "tunnels",
+                ComposableGenerator::tunnels(SEED),
+                &CHUNKS,
+                "352988de34ca2ae8",
+            ),
+        ] {
+            assert_eq!(hash_chunks(&generator, chunks), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn sub_generators_use_different_noise() {
+        let chunk = ChunkID::new(0, IVec3::new(1, -3, 2));
+        assert_ne!(
+            ComposableGenerator::rain_drops(SEED).generate(chunk),
+            ComposableGenerator::open_caves(SEED).generate(chunk)
+        );
+    }
 }
@@ -0,0 +1,368 @@
+use std::{fs, path::Path, sync::Arc};
+
+use glam::IVec3;
+
+use crate::{
+    ChunkID, VoxelType,
+    chunk::DenseChunk,
+    error::{FormatError, FormatResult},
+    formats::png,
+    voxel::VoxelTypes,
+};
+
+/// Fills everything below an imported heightmap, for example a DEM or a painted map.
+///
+/// Pixel `(u, v)` lies at the world position `(offset.x + u * horizontal_scale, offset.z + v *
+/// horizontal_scale)`. Heights are interpolated bilinearly between pixels, so the terrain stays
+/// smooth when a pixel covers more than one voxel.
+#[derive(Debug, Clone)]
+pub struct GenHeightmap {
+    pub heightmap: Arc<Heightmap>,
+    /// Colors the top `surface_depth` voxels of the terrain.
+    pub materials: Option<Arc<MaterialMap>>,
+    pub surface_depth: i32,
+
+    /// World voxels per pixel along x and z.
+    pub horizontal_scale: f64,
+    /// World voxels between the lowest and the highest possible height.
+    pub vertical_scale: f64,
+    /// World position of the first pixel at height zero.
+    pub offset: IVec3,
+    pub tiling: Tiling,
+}
+
+/// What lies outside of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tiling {
+    /// Nothing, the layer doesn't touch voxels outside of the image.
+    None,
+    /// The edge pixels are stretched to infinity.
+    Clamp,
+    /// The image is repeated.
+    Repeat,
+    /// The image is repeated, every other copy mirrored so the seams line up.
+    Mirror,
+}
+
+/// 16-bit heights, row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heightmap {
+    pub width: usize,
+    pub depth: usize,
+    pub heights: Vec<u16>,
+}
+
+/// The voxel type for every pixel, `None` keeps the layers material.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialMap {
+    pub width: usize,
+    pub depth: usize,
+    pub materials: Vec<Option<VoxelType>>,
+}
+
+impl Heightmap {
+    /// Loads a grayscale PNG or a raw `.r16`/`.raw` file, which has to be square.
+    pub fn load(path: impl AsRef<Path>) -> FormatResult<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("png") => Self::from_png(&data),
+            Some("r16") | Some("raw") => {
+                let side = ((data.len() / 2) as f64).sqrt().round() as usize;
+                if side * side * 2 != data.len() {
+                    return Err(FormatError::corrupt(format!(
+                        "{} bytes aren't a square of 16-bit samples",
+                        data.len()
+                    )));
+                }
+                Self::from_raw(&data, side)
+            }
+            _ => Err(FormatError::unsupported(format!(
+                "heightmap extension of {}",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Uses the first channel of the image as height.
+    pub fn from_png(data: &[u8]) -> FormatResult<Self> {
+        let image = png::decode(data)?;
+        Ok(Self {
+            width: image.width,
+            depth: image.height,
+            heights: image
+                .samples
+                .iter()
+                .step_by(image.channels)
+                .copied()
+                .collect(),
+        })
+    }
+
+    /// Little endian 16-bit samples without a header, as exported by most terrain tools.
+    pub fn from_raw(data: &[u8], width: usize) -> FormatResult<Self> {
+        if width == 0 || !data.len().is_multiple_of(width * 2) {
+            return Err(FormatError::corrupt(format!(
+                "{} bytes aren't rows of {width} 16-bit samples",
+                data.len()
+            )));
+        }
+        Ok(Self {
+            width,
+            depth: data.len() / (width * 2),
+            heights: data
+                .chunks_exact(2)
+                .map(|sample| u16::from_le_bytes([sample[0], sample[1]]))
+                .collect(),
+        })
+    }
+
+    fn get(&self, u: usize, v: usize) -> f64 {
+        self.heights[v * self.width + u] as f64 / u16::MAX as f64
+    }
+}
+
+impl MaterialMap {
+    /// Maps every pixel of the PNG whose color is listed to its voxel type. Colors are compared
+    /// with 8 bits per channel.
+    pub fn from_png(data: &[u8], colors: &[([u8; 3], VoxelTypes)]) -> FormatResult<Self> {
+        let image = png::decode(data)?;
+        let mut materials = Vec::with_capacity(image.width * image.height);
+        for v in 0..image.height {
+            for u in 0..image.width {
+                let pixel = image.pixel(u, v);
+                let rgb = if image.channels >= 3 {
+                    [pixel[0], pixel[1], pixel[2]]
+                } else {
+                    [pixel[0]; 3]
+                }
+                .map(|channel| (channel >> 8) as u8);
+
+                materials.push(
+                    colors
+                        .iter()
+                        .find(|(color, _)| *color == rgb)
+                        .map(|(_, material)| *material as VoxelType),
+                );
+            }
+        }
+        Ok(Self {
+            width: image.width,
+            depth: image.height,
+            materials,
+        })
+    }
+}
+
+impl Tiling {
+    /// Maps a pixel coordinate into `0..len`.
+    fn apply(self, i: i64, len: usize) -> Option<usize> {
+        let len = len as i64;
+        match self {
+            Self::None => (0..len).contains(&i).then_some(i as usize),
+            Self::Clamp => Some(i.clamp(0, len - 1) as usize),
+            Self::Repeat => Some(i.rem_euclid(len) as usize),
+            Self::Mirror => {
+                let i = i.rem_euclid(2 * len);
+                Some(if i < len { i } else { 2 * len - 1 - i } as usize)
+            }
+        }
+    }
+}
+
+impl GenHeightmap {
+    pub(super) fn generate(&self, chunk: ChunkID, voxel: &mut DenseChunk, material: VoxelType) {
+        for (x, plane) in voxel.chunks_mut(32 * 32).enumerate() {
+            for z in 0..32 {
+                let pos_x = (x as i32 + chunk.pos.x * 32) << chunk.lod;
+                let pos_z = (z as i32 + chunk.pos.z * 32) << chunk.lod;
+
+                let u = (pos_x - self.offset.x) as f64 / self.horizontal_scale;
+                let v = (pos_z - self.offset.z) as f64 / self.horizontal_scale;
+                let Some(height) = self.height_at(u, v) else {
+                    continue;
+                };
+                let surface_material = self.material_at(u, v).unwrap_or(material);
+
+                for y in 0..32 {
+                    let pos_y = (y as i32 + chunk.pos.y * 32) << chunk.lod;
+                    if pos_y < height - self.surface_depth {
+                        plane[y * 32 + z] = material;
+                    } else if pos_y < height {
+                        plane[y * 32 + z] = surface_material;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The world height at the pixel coordinates, `None` outside of the image without tiling.
+    fn height_at(&self, u: f64, v: f64) -> Option<i32> {
+        let map = &self.heightmap;
+        let (u0, v0) = (u.floor() as i64, v.floor() as i64);
+        let (tu, tv) = (u - u0 as f64, v - v0 as f64);
+
+        // without tiling the edge pixels are held, so the terrain doesn't slope down to zero
+        let tiling = match self.tiling {
+            Tiling::None => {
+                self.tiling.apply(u0, map.width)?;
+                self.tiling.apply(v0, map.depth)?;
+                Tiling::Clamp
+            }
+            tiling => tiling,
+        };
+        let sample = |du: i64, dv: i64| {
+            map.get(
+                tiling.apply(u0 + du, map.width).unwrap(),
+                tiling.apply(v0 + dv, map.depth).unwrap(),
+            )
+        };
+
+        let top = sample(0, 0) * (1. - tu) + sample(1, 0) * tu;
+        let bottom = sample(0, 1) * (1. - tu) + sample(1, 1) * tu;
+        let height = top * (1. - tv) + bottom * tv;
+
+        Some(self.offset.y + (height * self.vertical_scale).round() as i32)
+    }
+
+    fn material_at(&self, u: f64, v: f64) -> Option<VoxelType> {
+        let materials = self.materials.as_ref()?;
+        let u = self.tiling.apply(u.round() as i64, materials.width)?;
+        let v = self.tiling.apply(v.round() as i64, materials.depth)?;
+        materials.materials[v * materials.width + u]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc};
+
+    use glam::IVec3;
+
+    use crate::{
+        ChunkID, ComposableGenerator, Generator,
+        chunk::{CHUNK_VOLUME, coords_to_1d_index},
+        formats::png::{Image, encode},
+        voxel::VoxelTypes,
+    };
+
+    use super::{GenHeightmap, Heightmap, MaterialMap, Tiling};
+
+    fn column_height(chunk: &[u16; CHUNK_VOLUME], x: u32, z: u32) -> u32 {
+        (0..32)
+            .take_while(|y| chunk[coords_to_1d_index(glam::UVec3::new(x, *y, z))] != 1)
+            .count() as u32
+    }
+
+    fn ramp() -> Heightmap {
+        // every pixel is one voxel higher than its left neighbor
+        Heightmap {
+            width: 16,
+            depth: 4,
+            heights: (0..64).map(|i| (i % 16) as u16 * 257).collect(),
+        }
+    }
+
+    fn generator(heightmap: Heightmap, tiling: Tiling) -> ComposableGenerator {
+        ComposableGenerator::gen_heightmap(
+            GenHeightmap {
+                heightmap: Arc::new(heightmap),
+                materials: None,
+                surface_depth: 0,
+                horizontal_scale: 1.,
+                vertical_scale: 255.,
+                offset: IVec3::new(0, 2, 0),
+                tiling,
+            },
+            VoxelTypes::Stone,
+        )
+    }
+
+    #[test]
+    fn png_and_raw_heightmaps_load_the_same() {
+        let heightmap = ramp();
+        let dir = std::env::temp_dir().join("voxine_heightmap_test");
+        fs::create_dir_all(&dir).unwrap();
+
+        let png = encode(&Image {
+            width: 16,
+            height: 4,
+            channels: 1,
+            samples: heightmap.heights.clone(),
+        });
+        fs::write(dir.join("ramp.png"), png).unwrap();
+        assert_eq!(Heightmap::load(dir.join("ramp.png")).unwrap(), heightmap);
+
+        let raw: Vec<u8> = heightmap
+            .heights
+            .iter()
+            .flat_map(|height| height.to_le_bytes())
+            .collect();
+        assert_eq!(Heightmap::from_raw(&raw, 16).unwrap(), heightmap);
+
+        // raw files don't store their width, so only square ones load
+        fs::write(dir.join("ramp.r16"), &raw[..96]).unwrap();
+        assert!(Heightmap::load(dir.join("ramp.r16")).is_err());
+        fs::write(dir.join("square.r16"), &raw).unwrap();
+        assert_eq!(Heightmap::load(dir.join("square.r16")).unwrap().width, 8);
+    }
+
+    #[test]
+    fn heights_follow_the_image() {
+        let chunk = generator(ramp(), Tiling::Repeat).generate(ChunkID::new(0, IVec3::ZERO));
+        for x in 0..32 {
+            assert_eq!(column_height(&chunk, x, 0), 2 + x % 16);
+        }
+    }
+
+    #[test]
+    fn without_tiling_the_outside_is_untouched() {
+        let chunk = generator(ramp(), Tiling::None).generate(ChunkID::new(0, IVec3::ZERO));
+        assert_eq!(column_height(&chunk, 3, 3), 5);
+        assert_eq!(column_height(&chunk, 3, 4), 0);
+        assert_eq!(column_height(&chunk, 20, 0), 0);
+    }
+
+    #[test]
+    fn material_map_colors_the_surface() {
+        let rgb = encode(&Image {
+            width: 1,
+            height: 1,
+            channels: 3,
+            samples: vec![0xFFFF, 0, 0],
+        });
+        let materials = MaterialMap::from_png(&rgb, &[([255, 0, 0], VoxelTypes::Dirt0)]).unwrap();
+
+        let generator = ComposableGenerator::gen_heightmap(
+            GenHeightmap {
+                heightmap: Arc::new(Heightmap {
+                    width: 1,
+                    depth: 1,
+                    heights: vec![10 * 257],
+                }),
+                materials: Some(Arc::new(materials)),
+                surface_depth: 3,
+                horizontal_scale: 1.,
+                vertical_scale: 255.,
+                offset: IVec3::ZERO,
+                tiling: Tiling::Clamp,
+            },
+            VoxelTypes::Stone,
+        );
+        let chunk = generator.generate(ChunkID::new(0, IVec3::ZERO));
+        let column: Vec<u16> = (0..11)
+            .map(|y| chunk[coords_to_1d_index(glam::UVec3::new(5, y, 5))])
+            .collect();
+        let (stone, dirt, air) = (
+            VoxelTypes::Stone as u16,
+            VoxelTypes::Dirt0 as u16,
+            VoxelTypes::Air as u16,
+        );
+        assert_eq!(
+            column,
+            [
+                stone, stone, stone, stone, stone, stone, stone, dirt, dirt, dirt, air
+            ]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+use crate::{ChunkID, Lod};
+
+/// Height thresholds of every voxel column in a chunk, indexed by `x * 32 + z`.
+pub type ColumnHeights = [i32; 32 * 32];
+
+const SHARDS: usize = 16;
+
+/// Identifies a vertical stack of chunks.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct ColumnID {
+    x: i32,
+    z: i32,
+    lod: Lod,
+}
+
+impl From<ChunkID> for ColumnID {
+    fn from(chunk: ChunkID) -> Self {
+        Self {
+            x: chunk.pos.x,
+            z: chunk.pos.z,
+            lod: chunk.lod,
+        }
+    }
+}
+
+/// A bounded cache of column heights, shared by every clone and therefore by every worker.
+///
+/// The cache is split into shards to keep lock contention low. Once a shard is full the oldest
+/// column gets evicted. A capacity of `0` disables caching.
+#[derive(Debug, Clone)]
+pub struct HeightmapCache {
+    shards: Arc<[Mutex<Shard>; SHARDS]>,
+    capacity_per_shard: usize,
+}
+
+#[derive(Debug, Default)]
+struct Shard {
+    columns: HashMap<ColumnID, Arc<ColumnHeights>>,
+    insertion_order: VecDeque<ColumnID>,
+}
+
+impl Default for HeightmapCache {
+    /// Caches up to 1024 columns, which are 4MB.
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl HeightmapCache {
+    /// `capacity` is the number of columns the cache holds at most.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            shards: Arc::new(std::array::from_fn(|_| Mutex::new(Shard::default()))),
+            capacity_per_shard: capacity.div_ceil(SHARDS),
+        }
+    }
+
+    /// Returns the cached heights of the column the chunk is in or computes and caches them.
+    /// The heights are computed without holding a lock, so two workers might compute the same
+    /// column at once, in which case the first result wins.
+    pub fn get_or_insert_with(
+        &self,
+        chunk: ChunkID,
+        compute: impl FnOnce() -> ColumnHeights,
+    ) -> Arc<ColumnHeights> {
+        if self.capacity_per_shard == 0 {
+            return Arc::new(compute());
+        }
+
+        let column = ColumnID::from(chunk);
+        let shard = &self.shards[shard_of(column)];
+
+        if let Some(heights) = shard.lock().columns.get(&column) {
+            return heights.clone();
+        }
+
+        let heights = Arc::new(compute());
+
+        let mut shard = shard.lock();
+        if let Some(heights) = shard.columns.get(&column) {
+            return heights.clone();
+        }
+        if shard.insertion_order.len() >= self.capacity_per_shard
+            && let Some(oldest) = shard.insertion_order.pop_front()
+        {
+            shard.columns.remove(&oldest);
+        }
+        shard.insertion_order.push_back(column);
+        shard.columns.insert(column, heights.clone());
+
+        heights
+    }
+
+    /// The number of columns currently cached.
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().columns.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn shard_of(column: ColumnID) -> usize {
+    let mut hasher = std::hash::DefaultHasher::new();
+    column.hash(&mut hasher);
+    hasher.finish() as usize % SHARDS
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::ChunkID;
+
+    use super::HeightmapCache;
+
+    #[test]
+    fn columns_are_computed_once_per_stack() {
+        let cache = HeightmapCache::new(64);
+        let mut computed = 0;
+        for y in -4..4 {
+            cache.get_or_insert_with(ChunkID::new(0, IVec3::new(3, y, -7)), || {
+                computed += 1;
+                [y; 32 * 32]
+            });
+        }
+        assert_eq!(computed, 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn capacity_is_bounded() {
+        let cache = HeightmapCache::new(32);
+        for x in 0..1000 {
+            cache.get_or_insert_with(ChunkID::new(0, IVec3::new(x, 0, 0)), || [x; 32 * 32]);
+        }
+        assert!(cache.len() <= 32);
+    }
+
+    #[test]
+    fn zero_capacity_disables_the_cache() {
+        let cache = HeightmapCache::new(0);
+        cache.get_or_insert_with(ChunkID::new(0, IVec3::ZERO), || [0; 32 * 32]);
+        assert!(cache.is_empty());
+    }
+}
@@ -8,6 +8,13 @@ use crate::{
 };
 
 pub mod generators;
+mod heightmap;
+mod heightmap_cache;
+mod worms;
+
+pub use heightmap::{GenHeightmap, Heightmap, MaterialMap, Tiling};
+pub use heightmap_cache::{ColumnHeights, HeightmapCache};
+pub use worms::GenWorms;
 
 pub type Seed = u64;
 pub trait Generator: Clone + Send + Sync + 'static {
@@ -19,6 +26,8 @@ pub enum ShapeGenerator {
     Gen2D(Gen2D),
     Gen3D(Gen3D),
     Box(GenBox),
+    Worms(GenWorms),
+    Heightmap(GenHeightmap),
     Full,
 }
 
@@ -32,6 +41,9 @@ pub struct Gen2D {
     pub x_scale: f64,
     pub y_scale: f64,
     pub z_scale: f64,
+
+    /// Shared between every clone, so chunks stacked on top of each other reuse the heights.
+    pub heightmap_cache: HeightmapCache,
 }
 
 #[derive(Debug, Clone)]
@@ -43,6 +55,11 @@ pub struct Gen3D {
     pub z_scale: f64,
     pub exponent: f64,
     pub threshold: f64,
+
+    /// Distance in voxels between two noise samples along every axis. `1` evaluates the noise for
+    /// every voxel, anything above trilinearly interpolates a coarser grid. Steps that don't divide
+    /// 32 work, but put the last grid points past the chunk's edge.
+    pub sampling_step: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -72,6 +89,10 @@ impl Generator for ComposableGenerator {
                 ShapeGenerator::Gen2D(generator) => generator.generate(chunk, &mut voxel, material),
                 ShapeGenerator::Gen3D(generator) => generator.generate(chunk, &mut voxel, material),
                 ShapeGenerator::Box(generator) => generator.generate(chunk, &mut voxel, material),
+                ShapeGenerator::Worms(generator) => generator.generate(chunk, &mut voxel, material),
+                ShapeGenerator::Heightmap(generator) => {
+                    generator.generate(chunk, &mut voxel, material)
+                }
                 ShapeGenerator::Full => (0..CHUNK_VOLUME).for_each(|i| voxel[i] = material as u16),
             }
         }
@@ -82,24 +103,17 @@ impl Generator for ComposableGenerator {
 
 impl Gen2D {
     fn generate(&self, chunk: ChunkID, voxel: &mut DenseChunk, material: VoxelType) {
+        let heights = self
+            .heightmap_cache
+            .get_or_insert_with(chunk, || self.column_heights(chunk));
+
         for (x, plane) in voxel.chunks_mut(32 * 32).enumerate() {
             for z in 0..32 {
-                let pos_x = (x as i32 + chunk.pos.x * 32) << chunk.lod;
-                let pos_z = (z as i32 + chunk.pos.z * 32) << chunk.lod;
-
-                let height = self.noise.get_octaves(
-                    pos_x as f64 / self.x_scale,
-                    0.0,
-                    pos_z as f64 / self.z_scale,
-                    1.,
-                    self.octaves,
-                );
+                let height = heights[x * 32 + z];
                 for y in 0..32 {
                     let pos_y = (y as i32 + chunk.pos.y * 32) << chunk.lod;
 
-                    plane[y * 32 + z] = if pos_y
-                        < ((2.0_f64.powf(height as f64 * self.y_scale)) - self.base_height) as i32
-                    {
+                    plane[y * 32 + z] = if pos_y < height {
                         if self.invert {
                             continue;
                         }
@@ -113,34 +127,117 @@ impl Gen2D {
             }
         }
     }
+
+    /// The height below which the layer is solid for every column of the chunk.
+    fn column_heights(&self, chunk: ChunkID) -> ColumnHeights {
+        let mut heights = [0; 32 * 32];
+        for x in 0..32 {
+            for z in 0..32 {
+                let pos_x = (x as i32 + chunk.pos.x * 32) << chunk.lod;
+                let pos_z = (z as i32 + chunk.pos.z * 32) << chunk.lod;
+
+                let height = self.noise.get_octaves(
+                    pos_x as f64 / self.x_scale,
+                    0.0,
+                    pos_z as f64 / self.z_scale,
+                    1.,
+                    self.octaves,
+                );
+                heights[x * 32 + z] =
+                    ((2.0_f64.powf(height * self.y_scale)) - self.base_height) as i32;
+            }
+        }
+        heights
+    }
 }
 
 impl Gen3D {
     fn generate(&self, chunk: ChunkID, voxel: &mut DenseChunk, material: VoxelType) {
-        for (i, voxel) in voxel.iter_mut().enumerate() {
-            let coord = idx_to_coord(i);
+        if self.sampling_step > 1 {
+            let density = self.sample_interpolated(chunk);
+            for (voxel, density) in voxel.iter_mut().zip(density) {
+                self.fill(voxel, density, material);
+            }
+        } else {
+            self.sample_every_voxel(chunk, voxel, material);
+        }
+    }
 
-            let pos = IVec3::new(
-                (coord.x as i32 + chunk.pos.x * 32) << chunk.lod,
-                (coord.y as i32 + chunk.pos.y * 32) << chunk.lod,
-                (coord.z as i32 + chunk.pos.z * 32) << chunk.lod,
-            );
+    #[inline]
+    fn fill(&self, voxel: &mut VoxelType, density: f64, material: VoxelType) {
+        if density.powf(self.exponent) >= self.threshold {
+            *voxel = material;
+        }
+    }
 
-            let val = self.noise.get_octaves(
-                pos.x as f64 / self.x_scale,
-                pos.y as f64 / self.y_scale,
-                pos.z as f64 / self.z_scale,
-                1.,
-                self.octaves,
-            );
+    /// The scaled noise coordinates of every `step`-th voxel along each axis, plus one past the
+    /// last voxel, so the grid reaches the chunks far edge.
+    fn sample_coords(&self, chunk: ChunkID, step: usize) -> [Vec<f64>; 3] {
+        let samples = 32_usize.div_ceil(step) + 1;
+        let axis = |chunk_pos: i32, scale: f64| {
+            (0..samples)
+                .map(|i| (((i * step) as i32 + chunk_pos * 32) << chunk.lod) as f64 / scale)
+                .collect()
+        };
+        [
+            axis(chunk.pos.x, self.x_scale),
+            axis(chunk.pos.y, self.y_scale),
+            axis(chunk.pos.z, self.z_scale),
+        ]
+    }
 
-            *voxel = if val.powf(self.exponent as f64) < self.threshold {
-                continue;
-            } else {
-                material
+    /// Evaluates the noise for every voxel and writes the result straight into the chunk.
+    fn sample_every_voxel(&self, chunk: ChunkID, voxel: &mut DenseChunk, material: VoxelType) {
+        let axis = |chunk_pos: i32, scale: f64| -> [f64; 32] {
+            std::array::from_fn(|i| ((i as i32 + chunk_pos * 32) << chunk.lod) as f64 / scale)
+        };
+        let xs = axis(chunk.pos.x, self.x_scale);
+        let ys = axis(chunk.pos.y, self.y_scale);
+        let zs = axis(chunk.pos.z, self.z_scale);
+
+        let mut voxels = voxel.iter_mut();
+        for x in xs {
+            for y in ys {
+                for z in zs {
+                    let density = self.noise.get_octaves(x, y, z, 1., self.octaves);
+                    self.fill(voxels.next().unwrap(), density, material);
+                }
             }
         }
     }
+
+    fn sample_interpolated(&self, chunk: ChunkID) -> Vec<f64> {
+        let step = self.sampling_step;
+        let [xs, ys, zs] = self.sample_coords(chunk, step);
+        let n = xs.len();
+
+        let mut grid = Vec::with_capacity(n * n * n);
+        for x in &xs {
+            for y in &ys {
+                for z in &zs {
+                    grid.push(self.noise.get_octaves(*x, *y, *z, 1., self.octaves));
+                }
+            }
+        }
+        let grid_at = |x: usize, y: usize, z: usize| grid[(x * n + y) * n + z];
+
+        let mut density = Vec::with_capacity(CHUNK_VOLUME);
+        for x in 0..32 {
+            let (gx, tx) = (x / step, (x % step) as f64 / step as f64);
+            for y in 0..32 {
+                let (gy, ty) = (y / step, (y % step) as f64 / step as f64);
+                for z in 0..32 {
+                    let (gz, tz) = (z / step, (z % step) as f64 / step as f64);
+
+                    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+                    let along_z = |x, y| lerp(grid_at(x, y, gz), grid_at(x, y, gz + 1), tz);
+                    let along_y = |x| lerp(along_z(x, gy), along_z(x, gy + 1), ty);
+                    density.push(lerp(along_y(gx), along_y(gx + 1), tx));
+                }
+            }
+        }
+        density
+    }
 }
 
 impl GenBox {
@@ -172,10 +269,157 @@ impl GenBox {
 mod tests {
     use glam::IVec3;
 
-    use crate::{ChunkID, voxel::VoxelTypes};
+    use crate::{
+        ChunkID,
+        chunk::{CHUNK_VOLUME, DenseChunk, idx_to_coord},
+        voxel::VoxelTypes,
+    };
 
     use super::Gen3D;
 
+    fn caves(sampling_step: usize) -> Gen3D {
+        Gen3D {
+            noise: crate::Noise::new(42),
+            octaves: 3,
+            x_scale: 32.0,
+            y_scale: 32.0,
+            z_scale: 32.0,
+            exponent: 1.0,
+            threshold: 0.5,
+            sampling_step,
+        }
+    }
+
+    /// Evaluates the noise for one voxel after the other.
+    fn exact_density(gen3d: &Gen3D, chunk: ChunkID) -> Vec<f64> {
+        (0..CHUNK_VOLUME)
+            .map(|i| {
+                let coord = idx_to_coord(i);
+                let pos = IVec3::new(
+                    (coord.x as i32 + chunk.pos.x * 32) << chunk.lod,
+                    (coord.y as i32 + chunk.pos.y * 32) << chunk.lod,
+                    (coord.z as i32 + chunk.pos.z * 32) << chunk.lod,
+                );
+                gen3d.noise.get_octaves(
+                    pos.x as f64 / gen3d.x_scale,
+                    pos.y as f64 / gen3d.y_scale,
+                    pos.z as f64 / gen3d.z_scale,
+                    1.,
+                    gen3d.octaves,
+                )
+            })
+            .collect()
+    }
+
+    /// Stone wherever the density reaches the threshold.
+    fn thresholded(gen3d: &Gen3D, density: &[f64]) -> DenseChunk {
+        let mut out = [VoxelTypes::Air as u16; CHUNK_VOLUME];
+        for (voxel, density) in out.iter_mut().zip(density) {
+            if density.powf(gen3d.exponent) >= gen3d.threshold {
+                *voxel = VoxelTypes::Stone as u16;
+            }
+        }
+        out
+    }
+
+    fn exact_chunk(gen3d: &Gen3D, chunk: ChunkID) -> DenseChunk {
+        thresholded(gen3d, &exact_density(gen3d, chunk))
+    }
+
+    const CHUNKS: [ChunkID; 3] = [
+        ChunkID {
+            lod: 0,
+            pos: IVec3::new(0, 0, 0),
+        },
+        ChunkID {
+            lod: 0,
+            pos: IVec3::new(-3, 1, 7),
+        },
+        ChunkID {
+            lod: 2,
+            pos: IVec3::new(5, -2, -1),
+        },
+    ];
+
+    #[test]
+    fn batched_sampling_matches_per_voxel_sampling() {
+        let gen3d = caves(1);
+        for chunk in CHUNKS {
+            let mut out = [VoxelTypes::Air as u16; CHUNK_VOLUME];
+            gen3d.generate(chunk, &mut out, VoxelTypes::Stone as u16);
+            assert_eq!(out, exact_chunk(&gen3d, chunk));
+        }
+    }
+
+    #[test]
+    fn interpolated_grid_points_are_exact() {
+        for step in [2, 4, 8] {
+            let gen3d = caves(step);
+            for chunk in CHUNKS {
+                let exact = exact_density(&gen3d, chunk);
+                let interpolated = gen3d.sample_interpolated(chunk);
+                for i in 0..CHUNK_VOLUME {
+                    let coord = idx_to_coord(i);
+                    if (coord % step as u32).cmpeq(glam::UVec3::ZERO).all() {
+                        assert_eq!(interpolated[i], exact[i]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn steps_that_dont_divide_32_still_cover_the_chunk() {
+        for step in [3, 5, 31, 33, 100] {
+            let gen3d = caves(step);
+            let chunk = CHUNKS[1];
+            let exact = exact_density(&gen3d, chunk);
+            let interpolated = gen3d.sample_interpolated(chunk);
+            assert_eq!(interpolated.len(), CHUNK_VOLUME);
+            assert_eq!(interpolated[0], exact[0]);
+
+            let mut out = [VoxelTypes::Air as u16; CHUNK_VOLUME];
+            gen3d.generate(chunk, &mut out, VoxelTypes::Stone as u16);
+            // every voxel up to the last one follows the interpolation, which is exact at the grid
+            assert_eq!(out, thresholded(&gen3d, &interpolated), "step {step}");
+            let exact = thresholded(&gen3d, &exact);
+            for i in 0..CHUNK_VOLUME {
+                if (idx_to_coord(i) % step as u32)
+                    .cmpeq(glam::UVec3::ZERO)
+                    .all()
+                {
+                    assert_eq!(out[i], exact[i], "step {step}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn interpolated_sampling_approximates_per_voxel_sampling() {
+        let gen3d = caves(4);
+        // at higher `LOD`s the grid spans more world space, so only full detail is held to a bound
+        for chunk in CHUNKS.into_iter().filter(|chunk| chunk.lod == 0) {
+            let exact = exact_density(&gen3d, chunk);
+            let interpolated = gen3d.sample_interpolated(chunk);
+            let mean_error = exact
+                .iter()
+                .zip(interpolated.iter())
+                .map(|(a, b)| (a - b).abs())
+                .sum::<f64>()
+                / CHUNK_VOLUME as f64;
+            assert!(mean_error < 0.01, "mean error {mean_error}");
+
+            let mut out = [VoxelTypes::Air as u16; CHUNK_VOLUME];
+            gen3d.generate(chunk, &mut out, VoxelTypes::Stone as u16);
+            let mismatches = out
+                .iter()
+                .zip(exact_chunk(&gen3d, chunk).iter())
+                .filter(|(a, b)| a != b)
+                .count();
+            assert!(mismatches < CHUNK_VOLUME / 20, "{mismatches} voxels differ");
+        }
+    }
+
     #[test]
     fn gen3d_uses_z_coordinate_for_world_z() {
         let chunk = ChunkID::new(0, IVec3::new(0, 0, 0));
@@ -188,6 +432,7 @@ mod tests {
             z_scale: 1.0,
             exponent: 1.0,
             threshold: -1.0,
+            sampling_step: 1,
         };
 
         gen3d.generate(chunk, &mut out, VoxelTypes::Air as u16);
@@ -0,0 +1,228 @@
+use std::f64::consts::{FRAC_PI_2, TAU};
+
+use glam::{DVec3, IVec3};
+use rand::Rng;
+
+use crate::{
+    ChunkID, VoxelType,
+    chunk::DenseChunk,
+    random::{Noise, cell_rng},
+    world_gen::Seed,
+};
+
+/// Carves connected tunnel systems out of the chunk.
+///
+/// The world is split into cubic regions of `region_size` voxels. Every region deterministically
+/// seeds `worms_per_region` worms which wander along noise-driven paths and carve spheres of a
+/// varying radius. Because every worm is always simulated from its start, a chunk can be generated
+/// independently, in any order and at any `LOD` and still agrees with its neighbors.
+#[derive(Debug, Clone)]
+pub struct GenWorms {
+    pub seed: Seed,
+    pub noise: Noise,
+
+    /// Edge length of the regions worms are seeded in, in voxels.
+    pub region_size: i32,
+    pub worms_per_region: u32,
+
+    pub steps: u32,
+    pub step_length: f64,
+
+    pub min_radius: f64,
+    pub max_radius: f64,
+
+    /// How many voxels a worm travels before the noise steering it changes noticeably.
+    pub turn_scale: f64,
+    /// Maximal change of direction per step in radians.
+    pub max_turn: f64,
+    /// Maximal pitch in radians, keeps tunnels from becoming vertical shafts.
+    pub max_pitch: f64,
+}
+
+impl GenWorms {
+    pub(super) fn generate(&self, chunk: ChunkID, voxel: &mut DenseChunk, material: VoxelType) {
+        let chunk_min = (chunk.pos * 32) << chunk.lod;
+        let chunk_max = ((chunk.pos + 1) * 32) << chunk.lod;
+
+        let reach = self.reach();
+        let region_min = region_of(chunk_min - reach, self.region_size);
+        let region_max = region_of(chunk_max + reach, self.region_size);
+
+        for x in region_min.x..=region_max.x {
+            for y in region_min.y..=region_max.y {
+                for z in region_min.z..=region_max.z {
+                    let region = IVec3::new(x, y, z);
+                    for worm in 0..self.worms_per_region {
+                        self.carve_worm(region, worm, |center, radius| {
+                            carve_sphere(chunk, voxel, material, center, radius)
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// The furthest distance from its start a worm can carve.
+    fn reach(&self) -> i32 {
+        (self.steps as f64 * self.step_length + self.max_radius).ceil() as i32
+    }
+
+    fn carve_worm(&self, region: IVec3, worm: u32, mut carve: impl FnMut(DVec3, f64)) {
+        let mut rng = cell_rng(self.seed, region, worm);
+
+        let mut pos = (region * self.region_size).as_dvec3()
+            + DVec3::new(rng.r#gen::<f64>(), rng.r#gen::<f64>(), rng.r#gen::<f64>())
+                * self.region_size as f64;
+        let mut yaw = rng.r#gen::<f64>() * TAU;
+        let mut pitch = (rng.r#gen::<f64>() - 0.5) * self.max_pitch;
+
+        // keeps worms starting close to each other from following the same path
+        let offset = rng.r#gen::<f64>() * 1024.;
+
+        for step in 0..self.steps {
+            let sample = pos / self.turn_scale;
+            let turn = self.noise.get(sample.x + offset, sample.y, sample.z, 1.) - 0.5;
+            let climb = self.noise.get(sample.x, sample.y + offset, sample.z, 1.) - 0.5;
+            let width = self
+                .noise
+                .get(step as f64 / self.turn_scale, offset, 0.5, 1.);
+
+            yaw += turn * 2. * self.max_turn;
+            pitch = (pitch + climb * 2. * self.max_turn).clamp(-self.max_pitch, self.max_pitch);
+
+            let radius = self.min_radius + (self.max_radius - self.min_radius) * width;
+            carve(pos, radius);
+
+            let (sin_pitch, cos_pitch) = sin_cos(pitch);
+            let (sin_yaw, cos_yaw) = sin_cos(yaw);
+            pos +=
+                DVec3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw) * self.step_length;
+        }
+    }
+}
+
+/// `f64::sin_cos` calls the platform's libm, whose last bits differ between platforms and would move
+/// tunnels. This only adds, multiplies and divides, which IEEE 754 rounds the same everywhere.
+fn sin_cos(angle: f64) -> (f64, f64) {
+    // the Taylor series are accurate to about 1e-12 within an eighth turn of the nearest quarter
+    let quarter = (angle / FRAC_PI_2).round();
+    let x = angle - quarter * FRAC_PI_2;
+    let (mut sin, mut cos) = (x, 1.);
+    let (mut sin_term, mut cos_term) = (x, 1.);
+    for n in 1..=6 {
+        let n = n as f64 * 2.;
+        sin_term *= -x * x / (n * (n + 1.));
+        cos_term *= -x * x / ((n - 1.) * n);
+        sin += sin_term;
+        cos += cos_term;
+    }
+    match (quarter as i64).rem_euclid(4) {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    }
+}
+
+fn region_of(pos: IVec3, region_size: i32) -> IVec3 {
+    IVec3::new(
+        pos.x.div_euclid(region_size),
+        pos.y.div_euclid(region_size),
+        pos.z.div_euclid(region_size),
+    )
+}
+
+/// Sets every voxel of the chunk whose sample position lies inside the sphere.
+fn carve_sphere(
+    chunk: ChunkID,
+    voxel: &mut DenseChunk,
+    material: VoxelType,
+    center: DVec3,
+    radius: f64,
+) {
+    let scale = (1 << chunk.lod) as f64;
+    let chunk_origin = (chunk.pos * 32).as_dvec3();
+
+    let min = ((center - radius) / scale - chunk_origin)
+        .ceil()
+        .max(DVec3::ZERO);
+    let max = ((center + radius) / scale - chunk_origin)
+        .floor()
+        .min(DVec3::splat(31.));
+    if min.cmpgt(max).any() {
+        return;
+    }
+
+    let radius_squared = radius * radius;
+    for x in min.x as usize..=max.x as usize {
+        for y in min.y as usize..=max.y as usize {
+            for z in min.z as usize..=max.z as usize {
+                let pos = (chunk_origin + DVec3::new(x as f64, y as f64, z as f64)) * scale;
+                if pos.distance_squared(center) <= radius_squared {
+                    voxel[x * 32 * 32 + y * 32 + z] = material;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::{ChunkID, Generator, chunk::coords_to_1d_index, voxel::VoxelTypes};
+
+    use super::super::ComposableGenerator;
+
+    fn tunnels() -> ComposableGenerator {
+        ComposableGenerator::tunnels(0x5EED)
+    }
+
+    #[test]
+    fn tunnels_carve_something() {
+        let generator = tunnels();
+        let air = (-2..2)
+            .flat_map(|x| (-2..2).flat_map(move |y| (-2..2).map(move |z| IVec3::new(x, y, z))))
+            .map(|pos| generator.generate(ChunkID::new(0, pos)))
+            .flat_map(|chunk| chunk.into_iter())
+            .filter(|voxel| *voxel == VoxelTypes::Air as u16)
+            .count();
+        assert!(air > 0);
+    }
+
+    #[test]
+    fn sin_cos_matches_std() {
+        for i in -1000..1000 {
+            let angle = i as f64 * 0.0123;
+            let (sin, cos) = super::sin_cos(angle);
+            assert!((sin - angle.sin()).abs() < 1e-10, "{angle}");
+            assert!((cos - angle.cos()).abs() < 1e-10, "{angle}");
+        }
+    }
+
+    #[test]
+    fn higher_lod_samples_match_full_detail() {
+        let generator = tunnels();
+        let parent = ChunkID::new(1, IVec3::new(0, -1, 0));
+        let coarse = generator.generate(parent);
+
+        for child in 0..8 {
+            let offset = IVec3::new(child & 1, (child >> 1) & 1, child >> 2);
+            let child_id = ChunkID::new(0, (parent.pos << 1) + offset);
+            let fine = generator.generate(child_id);
+
+            for x in 0..16 {
+                for y in 0..16 {
+                    for z in 0..16 {
+                        let coarse_pos = (offset * 16 + IVec3::new(x, y, z)).as_uvec3();
+                        let fine_pos = (IVec3::new(x, y, z) * 2).as_uvec3();
+                        assert_eq!(
+                            coarse[coords_to_1d_index(coarse_pos)],
+                            fine[coords_to_1d_index(fine_pos)]
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
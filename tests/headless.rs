@@ -0,0 +1,148 @@
+//! Runs the engine the way a dedicated server would. Passes with `--no-default-features`, which
+//! leaves out wgpu.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use glam::{IVec3, UVec3, Vec3};
+use voxine::{
+    ChunkID, ComposableGenerator, DeltaTimeMeter, Update, VoxelTypes,
+    cam_controller::{CamController, CameraConfig},
+    config::EngineConfig,
+    engine_thread, mpsc,
+};
+
+const CONFIG: &str = r#"
+full_detail_distance = 1.0
+task_cancelation_lod_threshold = 2
+total_generation_distance = 3.0
+max_chunks = 1000
+target_tps = 0.01
+worker_count = 2
+headless = true
+
+engine_worker_config_queue_cap = 4
+task_queue_cap = 256
+discarded_tasks_queue_cap = 256
+mesh_queue_cap = 256
+chunk_queue_cap = 256
+collider_queue_cap = 256
+solid_map_queue_cap = 256
+"#;
+
+const CAMERA: &str = r#"
+friction = 0.9
+standart-speed = 1.0
+max-speed = 10.0
+acc-change-sensitivity = 0.1
+sensitivity = 0.01
+"#;
+
+#[test]
+fn generates_and_simulates_without_meshing() {
+    let config: EngineConfig = toml::from_str(CONFIG).unwrap();
+    let camera: CameraConfig = toml::from_str(CAMERA).unwrap();
+    let player = CamController::new(
+        Vec3::ZERO,
+        0.,
+        0.,
+        true,
+        DeltaTimeMeter::new().reader(),
+        camera,
+    );
+    // a stone floor below y = 0
+    let ground = ComposableGenerator::gen_cube(
+        IVec3::splat(-1 << 20),
+        IVec3::new(1 << 20, 0, 1 << 20),
+        VoxelTypes::Stone,
+    );
+    let mut channels = engine_thread(config, player, ground).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let origin = ChunkID::new(0, IVec3::ZERO);
+    while !channels.voxel_collider.read().contains_key(&origin) {
+        assert!(
+            Instant::now() < deadline,
+            "the chunk at the origin didn't get generated"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    // water poured onto the floor has to flow sideways
+    let (reply, copies) = mpsc::new(1);
+    let source = IVec3::new(4, 0, 4);
+    loop {
+        assert!(Instant::now() < deadline, "the water didn't spread");
+        // the chunk might not be in the world yet when its collider shows up, so keep pouring
+        let pour = Update::SetVoxel {
+            pos: source,
+            voxel: VoxelTypes::Water as u16,
+        };
+        assert!(channels.updates.push(pour).is_ok());
+        thread::sleep(Duration::from_millis(100));
+
+        let copy = Update::CopySchematic {
+            min: source - IVec3::new(1, 0, 1),
+            max: source + IVec3::new(1, 0, 1),
+            reply: reply.clone(),
+        };
+        assert!(channels.updates.push(copy).is_ok());
+        let copy = loop {
+            if let Ok(copy) = copies.pop() {
+                break copy.unwrap();
+            }
+            assert!(Instant::now() < deadline, "no copy came back");
+            thread::sleep(Duration::from_millis(10));
+        };
+        let neighbors = [
+            UVec3::new(0, 0, 1),
+            UVec3::new(2, 0, 1),
+            UVec3::new(1, 0, 0),
+            UVec3::new(1, 0, 2),
+        ];
+        if neighbors
+            .into_iter()
+            .any(|pos| copy.get(pos) != VoxelTypes::Air as u16)
+        {
+            break;
+        }
+    }
+    // edits have to reach the collider without a mesh
+    let (x, y, z): (usize, usize, usize) = (20, 10, 20);
+    let is_solid = |collider: &[[u32; 32]; 32]| collider[x][y] & (1 << 31 >> z) != 0;
+    assert!(!is_solid(&channels.voxel_collider.read()[&origin]));
+    let place = Update::SetVoxel {
+        pos: IVec3::new(x as i32, y as i32, z as i32),
+        voxel: VoxelTypes::Stone as u16,
+    };
+    assert!(channels.updates.push(place).is_ok());
+    while !is_solid(&channels.voxel_collider.read()[&origin]) {
+        assert!(
+            Instant::now() < deadline,
+            "the collider didn't pick up the edit"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    // the metrics refresh every half second
+    thread::sleep(Duration::from_millis(600));
+
+    assert!(
+        channels.mesh_updates.pop().is_err(),
+        "headless engine meshed"
+    );
+    let metrics = channels.metrics.read().clone();
+    assert!(metrics.tps > 0., "the engine didn't tick");
+    let full_detail = metrics.chunks_per_lod.first().copied().unwrap_or(0);
+    assert!(full_detail > 0, "no full detail chunk got loaded");
+    assert!(
+        metrics.chunks_per_lod.iter().skip(1).all(|n| *n == 0),
+        "headless engine generated lower detail chunks: {:?}",
+        metrics.chunks_per_lod
+    );
+
+    assert!(channels.updates.push(Update::ShutDown).is_ok());
+    channels.engine.join().unwrap().unwrap();
+}